@@ -132,7 +132,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "The contract is not initialized")]
+    #[should_panic(expected = "NOT_INITIALIZED")]
     fn test_default() {
         let context = get_context(accounts(1));
         testing_env!(context.build());
@@ -1,5 +1,6 @@
 use syn::export::TokenStream2;
 
+use super::sdk_error;
 use crate::info_extractor::{
     ArgInfo, AttrSigInfo, BindgenArgType, InputStructType, SerializerType,
 };
@@ -160,33 +161,38 @@ impl AttrSigInfo {
         result
     }
 
-    /// Create code that deserializes arguments that were decorated with `#[callback]`
+    /// Create code that deserializes arguments that were decorated with `#[callback]` or
+    /// `#[callback_result]`.
     pub fn callback_deserialization(&self) -> TokenStream2 {
         self
             .args
             .iter()
             .filter(|arg| match arg.bindgen_ty {
-                BindgenArgType::CallbackArg => true,
+                BindgenArgType::CallbackArg | BindgenArgType::CallbackResultArg => true,
                 _ => false,
             })
             .enumerate()
             .fold(TokenStream2::new(), |acc, (idx, arg)| {
                 let idx = idx as u64;
                 let ArgInfo { mutability, ident, ty, .. } = arg;
+                let invocation = callback_invocation(arg, quote! { #idx });
+                if let BindgenArgType::CallbackResultArg = arg.bindgen_ty {
+                    return quote! {
+                    #acc
+                    let #mutability #ident: #ty = match near_sdk::env::promise_result(#idx) {
+                        near_sdk::PromiseResult::Successful(data) => Ok(#invocation),
+                        near_sdk::PromiseResult::NotReady => Err(near_sdk::PromiseError::NotReady),
+                        near_sdk::PromiseResult::Failed => Err(near_sdk::PromiseError::Failed),
+                    };
+                };
+                }
+                let error = sdk_error(quote! { CallbackFailed { index: #idx } });
                 let read_data = quote! {
                 let data: Vec<u8> = match near_sdk::env::promise_result(#idx) {
                     near_sdk::PromiseResult::Successful(x) => x,
-                    _ => panic!("Callback computation {} was not successful", #idx)
+                    _ => #error
                 };
             };
-                let invocation = match arg.serializer_ty {
-                    SerializerType::JSON => quote! {
-                        near_sdk::serde_json::from_slice(&data).expect("Failed to deserialize callback using JSON")
-                    },
-                    SerializerType::Borsh => quote! {
-                        near_sdk::borsh::BorshDeserialize::try_from_slice(&data).expect("Failed to deserialize callback using Borsh")
-                    },
-                };
                 quote! {
                 #acc
                 #read_data
@@ -201,26 +207,31 @@ impl AttrSigInfo {
             .args
             .iter()
             .filter(|arg| match arg.bindgen_ty {
-                BindgenArgType::CallbackArgVec => true,
+                BindgenArgType::CallbackArgVec | BindgenArgType::CallbackResultArgVec => true,
                 _ => false,
             })
             .fold(TokenStream2::new(), |acc, arg| {
                 let ArgInfo { mutability, ident, ty, .. } = arg;
-                let invocation = match arg.serializer_ty {
-                    SerializerType::JSON => quote! {
-                        near_sdk::serde_json::from_slice(&data).expect("Failed to deserialize callback using JSON")
-                    },
-                    SerializerType::Borsh => quote! {
-                        near_sdk::borsh::BorshDeserialize::try_from_slice(&data).expect("Failed to deserialize callback using Borsh")
-                    },
+                let invocation = callback_invocation(arg, quote! { i });
+                if let BindgenArgType::CallbackResultArgVec = arg.bindgen_ty {
+                    return quote! {
+                    #acc
+                    let #mutability #ident: #ty = (0..near_sdk::env::promise_results_count())
+                    .map(|i| match near_sdk::env::promise_result(i) {
+                        near_sdk::PromiseResult::Successful(data) => Ok(#invocation),
+                        near_sdk::PromiseResult::NotReady => Err(near_sdk::PromiseError::NotReady),
+                        near_sdk::PromiseResult::Failed => Err(near_sdk::PromiseError::Failed),
+                    }).collect();
                 };
+                }
+                let error = sdk_error(quote! { CallbackFailed { index: i } });
                 quote! {
                 #acc
                 let #mutability #ident: #ty = (0..near_sdk::env::promise_results_count())
                 .map(|i| {
                     let data: Vec<u8> = match near_sdk::env::promise_result(i) {
                         near_sdk::PromiseResult::Successful(x) => x,
-                        _ => panic!("Callback computation {} was not successful", i)
+                        _ => #error
                     };
                     #invocation
                 }).collect();
@@ -228,3 +239,26 @@ impl AttrSigInfo {
             })
     }
 }
+
+/// Create code that deserializes the `data` of the promise result `index` for the callback
+/// argument.
+fn callback_invocation(arg: &ArgInfo, index: TokenStream2) -> TokenStream2 {
+    match arg.serializer_ty {
+        SerializerType::JSON => {
+            let error = sdk_error(quote! {
+                InvalidCallbackResult { index: #index, serializer: "JSON".to_string() }
+            });
+            quote! {
+                near_sdk::serde_json::from_slice(&data).unwrap_or_else(|_| #error)
+            }
+        }
+        SerializerType::Borsh => {
+            let error = sdk_error(quote! {
+                InvalidCallbackResult { index: #index, serializer: "Borsh".to_string() }
+            });
+            quote! {
+                near_sdk::borsh::BorshDeserialize::try_from_slice(&data).unwrap_or_else(|_| #error)
+            }
+        }
+    }
+}
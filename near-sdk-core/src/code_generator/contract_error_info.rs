@@ -0,0 +1,86 @@
+use crate::info_extractor::ContractErrorInfo;
+use quote::quote;
+use syn::export::TokenStream2;
+
+impl ContractErrorInfo {
+    /// Generate the implementations of `ContractError` and of `FunctionError` with the error message.
+    pub fn contract_error_impl(&self) -> TokenStream2 {
+        let ContractErrorInfo { ident, generics, codes } = self;
+        let mut generics = generics.clone();
+        generics.make_where_clause().predicates.push(syn::parse_quote! {
+            Self: near_sdk::serde::Serialize
+        });
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        let arms = codes.iter().map(|(variant, code)| match variant {
+            Some(variant) => quote! { #ident::#variant { .. } => #code, },
+            None => quote! { #ident { .. } => #code, },
+        });
+        quote! {
+            impl #impl_generics near_sdk::ContractError for #ident #ty_generics #where_clause {
+                fn error_code(&self) -> &'static str {
+                    match self {
+                        #(#arms)*
+                    }
+                }
+            }
+
+            impl #impl_generics near_sdk::FunctionError for #ident #ty_generics #where_clause {
+                fn panic(&self) -> ! {
+                    near_sdk::env::panic(near_sdk::ContractError::error_message(self).as_bytes())
+                }
+            }
+        }
+    }
+}
+
+// Rustfmt removes comas.
+#[rustfmt::skip]
+#[cfg(test)]
+mod tests {
+    use syn::{DeriveInput, parse_quote};
+    use quote::quote;
+    use crate::info_extractor::ContractErrorInfo;
+
+    #[test]
+    fn enum_codes() {
+        let input: DeriveInput = parse_quote! {
+            enum Error {
+                NotEnoughBalance { needed: u64 },
+                #[error_code = "E_NOT_FOUND"]
+                NotFound(String),
+                Paused,
+            }
+        };
+        let actual = ContractErrorInfo::new(&input).unwrap().contract_error_impl();
+        let expected = quote!(
+            impl near_sdk::ContractError for Error where Self: near_sdk::serde::Serialize {
+                fn error_code(&self) -> &'static str {
+                    match self {
+                        Error::NotEnoughBalance { .. } => "NOT_ENOUGH_BALANCE",
+                        Error::NotFound { .. } => "E_NOT_FOUND",
+                        Error::Paused { .. } => "PAUSED",
+                    }
+                }
+            }
+
+            impl near_sdk::FunctionError for Error where Self: near_sdk::serde::Serialize {
+                fn panic(&self) -> ! {
+                    near_sdk::env::panic(near_sdk::ContractError::error_message(self).as_bytes())
+                }
+            }
+        );
+        assert_eq!(expected.to_string(), actual.to_string());
+    }
+
+    #[test]
+    fn duplicate_codes() {
+        let input: DeriveInput = parse_quote! {
+            enum Error {
+                #[error_code = "PAUSED"]
+                Stopped,
+                Paused,
+            }
+        };
+        assert!(ContractErrorInfo::new(&input).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use super::sdk_error;
 use crate::info_extractor::{
     AttrSigInfo, ImplItemMethodInfo, InputStructType, MethodType, SerializerType,
 };
@@ -23,17 +24,26 @@ impl ImplItemMethodInfo {
         if has_input_args {
             arg_struct = attr_signature_info.input_struct(InputStructType::Deserialization);
             let decomposition = attr_signature_info.decomposition_pattern();
+            let missing_input = sdk_error(quote! { MissingInput });
             let serializer_invocation = match attr_signature_info.input_serializer {
-                SerializerType::JSON => quote! {
-                    near_sdk::serde_json::from_slice(
-                        &near_sdk::env::input().expect("Expected input since method has arguments.")
-                    ).expect("Failed to deserialize input from JSON.")
-                },
-                SerializerType::Borsh => quote! {
-                    near_sdk::borsh::BorshDeserialize::try_from_slice(
-                        &near_sdk::env::input().expect("Expected input since method has arguments.")
-                    ).expect("Failed to deserialize input from Borsh.")
-                },
+                SerializerType::JSON => {
+                    let invalid_input =
+                        sdk_error(quote! { InvalidInput { serializer: "JSON".to_string() } });
+                    quote! {
+                        near_sdk::serde_json::from_slice(
+                            &near_sdk::env::input().unwrap_or_else(|| #missing_input)
+                        ).unwrap_or_else(|_| #invalid_input)
+                    }
+                }
+                SerializerType::Borsh => {
+                    let invalid_input =
+                        sdk_error(quote! { InvalidInput { serializer: "Borsh".to_string() } });
+                    quote! {
+                        near_sdk::borsh::BorshDeserialize::try_from_slice(
+                            &near_sdk::env::input().unwrap_or_else(|| #missing_input)
+                        ).unwrap_or_else(|_| #invalid_input)
+                    }
+                }
             };
             arg_parsing = quote! {
                 let #decomposition : Input = #serializer_invocation ;
@@ -56,6 +66,7 @@ impl ImplItemMethodInfo {
             method_type,
             is_payable,
             is_private,
+            is_handles_result,
             ..
         } = attr_signature_info;
         let deposit_check = if *is_payable || matches!(method_type, &MethodType::View) {
@@ -63,34 +74,49 @@ impl ImplItemMethodInfo {
             quote! {}
         } else {
             // If method is not payable, do a check to make sure that it doesn't consume deposit
-            let error = format!("Method {} doesn't accept deposit", ident.to_string());
+            let method = ident.to_string();
+            let error = sdk_error(quote! { DepositNotAccepted { method: #method.to_string() } });
             quote! {
                 if near_sdk::env::attached_deposit() != 0 {
-                    near_sdk::env::panic(#error.as_bytes());
+                    #error;
                 }
             }
         };
         let is_private_check = if *is_private {
-            let error = format!("Method {} is private", ident.to_string());
+            let method = ident.to_string();
+            let error = sdk_error(quote! { PrivateMethod { method: #method.to_string() } });
             quote! {
                 if env::current_account_id() != env::predecessor_account_id() {
-                    near_sdk::env::panic(#error.as_bytes());
+                    #error;
                 }
             }
         } else {
             quote! {}
         };
+        let init_invocation = if *is_handles_result {
+            quote! {
+                let contract = match #struct_type::#ident(#arg_list) {
+                    Ok(contract) => contract,
+                    Err(err) => near_sdk::FunctionError::panic(&err),
+                };
+            }
+        } else {
+            quote! {
+                let contract = #struct_type::#ident(#arg_list);
+            }
+        };
         let body = if matches!(method_type, &MethodType::Init) {
+            let error = sdk_error(quote! { AlreadyInitialized });
             quote! {
                 if near_sdk::env::state_exists() {
-                    near_sdk::env::panic(b"The contract has already been initialized");
+                    #error;
                 }
-                let contract = #struct_type::#ident(#arg_list);
+                #init_invocation
                 near_sdk::env::state_write(&contract);
             }
         } else if matches!(method_type, &MethodType::InitIgnoreState) {
             quote! {
-                let contract = #struct_type::#ident(#arg_list);
+                #init_invocation
                 near_sdk::env::state_write(&contract);
             }
         } else {
@@ -127,19 +153,45 @@ impl ImplItemMethodInfo {
                 },
                 ReturnType::Type(_, _) => {
                     let value_ser = match result_serializer {
-                        SerializerType::JSON => quote! {
-                            let result = near_sdk::serde_json::to_vec(&result).expect("Failed to serialize the return value using JSON.");
-                        },
-                        SerializerType::Borsh => quote! {
-                            let result = near_sdk::borsh::BorshSerialize::try_to_vec(&result).expect("Failed to serialize the return value using Borsh.");
-                        },
+                        SerializerType::JSON => {
+                            let error = sdk_error(
+                                quote! { ResultSerialization { serializer: "JSON".to_string() } },
+                            );
+                            quote! {
+                                let result = near_sdk::serde_json::to_vec(&result).unwrap_or_else(|_| #error);
+                            }
+                        }
+                        SerializerType::Borsh => {
+                            let error = sdk_error(
+                                quote! { ResultSerialization { serializer: "Borsh".to_string() } },
+                            );
+                            quote! {
+                                let result = near_sdk::borsh::BorshSerialize::try_to_vec(&result).unwrap_or_else(|_| #error);
+                            }
+                        }
                     };
-                    quote! {
-                    #contract_deser
-                    let result = #method_invocation;
-                    #value_ser
-                    near_sdk::env::value_return(&result);
-                    #contract_ser
+                    if *is_handles_result {
+                        // The state is only written on `Ok`, `Err` fails the call.
+                        quote! {
+                        #contract_deser
+                        let result = #method_invocation;
+                        match result {
+                            Ok(result) => {
+                                #value_ser
+                                near_sdk::env::value_return(&result);
+                                #contract_ser
+                            }
+                            Err(err) => near_sdk::FunctionError::panic(&err),
+                        }
+                        }
+                    } else {
+                        quote! {
+                        #contract_deser
+                        let result = #method_invocation;
+                        #value_ser
+                        near_sdk::env::value_return(&result);
+                        #contract_ser
+                        }
                     }
                 }
             }
@@ -101,7 +101,7 @@ mod tests {
                 near_sdk::env::setup_panic_hook();
                 near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
                 if near_sdk::env::attached_deposit() != 0 {
-                    near_sdk::env::panic("Method method doesn't accept deposit".as_bytes());
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::DepositNotAccepted { method: "method".to_string() });
                 }
                 let mut contract: Hello = near_sdk::env::state_read().unwrap_or_default();
                 contract.method();
@@ -129,9 +129,9 @@ mod tests {
                     k: u64,
                 }
                 let Input { k, }: Input = near_sdk::serde_json::from_slice(
-                    &near_sdk::env::input().expect("Expected input since method has arguments.")
+                    &near_sdk::env::input().unwrap_or_else(|| near_sdk::FunctionError::panic(&near_sdk::SdkError::MissingInput))
                 )
-                .expect("Failed to deserialize input from JSON.");
+                .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidInput { serializer: "JSON".to_string() }));
                 let contract: Hello = near_sdk::env::state_read().unwrap_or_default();
                 contract.method(k, );
             }
@@ -153,7 +153,7 @@ mod tests {
                     near_sdk::env::setup_panic_hook();
                     near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
                     if near_sdk::env::attached_deposit() != 0 {
-                        near_sdk::env::panic("Method method doesn't accept deposit".as_bytes());
+                        near_sdk::FunctionError::panic(&near_sdk::SdkError::DepositNotAccepted { method: "method".to_string() });
                     }
                     #[derive(near_sdk :: serde :: Deserialize)]
                     #[serde(crate = "near_sdk::serde")]
@@ -162,9 +162,9 @@ mod tests {
                         m: Bar,
                     }
                     let Input { k, m, }: Input = near_sdk::serde_json::from_slice(
-                        &near_sdk::env::input().expect("Expected input since method has arguments.")
+                        &near_sdk::env::input().unwrap_or_else(|| near_sdk::FunctionError::panic(&near_sdk::SdkError::MissingInput))
                     )
-                    .expect("Failed to deserialize input from JSON.");
+                    .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidInput { serializer: "JSON".to_string() }));
                     let mut contract: Hello = near_sdk::env::state_read().unwrap_or_default();
                     contract.method(k, m, );
                     near_sdk::env::state_write(&contract);
@@ -187,7 +187,7 @@ mod tests {
                     near_sdk::env::setup_panic_hook();
                     near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
                     if near_sdk::env::attached_deposit() != 0 {
-                        near_sdk::env::panic("Method method doesn't accept deposit".as_bytes());
+                        near_sdk::FunctionError::panic(&near_sdk::SdkError::DepositNotAccepted { method: "method".to_string() });
                     }
                     #[derive(near_sdk :: serde :: Deserialize)]
                     #[serde(crate = "near_sdk::serde")]
@@ -196,13 +196,13 @@ mod tests {
                         m: Bar,
                     }
                     let Input { k, m, }: Input = near_sdk::serde_json::from_slice(
-                        &near_sdk::env::input().expect("Expected input since method has arguments.")
+                        &near_sdk::env::input().unwrap_or_else(|| near_sdk::FunctionError::panic(&near_sdk::SdkError::MissingInput))
                     )
-                    .expect("Failed to deserialize input from JSON.");
+                    .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidInput { serializer: "JSON".to_string() }));
                     let mut contract: Hello = near_sdk::env::state_read().unwrap_or_default();
                     let result = contract.method(k, m, );
                     let result =
-                        near_sdk::serde_json::to_vec(&result).expect("Failed to serialize the return value using JSON.");
+                        near_sdk::serde_json::to_vec(&result).unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::ResultSerialization { serializer: "JSON".to_string() }));
                     near_sdk::env::value_return(&result);
                     near_sdk::env::state_write(&contract);
                 }
@@ -226,7 +226,7 @@ mod tests {
                 let contract: Hello = near_sdk::env::state_read().unwrap_or_default();
                 let result = contract.method();
                 let result =
-                    near_sdk::serde_json::to_vec(&result).expect("Failed to serialize the return value using JSON.");
+                    near_sdk::serde_json::to_vec(&result).unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::ResultSerialization { serializer: "JSON".to_string() }));
                 near_sdk::env::value_return(&result);
             }
         );
@@ -251,9 +251,9 @@ mod tests {
                         k: u64,
                     }
                     let Input { k, }: Input = near_sdk::serde_json::from_slice(
-                        &near_sdk::env::input().expect("Expected input since method has arguments.")
+                        &near_sdk::env::input().unwrap_or_else(|| near_sdk::FunctionError::panic(&near_sdk::SdkError::MissingInput))
                     )
-                    .expect("Failed to deserialize input from JSON.");
+                    .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidInput { serializer: "JSON".to_string() }));
                     let contract: Hello = near_sdk::env::state_read().unwrap_or_default();
                     contract.method(&k, );
                 }
@@ -280,9 +280,9 @@ mod tests {
                     k: u64,
                 }
                 let Input { mut k, }: Input = near_sdk::serde_json::from_slice(
-                    &near_sdk::env::input().expect("Expected input since method has arguments.")
+                    &near_sdk::env::input().unwrap_or_else(|| near_sdk::FunctionError::panic(&near_sdk::SdkError::MissingInput))
                 )
-                .expect("Failed to deserialize input from JSON.");
+                .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidInput { serializer: "JSON".to_string() }));
                 let contract: Hello = near_sdk::env::state_read().unwrap_or_default();
                 contract.method(&mut k, );
             }
@@ -305,7 +305,7 @@ mod tests {
                 near_sdk::env::setup_panic_hook();
                 near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
                 if env::current_account_id() != env::predecessor_account_id() {
-                    near_sdk::env::panic("Method method is private".as_bytes());
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::PrivateMethod { method: "method".to_string() });
                 }
                 #[derive(near_sdk :: serde :: Deserialize)]
                 #[serde(crate = "near_sdk::serde")]
@@ -313,21 +313,21 @@ mod tests {
                     y: String,
                 }
                 let Input { y, }: Input = near_sdk::serde_json::from_slice(
-                    &near_sdk::env::input().expect("Expected input since method has arguments.")
+                    &near_sdk::env::input().unwrap_or_else(|| near_sdk::FunctionError::panic(&near_sdk::SdkError::MissingInput))
                 )
-                .expect("Failed to deserialize input from JSON.");
+                .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidInput { serializer: "JSON".to_string() }));
                 let data: Vec<u8> = match near_sdk::env::promise_result(0u64) {
                     near_sdk::PromiseResult::Successful(x) => x,
-                    _ => panic!("Callback computation {} was not successful", 0u64)
+                    _ => near_sdk::FunctionError::panic(&near_sdk::SdkError::CallbackFailed { index: 0u64 })
                 };
                 let mut x: u64 =
-                    near_sdk::serde_json::from_slice(&data).expect("Failed to deserialize callback using JSON");
+                    near_sdk::serde_json::from_slice(&data).unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidCallbackResult { index: 0u64, serializer: "JSON".to_string() }));
                 let data: Vec<u8> = match near_sdk::env::promise_result(1u64) {
                     near_sdk::PromiseResult::Successful(x) => x,
-                    _ => panic!("Callback computation {} was not successful", 1u64)
+                    _ => near_sdk::FunctionError::panic(&near_sdk::SdkError::CallbackFailed { index: 1u64 })
                 };
                 let z: Vec<u8> =
-                    near_sdk::serde_json::from_slice(&data).expect("Failed to deserialize callback using JSON");
+                    near_sdk::serde_json::from_slice(&data).unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidCallbackResult { index: 1u64, serializer: "JSON".to_string() }));
                 let contract: Hello = near_sdk::env::state_read().unwrap_or_default();
                 contract.method(&mut x, y, z, );
             }
@@ -350,20 +350,20 @@ mod tests {
                 near_sdk::env::setup_panic_hook();
                 near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
                 if env::current_account_id() != env::predecessor_account_id() {
-                    near_sdk::env::panic("Method method is private".as_bytes());
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::PrivateMethod { method: "method".to_string() });
                 }
                 let data: Vec<u8> = match near_sdk::env::promise_result(0u64) {
                     near_sdk::PromiseResult::Successful(x) => x,
-                    _ => panic!("Callback computation {} was not successful", 0u64)
+                    _ => near_sdk::FunctionError::panic(&near_sdk::SdkError::CallbackFailed { index: 0u64 })
                 };
                 let mut x: u64 =
-                    near_sdk::serde_json::from_slice(&data).expect("Failed to deserialize callback using JSON");
+                    near_sdk::serde_json::from_slice(&data).unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidCallbackResult { index: 0u64, serializer: "JSON".to_string() }));
                 let data: Vec<u8> = match near_sdk::env::promise_result(1u64) {
                     near_sdk::PromiseResult::Successful(x) => x,
-                    _ => panic!("Callback computation {} was not successful", 1u64)
+                    _ => near_sdk::FunctionError::panic(&near_sdk::SdkError::CallbackFailed { index: 1u64 })
                 };
                 let y: String =
-                    near_sdk::serde_json::from_slice(&data).expect("Failed to deserialize callback using JSON");
+                    near_sdk::serde_json::from_slice(&data).unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidCallbackResult { index: 1u64, serializer: "JSON".to_string() }));
                 let contract: Hello = near_sdk::env::state_read().unwrap_or_default();
                 contract.method(&mut x, y, );
             }
@@ -387,7 +387,7 @@ mod tests {
                 near_sdk::env::setup_panic_hook();
                 near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
                 if env::current_account_id() != env::predecessor_account_id() {
-                    near_sdk::env::panic("Method method is private".as_bytes());
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::PrivateMethod { method: "method".to_string() });
                 }
                 #[derive(near_sdk :: serde :: Deserialize)]
                 #[serde(crate = "near_sdk::serde")]
@@ -395,16 +395,16 @@ mod tests {
                     y: String,
                 }
                 let Input { y, }: Input = near_sdk::serde_json::from_slice(
-                    &near_sdk::env::input().expect("Expected input since method has arguments.")
+                    &near_sdk::env::input().unwrap_or_else(|| near_sdk::FunctionError::panic(&near_sdk::SdkError::MissingInput))
                 )
-                .expect("Failed to deserialize input from JSON.");
+                .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidInput { serializer: "JSON".to_string() }));
                 let x: Vec<String> = (0..near_sdk::env::promise_results_count())
                     .map(|i| {
                         let data: Vec<u8> = match near_sdk::env::promise_result(i) {
                             near_sdk::PromiseResult::Successful(x) => x,
-                            _ => panic!("Callback computation {} was not successful", i)
+                            _ => near_sdk::FunctionError::panic(&near_sdk::SdkError::CallbackFailed { index: i })
                         };
-                        near_sdk::serde_json::from_slice(&data).expect("Failed to deserialize callback using JSON")
+                        near_sdk::serde_json::from_slice(&data).unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidCallbackResult { index: i, serializer: "JSON".to_string() }))
                     })
                     .collect();
                 let contract: Hello = near_sdk::env::state_read().unwrap_or_default();
@@ -414,6 +414,91 @@ mod tests {
         assert_eq!(expected.to_string(), actual.to_string());
     }
 
+    #[test]
+    fn callback_result_args() {
+        let impl_type: Type = syn::parse_str("Hello").unwrap();
+        let mut method: ImplItemMethod = parse_quote! {
+            #[private] pub fn method(&self, #[callback] x: u64, #[callback_result] #[serializer(borsh)] y: Result<String, PromiseError>) { }
+        };
+        let method_info = ImplItemMethodInfo::new(&mut method, impl_type).unwrap();
+        let actual = method_info.method_wrapper();
+        let expected = quote!(
+            #[cfg(target_arch = "wasm32")]
+            #[no_mangle]
+            pub extern "C" fn method() {
+                near_sdk::env::setup_panic_hook();
+                near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
+                if env::current_account_id() != env::predecessor_account_id() {
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::PrivateMethod { method: "method".to_string() });
+                }
+                let data: Vec<u8> = match near_sdk::env::promise_result(0u64) {
+                    near_sdk::PromiseResult::Successful(x) => x,
+                    _ => near_sdk::FunctionError::panic(&near_sdk::SdkError::CallbackFailed { index: 0u64 })
+                };
+                let x: u64 = near_sdk::serde_json::from_slice(&data).unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidCallbackResult { index: 0u64, serializer: "JSON".to_string() }));
+                let y: Result<String, PromiseError> = match near_sdk::env::promise_result(1u64) {
+                    near_sdk::PromiseResult::Successful(data) => Ok(near_sdk::borsh::BorshDeserialize::try_from_slice(&data)
+                        .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidCallbackResult { index: 1u64, serializer: "Borsh".to_string() }))),
+                    near_sdk::PromiseResult::NotReady => Err(near_sdk::PromiseError::NotReady),
+                    near_sdk::PromiseResult::Failed => Err(near_sdk::PromiseError::Failed),
+                };
+                let contract: Hello = near_sdk::env::state_read().unwrap_or_default();
+                contract.method(x, y, );
+            }
+        );
+        assert_eq!(expected.to_string(), actual.to_string());
+    }
+
+    #[test]
+    fn callback_result_args_vec() {
+        let impl_type: Type = syn::parse_str("Hello").unwrap();
+        let mut method: ImplItemMethod = parse_quote! {
+            #[private] pub fn method(&self, #[callback_vec] #[callback_result] x: Vec<Result<String, PromiseError>>) { }
+        };
+        let method_info = ImplItemMethodInfo::new(&mut method, impl_type).unwrap();
+        let actual = method_info.method_wrapper();
+        let expected = quote!(
+            #[cfg(target_arch = "wasm32")]
+            #[no_mangle]
+            pub extern "C" fn method() {
+                near_sdk::env::setup_panic_hook();
+                near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
+                if env::current_account_id() != env::predecessor_account_id() {
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::PrivateMethod { method: "method".to_string() });
+                }
+                let x: Vec<Result<String, PromiseError> > = (0..near_sdk::env::promise_results_count())
+                    .map(|i| match near_sdk::env::promise_result(i) {
+                        near_sdk::PromiseResult::Successful(data) => Ok(near_sdk::serde_json::from_slice(&data)
+                            .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidCallbackResult { index: i, serializer: "JSON".to_string() }))),
+                        near_sdk::PromiseResult::NotReady => Err(near_sdk::PromiseError::NotReady),
+                        near_sdk::PromiseResult::Failed => Err(near_sdk::PromiseError::Failed),
+                    })
+                    .collect();
+                let contract: Hello = near_sdk::env::state_read().unwrap_or_default();
+                contract.method(x, );
+            }
+        );
+        assert_eq!(expected.to_string(), actual.to_string());
+    }
+
+    #[test]
+    fn callback_result_not_result() {
+        let impl_type: Type = syn::parse_str("Hello").unwrap();
+        let mut method: ImplItemMethod = parse_quote! {
+            pub fn method(&self, #[callback_result] x: String) { }
+        };
+        assert!(ImplItemMethodInfo::new(&mut method, impl_type).is_err());
+    }
+
+    #[test]
+    fn callback_result_not_promise_error() {
+        let impl_type: Type = syn::parse_str("Hello").unwrap();
+        let mut method: ImplItemMethod = parse_quote! {
+            pub fn method(&self, #[callback_vec] #[callback_result] x: Vec<Result<String, String>>) { }
+        };
+        assert!(ImplItemMethodInfo::new(&mut method, impl_type).is_err());
+    }
+
     #[test]
     fn simple_init() {
         let impl_type: Type = syn::parse_str("Hello").unwrap();
@@ -430,7 +515,7 @@ mod tests {
                 near_sdk::env::setup_panic_hook();
                 near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
                 if near_sdk::env::attached_deposit() != 0 {
-                    near_sdk::env::panic("Method method doesn't accept deposit".as_bytes());
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::DepositNotAccepted { method: "method".to_string() });
                 }
                 #[derive(near_sdk :: serde :: Deserialize)]
                 #[serde(crate = "near_sdk::serde")]
@@ -438,11 +523,11 @@ mod tests {
                     k: u64,
                 }
                 let Input { mut k, }: Input = near_sdk::serde_json::from_slice(
-                    &near_sdk::env::input().expect("Expected input since method has arguments.")
+                    &near_sdk::env::input().unwrap_or_else(|| near_sdk::FunctionError::panic(&near_sdk::SdkError::MissingInput))
                 )
-                .expect("Failed to deserialize input from JSON.");
+                .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidInput { serializer: "JSON".to_string() }));
                 if near_sdk::env::state_exists() {
-                    near_sdk::env::panic(b"The contract has already been initialized");
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::AlreadyInitialized);
                 }
                 let contract = Hello::method(&mut k,);
                 near_sdk::env::state_write(&contract);
@@ -467,7 +552,7 @@ mod tests {
                 near_sdk::env::setup_panic_hook();
                 near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
                 if near_sdk::env::attached_deposit() != 0 {
-                    near_sdk::env::panic("Method method doesn't accept deposit".as_bytes());
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::DepositNotAccepted { method: "method".to_string() });
                 }
                 #[derive(near_sdk :: serde :: Deserialize)]
                 #[serde(crate = "near_sdk::serde")]
@@ -475,9 +560,9 @@ mod tests {
                     k: u64,
                 }
                 let Input { mut k, }: Input = near_sdk::serde_json::from_slice(
-                    &near_sdk::env::input().expect("Expected input since method has arguments.")
+                    &near_sdk::env::input().unwrap_or_else(|| near_sdk::FunctionError::panic(&near_sdk::SdkError::MissingInput))
                 )
-                .expect("Failed to deserialize input from JSON.");
+                .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidInput { serializer: "JSON".to_string() }));
                 let contract = Hello::method(&mut k,);
                 near_sdk::env::state_write(&contract);
             }
@@ -507,11 +592,11 @@ mod tests {
                     k: u64,
                 }
                 let Input { mut k, }: Input = near_sdk::serde_json::from_slice(
-                    &near_sdk::env::input().expect("Expected input since method has arguments.")
+                    &near_sdk::env::input().unwrap_or_else(|| near_sdk::FunctionError::panic(&near_sdk::SdkError::MissingInput))
                 )
-                .expect("Failed to deserialize input from JSON.");
+                .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidInput { serializer: "JSON".to_string() }));
                 if near_sdk::env::state_exists() {
-                    near_sdk::env::panic(b"The contract has already been initialized");
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::AlreadyInitialized);
                 }
                 let contract = Hello::method(&mut k,);
                 near_sdk::env::state_write(&contract);
@@ -536,7 +621,7 @@ mod tests {
                 near_sdk::env::setup_panic_hook();
                 near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
                 if near_sdk::env::attached_deposit() != 0 {
-                    near_sdk::env::panic("Method method doesn't accept deposit".as_bytes());
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::DepositNotAccepted { method: "method".to_string() });
                 }
                 #[derive(near_sdk :: borsh :: BorshDeserialize)]
                 struct Input {
@@ -544,13 +629,13 @@ mod tests {
                     m: Bar,
                 }
                 let Input { k, m, }: Input = near_sdk::borsh::BorshDeserialize::try_from_slice(
-                    &near_sdk::env::input().expect("Expected input since method has arguments.")
+                    &near_sdk::env::input().unwrap_or_else(|| near_sdk::FunctionError::panic(&near_sdk::SdkError::MissingInput))
                 )
-                .expect("Failed to deserialize input from Borsh.");
+                .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidInput { serializer: "Borsh".to_string() }));
                 let mut contract: Hello = near_sdk::env::state_read().unwrap_or_default();
                 let result = contract.method(k, m, );
                 let result = near_sdk::borsh::BorshSerialize::try_to_vec(&result)
-                    .expect("Failed to serialize the return value using Borsh.");
+                    .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::ResultSerialization { serializer: "Borsh".to_string() }));
                 near_sdk::env::value_return(&result);
                 near_sdk::env::state_write(&contract);
             }
@@ -573,28 +658,28 @@ mod tests {
                 near_sdk::env::setup_panic_hook();
                 near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
                 if env::current_account_id() != env::predecessor_account_id() {
-                    near_sdk::env::panic("Method method is private".as_bytes());
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::PrivateMethod { method: "method".to_string() });
                 }
                 #[derive(near_sdk :: borsh :: BorshDeserialize)]
                 struct Input {
                     y: String,
                 }
                 let Input { y, }: Input = near_sdk::borsh::BorshDeserialize::try_from_slice(
-                    &near_sdk::env::input().expect("Expected input since method has arguments.")
+                    &near_sdk::env::input().unwrap_or_else(|| near_sdk::FunctionError::panic(&near_sdk::SdkError::MissingInput))
                 )
-                .expect("Failed to deserialize input from Borsh.");
+                .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidInput { serializer: "Borsh".to_string() }));
                 let data: Vec<u8> = match near_sdk::env::promise_result(0u64) {
                     near_sdk::PromiseResult::Successful(x) => x,
-                    _ => panic!("Callback computation {} was not successful", 0u64)
+                    _ => near_sdk::FunctionError::panic(&near_sdk::SdkError::CallbackFailed { index: 0u64 })
                 };
                 let mut x: u64 = near_sdk::borsh::BorshDeserialize::try_from_slice(&data)
-                    .expect("Failed to deserialize callback using Borsh");
+                    .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidCallbackResult { index: 0u64, serializer: "Borsh".to_string() }));
                 let data: Vec<u8> = match near_sdk::env::promise_result(1u64) {
                     near_sdk::PromiseResult::Successful(x) => x,
-                    _ => panic!("Callback computation {} was not successful", 1u64)
+                    _ => near_sdk::FunctionError::panic(&near_sdk::SdkError::CallbackFailed { index: 1u64 })
                 };
                 let z: Vec<u8> =
-                    near_sdk::serde_json::from_slice(&data).expect("Failed to deserialize callback using JSON");
+                    near_sdk::serde_json::from_slice(&data).unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::InvalidCallbackResult { index: 1u64, serializer: "JSON".to_string() }));
                 let contract: Hello = near_sdk::env::state_read().unwrap_or_default();
                 contract.method(&mut x, y, z, );
             }
@@ -635,10 +720,10 @@ mod tests {
                 near_sdk::env::setup_panic_hook();
                 near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
                 if env::current_account_id() != env::predecessor_account_id() {
-                    near_sdk::env::panic("Method private_method is private".as_bytes());
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::PrivateMethod { method: "private_method".to_string() });
                 }
                 if near_sdk::env::attached_deposit() != 0 {
-                    near_sdk::env::panic("Method private_method doesn't accept deposit".as_bytes());
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::DepositNotAccepted { method: "private_method".to_string() });
                 }
                 let mut contract: Hello = near_sdk::env::state_read().unwrap_or_default();
                 contract.private_method();
@@ -648,6 +733,79 @@ mod tests {
         assert_eq!(expected.to_string(), actual.to_string());
     }
 
+    #[test]
+    fn handle_result_mut() {
+        let impl_type: Type = syn::parse_str("Hello").unwrap();
+        let mut method: ImplItemMethod = parse_quote! {
+            #[handle_result]
+            pub fn method(&mut self) -> Result<u64, String> { }
+        };
+        let method_info = ImplItemMethodInfo::new(&mut method, impl_type).unwrap();
+        let actual = method_info.method_wrapper();
+        let expected = quote!(
+            #[cfg(target_arch = "wasm32")]
+            #[no_mangle]
+            pub extern "C" fn method() {
+                near_sdk::env::setup_panic_hook();
+                near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
+                if near_sdk::env::attached_deposit() != 0 {
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::DepositNotAccepted { method: "method".to_string() });
+                }
+                let mut contract: Hello = near_sdk::env::state_read().unwrap_or_default();
+                let result = contract.method();
+                match result {
+                    Ok(result) => {
+                        let result = near_sdk::serde_json::to_vec(&result)
+                            .unwrap_or_else(|_| near_sdk::FunctionError::panic(&near_sdk::SdkError::ResultSerialization { serializer: "JSON".to_string() }));
+                        near_sdk::env::value_return(&result);
+                        near_sdk::env::state_write(&contract);
+                    }
+                    Err(err) => near_sdk::FunctionError::panic(&err),
+                }
+            }
+        );
+        assert_eq!(expected.to_string(), actual.to_string());
+    }
+
+    #[test]
+    fn handle_result_init() {
+        let impl_type: Type = syn::parse_str("Hello").unwrap();
+        let mut method: ImplItemMethod = parse_quote! {
+            #[init(ignore_state)]
+            #[handle_result]
+            pub fn method() -> Result<Self, String> { }
+        };
+        let method_info = ImplItemMethodInfo::new(&mut method, impl_type).unwrap();
+        let actual = method_info.method_wrapper();
+        let expected = quote!(
+            #[cfg(target_arch = "wasm32")]
+            #[no_mangle]
+            pub extern "C" fn method() {
+                near_sdk::env::setup_panic_hook();
+                near_sdk::env::set_blockchain_interface(Box::new(near_blockchain::NearBlockchain {}));
+                if near_sdk::env::attached_deposit() != 0 {
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::DepositNotAccepted { method: "method".to_string() });
+                }
+                let contract = match Hello::method() {
+                    Ok(contract) => contract,
+                    Err(err) => near_sdk::FunctionError::panic(&err),
+                };
+                near_sdk::env::state_write(&contract);
+            }
+        );
+        assert_eq!(expected.to_string(), actual.to_string());
+    }
+
+    #[test]
+    fn handle_result_without_result() {
+        let impl_type: Type = syn::parse_str("Hello").unwrap();
+        let mut method: ImplItemMethod = parse_quote! {
+            #[handle_result]
+            pub fn method(&self) -> u64 { }
+        };
+        assert!(ImplItemMethodInfo::new(&mut method, impl_type).is_err());
+    }
+
     #[test]
     fn marshall_one_arg() {
         let impl_type: Type = syn::parse_str("Hello").unwrap();
@@ -6,8 +6,10 @@ impl ItemTraitInfo {
     /// Generate code that wrapps external calls.
     pub fn wrapped_module(&self) -> TokenStream2 {
         let mut result = TokenStream2::new();
+        let mut typed = TokenStream2::new();
         for method in &self.methods {
             result.extend(method.method_wrapper());
+            typed.extend(method.typed_method_wrapper());
         }
         let mod_name = &self.mod_name;
         quote! {
@@ -16,6 +18,10 @@ impl ItemTraitInfo {
                 use near_sdk::{Gas, Balance, AccountId, Promise};
                 use std::string::ToString;
                 #result
+                pub mod typed {
+                    use super::*;
+                    #typed
+                }
             }
         }
     }
@@ -85,7 +91,66 @@ mod tests {
                         __gas,
                     )
                 }
+                pub mod typed {
+                    use super::*;
+                    pub fn merge_sort<T: ToString>(
+                        arr: Vec<u8>,
+                        __account_id: &T,
+                        __balance: near_sdk::Balance,
+                        __gas: near_sdk::Gas
+                    ) -> near_sdk::TypedPromise<Vec<u8> > {
+                        <near_sdk::TypedPromise<Vec<u8> > >::new(super::merge_sort(arr, __account_id, __balance, __gas))
+                    }
+                    pub fn merge<T: ToString>(
+                        __account_id: &T,
+                        __balance: near_sdk::Balance,
+                        __gas: near_sdk::Gas
+                    ) -> near_sdk::TypedCallback<(Vec<u8>, Vec<u8>,), Vec<u8> > {
+                        <near_sdk::TypedCallback<(Vec<u8>, Vec<u8>,), Vec<u8> > >::new(super::merge(__account_id, __balance, __gas))
+                    }
+                }
+            }
+        };
+        assert_eq!(actual.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn typed_callback() {
+        let mut t: ItemTrait = syn::parse2(
+            quote!{
+              trait TestExt {
+                fn on_get(&self, #[callback] value: u64);
+              }
+            }
+        ).unwrap();
+        let info = ItemTraitInfo::new(&mut t, None).unwrap();
+        let actual = info.wrapped_module();
+
+        let expected = quote! {
+          pub mod test_ext {
+            use super::*;
+            use near_sdk::{Gas, Balance, AccountId, Promise};
+            use std::string::ToString;
+            pub fn on_get<T: ToString>(__account_id: &T, __balance: near_sdk::Balance, __gas: near_sdk::Gas) -> near_sdk::Promise {
+                let args = vec![];
+                near_sdk::Promise::new(__account_id.to_string()).function_call(
+                    b"on_get".to_vec(),
+                    args,
+                    __balance,
+                    __gas,
+                )
+            }
+            pub mod typed {
+                use super::*;
+                pub fn on_get<T: ToString>(
+                    __account_id: &T,
+                    __balance: near_sdk::Balance,
+                    __gas: near_sdk::Gas
+                ) -> near_sdk::TypedCallback<u64, ()> {
+                    <near_sdk::TypedCallback<u64, ()> >::new(super::on_get(__account_id, __balance, __gas))
+                }
             }
+          }
         };
         assert_eq!(actual.to_string(), expected.to_string());
     }
@@ -128,8 +193,74 @@ mod tests {
                     __gas,
                 )
             }
+            pub mod typed {
+                use super::*;
+                pub fn test<T: ToString>(
+                    v: Vec<String>,
+                    __account_id: &T,
+                    __balance: near_sdk::Balance,
+                    __gas: near_sdk::Gas
+                ) -> near_sdk::TypedPromise<Vec<String> > {
+                    <near_sdk::TypedPromise<Vec<String> > >::new(super::test(v, __account_id, __balance, __gas))
+                }
+            }
         }
         };
         assert_eq!(actual.to_string(), expected.to_string());
     }
+
+    #[test]
+    fn handle_result() {
+        let mut t: ItemTrait = syn::parse2(
+            quote!{
+              trait TestExt {
+                #[handle_result]
+                fn withdraw(&self, amount: u64) -> Result<u64, MyError>;
+              }
+            }
+        ).unwrap();
+        let info = ItemTraitInfo::new(&mut t, None).unwrap();
+        let actual = info.wrapped_module();
+
+        let expected = quote! {
+          pub mod test_ext {
+            use super::*;
+            use near_sdk::{Gas, Balance, AccountId, Promise};
+            use std::string::ToString;
+            pub fn withdraw<T: ToString>(
+                amount: u64,
+                __account_id: &T,
+                __balance: near_sdk::Balance,
+                __gas: near_sdk::Gas
+            ) -> near_sdk::Promise {
+                #[derive(near_sdk :: serde :: Serialize)]
+                #[serde(crate = "near_sdk::serde")]
+                struct Input {
+                    amount: u64,
+                }
+                let args = Input { amount, };
+                let args = near_sdk::serde_json::to_vec(&args)
+                    .expect("Failed to serialize the cross contract args using JSON.");
+                near_sdk::Promise::new(__account_id.to_string()).function_call(
+                    b"withdraw".to_vec(),
+                    args,
+                    __balance,
+                    __gas,
+                )
+            }
+            pub mod typed {
+                use super::*;
+                pub fn withdraw<T: ToString>(
+                    amount: u64,
+                    __account_id: &T,
+                    __balance: near_sdk::Balance,
+                    __gas: near_sdk::Gas
+                ) -> near_sdk::TypedPromise<u64> {
+                    <near_sdk::TypedPromise<u64> >::new(super::withdraw(amount, __account_id, __balance, __gas))
+                }
+            }
+          }
+        };
+        assert_eq!(actual.to_string(), expected.to_string());
+    }
 }
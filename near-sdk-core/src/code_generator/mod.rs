@@ -15,3 +15,16 @@ pub use item_impl_info::*;
 
 mod item_struct_info;
 pub use item_struct_info::*;
+
+mod contract_error_info;
+pub use contract_error_info::*;
+
+use quote::quote;
+use syn::export::TokenStream2;
+
+/// Create code that fails the call with the `near_sdk::SdkError` constructed by `error`.
+fn sdk_error(error: TokenStream2) -> TokenStream2 {
+    quote! {
+        near_sdk::FunctionError::panic(&near_sdk::SdkError::#error)
+    }
+}
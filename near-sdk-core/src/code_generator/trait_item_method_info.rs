@@ -1,9 +1,12 @@
 use crate::{
-    info_extractor::{InputStructType, SerializerType, TraitItemMethodInfo},
+    info_extractor::{
+        nth_type_argument, BindgenArgType, InputStructType, SerializerType, TraitItemMethodInfo,
+    },
     AttrSigInfo,
 };
 use quote::quote;
 use syn::export::TokenStream2;
+use syn::{ReturnType, Type};
 
 impl TraitItemMethodInfo {
     /// Generate code that wraps the method.
@@ -29,6 +32,59 @@ impl TraitItemMethodInfo {
         }
     }
 
+    /// Generate code that wraps the method into a typed promise. It is placed in a module nested
+    /// in the module of `method_wrapper` and calls it.
+    pub fn typed_method_wrapper(&self) -> TokenStream2 {
+        let ident = &self.attr_sig_info.ident;
+        let pat_type_list = self.attr_sig_info.pat_type_list();
+        let arg_idents = self.attr_sig_info.input_args().map(|arg| &arg.ident);
+        let result_type = self.promise_result_type();
+        // Calls of callbacks can only be scheduled after promises that resolve to their input.
+        let callback_types: Vec<_> = self
+            .attr_sig_info
+            .args
+            .iter()
+            .filter(|arg| {
+                matches!(
+                    arg.bindgen_ty,
+                    BindgenArgType::CallbackArg | BindgenArgType::CallbackResultArg
+                )
+            })
+            .map(|arg| arg.callback_result_type().unwrap_or(&arg.ty))
+            .collect();
+        let return_type = match callback_types.as_slice() {
+            [] => quote! { near_sdk::TypedPromise<#result_type> },
+            [ty] => quote! { near_sdk::TypedCallback<#ty, #result_type> },
+            tys => quote! { near_sdk::TypedCallback<(#(#tys,)*), #result_type> },
+        };
+        quote! {
+            pub fn #ident<T: ToString>(#pat_type_list __account_id: &T, __balance: near_sdk::Balance, __gas: near_sdk::Gas) -> #return_type {
+                <#return_type>::new(super::#ident(#(#arg_idents,)* __account_id, __balance, __gas))
+            }
+        }
+    }
+
+    /// The type that a call of the method resolves to: `T` for methods that return
+    /// `PromiseOrValue<T>` or `TypedPromise<T>`, `T` for `#[handle_result]` methods that return
+    /// `Result<T, E>`, and the return type otherwise.
+    fn promise_result_type(&self) -> TokenStream2 {
+        let ty: &Type = match &self.attr_sig_info.returns {
+            ReturnType::Default => return quote! { () },
+            ReturnType::Type(_, ty) => ty,
+        };
+        // The error of a `#[handle_result]` method fails the call, so only `T` is returned.
+        let ty = match self.attr_sig_info.result_ok_type() {
+            Some(ok_type) if self.attr_sig_info.is_handles_result => ok_type,
+            _ => ty,
+        };
+        match nth_type_argument(ty, "PromiseOrValue", 0)
+            .or_else(|| nth_type_argument(ty, "TypedPromise", 0))
+        {
+            Some(inner) => quote! { #inner },
+            None => quote! { #ty },
+        }
+    }
+
     pub fn generate_serialier(
         attr_sig_info: &AttrSigInfo,
         serializer: &SerializerType,
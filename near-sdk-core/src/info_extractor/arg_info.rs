@@ -1,7 +1,8 @@
 use crate::info_extractor::serializer_attr::SerializerAttr;
-use crate::info_extractor::SerializerType;
+use crate::info_extractor::{nth_type_argument, SerializerType};
 use quote::ToTokens;
 use syn::export::Span;
+use syn::spanned::Spanned;
 use syn::{Attribute, Error, Ident, Pat, PatType, Token, Type};
 
 pub enum BindgenArgType {
@@ -11,6 +12,10 @@ pub enum BindgenArgType {
     CallbackArg,
     /// An argument that we read from all `env::promise_result()`.
     CallbackArgVec,
+    /// A `Result<T, PromiseError>` argument that we read from a single `env::promise_result()`.
+    CallbackResultArg,
+    /// A `Vec<Result<T, PromiseError>>` argument that we read from all `env::promise_result()`.
+    CallbackResultArgVec,
 }
 
 /// A single argument of a function after it was processed by the bindgen.
@@ -68,6 +73,7 @@ impl ArgInfo {
         let mut bindgen_ty = BindgenArgType::Regular;
         // In the absence of serialization attributes this is a JSON serialization.
         let mut serializer_ty = SerializerType::JSON;
+        let mut is_callback_result = false;
         for attr in &mut original.attrs {
             let attr_str = attr.path.to_token_stream().to_string();
             match attr_str.as_str() {
@@ -77,6 +83,9 @@ impl ArgInfo {
                 "callback_vec" => {
                     bindgen_ty = BindgenArgType::CallbackArgVec;
                 }
+                "callback_result" => {
+                    is_callback_result = true;
+                }
                 "serializer" => {
                     let serializer: SerializerAttr = syn::parse2(attr.tokens.clone())?;
                     serializer_ty = serializer.serializer_type;
@@ -89,9 +98,34 @@ impl ArgInfo {
 
         original.attrs.retain(|attr| {
             let attr_str = attr.path.to_token_stream().to_string();
-            attr_str != "callback" && attr_str != "callback_vec" && attr_str != "serializer"
+            attr_str != "callback"
+                && attr_str != "callback_vec"
+                && attr_str != "callback_result"
+                && attr_str != "serializer"
         });
 
+        if is_callback_result {
+            let (result_ty, error) = match bindgen_ty {
+                BindgenArgType::CallbackArgVec => {
+                    bindgen_ty = BindgenArgType::CallbackResultArgVec;
+                    (
+                        nth_type_argument(&ty, "Vec", 0),
+                        "#[callback_vec] #[callback_result] argument should be Vec<Result<T, PromiseError>>.",
+                    )
+                }
+                _ => {
+                    bindgen_ty = BindgenArgType::CallbackResultArg;
+                    (Some(&ty), "#[callback_result] argument should be Result<T, PromiseError>.")
+                }
+            };
+            let error_ty = result_ty.and_then(|ty| nth_type_argument(ty, "Result", 1));
+            match error_ty {
+                Some(ty) if is_path_to(ty, "PromiseError") => {}
+                Some(ty) => return Err(Error::new(ty.span(), error)),
+                None => return Err(Error::new(original.ty.span(), error)),
+            }
+        }
+
         Ok(Self {
             non_bindgen_attrs,
             ident,
@@ -105,4 +139,28 @@ impl ArgInfo {
             original: original.clone(),
         })
     }
+
+    /// The `T` of a `#[callback_result]` argument, the type that the promise resolves to.
+    pub fn callback_result_type(&self) -> Option<&Type> {
+        match self.bindgen_ty {
+            BindgenArgType::CallbackResultArg => nth_type_argument(&self.ty, "Result", 0),
+            BindgenArgType::CallbackResultArgVec => {
+                nth_type_argument(nth_type_argument(&self.ty, "Vec", 0)?, "Result", 0)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether `ty` is a path that ends with `ident` without type arguments, e.g. `PromiseError` or
+/// `near_sdk::PromiseError`.
+fn is_path_to(ty: &Type, ident: &str) -> bool {
+    match ty {
+        Type::Path(type_path) => type_path
+            .path
+            .segments
+            .last()
+            .map_or(false, |segment| segment.ident == ident && segment.arguments.is_empty()),
+        _ => false,
+    }
 }
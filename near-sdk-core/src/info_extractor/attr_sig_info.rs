@@ -1,11 +1,11 @@
 use crate::info_extractor::arg_info::{ArgInfo, BindgenArgType};
 use crate::info_extractor::serializer_attr::SerializerAttr;
 use crate::info_extractor::SerializerType;
-use crate::info_extractor::{InitAttr, MethodType};
+use crate::info_extractor::{nth_type_argument, InitAttr, MethodType};
 use quote::ToTokens;
 use syn::export::Span;
 use syn::spanned::Spanned;
-use syn::{Attribute, Error, FnArg, Ident, Receiver, ReturnType, Signature};
+use syn::{Attribute, Error, FnArg, Ident, Receiver, ReturnType, Signature, Type};
 
 /// Information extracted from method attributes and signature.
 pub struct AttrSigInfo {
@@ -21,6 +21,8 @@ pub struct AttrSigInfo {
     pub is_payable: bool,
     /// Whether method can accept calls from self (current account)
     pub is_private: bool,
+    /// Whether method returns `Result<T, E>` and fails the call on `Err`.
+    pub is_handles_result: bool,
     /// The serializer that we use for `env::input()`.
     pub input_serializer: SerializerType,
     /// The serializer that we use for the return type.
@@ -64,6 +66,7 @@ impl AttrSigInfo {
         let mut method_type = MethodType::Regular;
         let mut is_payable = false;
         let mut is_private = false;
+        let mut handle_result_span = None;
         // By the default we serialize the result with JSON.
         let mut result_serializer = SerializerType::JSON;
 
@@ -86,6 +89,9 @@ impl AttrSigInfo {
                 "private" => {
                     is_private = true;
                 }
+                "handle_result" => {
+                    handle_result_span = Some(attr.span());
+                }
                 "result_serializer" => {
                     let serializer: SerializerAttr = syn::parse2(attr.tokens.clone())?;
                     result_serializer = serializer.serializer_type;
@@ -128,6 +134,15 @@ impl AttrSigInfo {
             }
         }
 
+        if let Some(handle_result_span) = handle_result_span {
+            if result_ok_type(&original_sig.output).is_none() {
+                return Err(Error::new(
+                    handle_result_span,
+                    "Method with #[handle_result] must return Result<T, E>",
+                ));
+            }
+        }
+
         *original_attrs = non_bindgen_attrs.clone();
         let returns = original_sig.output.clone();
 
@@ -139,6 +154,7 @@ impl AttrSigInfo {
             method_type,
             is_payable,
             is_private,
+            is_handles_result: handle_result_span.is_some(),
             result_serializer,
             receiver,
             returns,
@@ -167,4 +183,16 @@ impl AttrSigInfo {
             _ => false,
         })
     }
+
+    /// The `T` of a method that returns `Result<T, E>`.
+    pub fn result_ok_type(&self) -> Option<&Type> {
+        result_ok_type(&self.returns)
+    }
+}
+
+fn result_ok_type(returns: &ReturnType) -> Option<&Type> {
+    match returns {
+        ReturnType::Type(_, ty) => nth_type_argument(ty, "Result", 0),
+        ReturnType::Default => None,
+    }
 }
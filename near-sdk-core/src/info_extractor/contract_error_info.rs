@@ -0,0 +1,61 @@
+use inflector::Inflector;
+use std::collections::HashSet;
+use syn::spanned::Spanned;
+use syn::{Attribute, Data, DeriveInput, Error, Generics, Ident, Lit, Meta};
+
+/// Information extracted from a type that derives `ContractError`.
+pub struct ContractErrorInfo {
+    /// The name of the error type.
+    pub ident: Ident,
+    /// The generics of the error type.
+    pub generics: Generics,
+    /// The error code of every variant of an enum, or the error code of a struct with `None`.
+    pub codes: Vec<(Option<Ident>, String)>,
+}
+
+impl ContractErrorInfo {
+    pub fn new(input: &DeriveInput) -> syn::Result<Self> {
+        let codes = match &input.data {
+            Data::Enum(data) => data
+                .variants
+                .iter()
+                .map(|variant| {
+                    Ok((Some(variant.ident.clone()), error_code(&variant.attrs, &variant.ident)?))
+                })
+                .collect::<syn::Result<Vec<_>>>()?,
+            Data::Struct(_) => vec![(None, error_code(&input.attrs, &input.ident)?)],
+            Data::Union(_) => {
+                return Err(Error::new(
+                    input.span(),
+                    "ContractError can only be derived for enums and structs.",
+                ))
+            }
+        };
+        let mut seen = HashSet::new();
+        for (variant, code) in &codes {
+            if !seen.insert(code) {
+                return Err(Error::new(
+                    variant.span(),
+                    format!("Error code {} is used more than once.", code),
+                ));
+            }
+        }
+        Ok(Self { ident: input.ident.clone(), generics: input.generics.clone(), codes })
+    }
+}
+
+/// The code set with `#[error_code = "..."]`, or the name in screaming snake case.
+fn error_code(attrs: &[Attribute], ident: &Ident) -> syn::Result<String> {
+    for attr in attrs {
+        if attr.path.is_ident("error_code") {
+            return match attr.parse_meta()? {
+                Meta::NameValue(meta) => match meta.lit {
+                    Lit::Str(code) => Ok(code.value()),
+                    lit => Err(Error::new(lit.span(), "Error code should be a string.")),
+                },
+                meta => Err(Error::new(meta.span(), "Expected #[error_code = \"...\"].")),
+            };
+        }
+    }
+    Ok(ident.to_string().to_screaming_snake_case())
+}
@@ -1,3 +1,5 @@
+use syn::{GenericArgument, PathArguments, Type};
+
 mod serializer_attr;
 pub use serializer_attr::SerializerAttr;
 
@@ -21,6 +23,9 @@ mod item_impl_info;
 mod init_attr;
 pub use init_attr::InitAttr;
 
+mod contract_error_info;
+pub use contract_error_info::ContractErrorInfo;
+
 pub use item_impl_info::ItemImplInfo;
 
 /// Type of serialization we use.
@@ -45,3 +50,22 @@ pub enum InputStructType {
     Serialization,
     Deserialization,
 }
+
+/// The type argument at position `n` of `ty` if it is a path that ends with `ident`, e.g. `E` of
+/// `Result<T, E>` for `n = 1`.
+pub(crate) fn nth_type_argument<'a>(ty: &'a Type, ident: &str, n: usize) -> Option<&'a Type> {
+    let segment = match ty {
+        Type::Path(type_path) => type_path.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != ident {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.iter().nth(n)? {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
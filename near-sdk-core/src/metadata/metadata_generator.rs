@@ -67,11 +67,11 @@ impl ImplItemMethodInfo {
             .args
             .iter()
             .filter(|arg| match arg.bindgen_ty {
-                BindgenArgType::CallbackArg => true,
+                BindgenArgType::CallbackArg | BindgenArgType::CallbackResultArg => true,
                 _ => false,
             })
             .map(|arg| {
-                let ty = &arg.ty;
+                let ty = arg.callback_result_type().unwrap_or(&arg.ty);
                 quote! {
                     #ty::schema_container()
                 }
@@ -82,7 +82,7 @@ impl ImplItemMethodInfo {
             .args
             .iter()
             .filter(|arg| match arg.bindgen_ty {
-                BindgenArgType::CallbackArgVec => true,
+                BindgenArgType::CallbackArgVec | BindgenArgType::CallbackResultArgVec => true,
                 _ => false,
             })
             .last()
@@ -92,14 +92,26 @@ impl ImplItemMethodInfo {
                     None
                 }
             }
-            Some(arg) => {
-                let ty = &arg.ty;
+            Some(arg) => match arg.callback_result_type() {
+                Some(ty) => quote! {
+                    Some(Vec::<#ty>::schema_container())
+                },
+                None => {
+                    let ty = &arg.ty;
+                    quote! {
+                        Some(#ty::schema_container())
+                    }
+                }
+            },
+        };
+        let result = match &self.attr_signature_info.returns {
+            _ if self.attr_signature_info.is_handles_result => {
+                // Only `Ok` is returned, `Err` fails the call.
+                let ty = self.attr_signature_info.result_ok_type();
                 quote! {
                     Some(#ty::schema_container())
                 }
             }
-        };
-        let result = match &self.attr_signature_info.returns {
             ReturnType::Default => {
                 quote! {
                     None
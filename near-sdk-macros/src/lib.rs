@@ -7,7 +7,7 @@ use near_sdk_core::*;
 use proc_macro2::Span;
 use quote::quote;
 use syn::visit::Visit;
-use syn::{File, ItemEnum, ItemImpl, ItemStruct, ItemTrait};
+use syn::{DeriveInput, File, ItemEnum, ItemImpl, ItemStruct, ItemTrait};
 
 #[proc_macro_attribute]
 pub fn near_bindgen(_attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -91,6 +91,12 @@ pub fn callback(_attr: TokenStream, item: TokenStream) -> TokenStream {
     item
 }
 
+/// `callback_result` is a marker attribute it does not generate code by itself.
+#[proc_macro_attribute]
+pub fn callback_result(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    item
+}
+
 /// `callback_args_vec` is a marker attribute it does not generate code by itself.
 #[proc_macro_attribute]
 pub fn callback_vec(_attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -142,8 +148,8 @@ pub fn metadata(item: TokenStream) -> TokenStream {
     }
 }
 
-/// `PanicOnDefault` generates implementation for `Default` trait that panics with the following
-/// message `The contract is not initialized` when `default()` is called.
+/// `PanicOnDefault` generates implementation for `Default` trait that panics with
+/// `near_sdk::SdkError::NotInitialized` when `default()` is called.
 /// This is a helpful macro in case the contract is required to be initialized with either `init` or
 /// `init(ignore_state)`.
 #[proc_macro_derive(PanicOnDefault)]
@@ -153,7 +159,7 @@ pub fn derive_no_default(item: TokenStream) -> TokenStream {
         TokenStream::from(quote! {
             impl Default for #name {
                 fn default() -> Self {
-                    near_sdk::env::panic(b"The contract is not initialized");
+                    near_sdk::FunctionError::panic(&near_sdk::SdkError::NotInitialized)
                 }
             }
         })
@@ -190,3 +196,20 @@ pub fn borsh_storage_key(item: TokenStream) -> TokenStream {
         impl near_sdk::BorshIntoStorageKey for #name {}
     })
 }
+
+/// `ContractError` generates implementations of `near_sdk::ContractError` and
+/// `near_sdk::FunctionError` for an enum or a struct. Every variant gets a stable error code, its
+/// name in screaming snake case unless set with `#[error_code = "..."]`, and the error fails the
+/// call with the JSON error message when it is returned from `#[handle_result]` methods. The type
+/// should also derive `Serialize`.
+#[proc_macro_derive(ContractError, attributes(error_code))]
+pub fn contract_error(item: TokenStream) -> TokenStream {
+    let input = match syn::parse::<DeriveInput>(item) {
+        Ok(input) => input,
+        Err(err) => return TokenStream::from(err.to_compile_error()),
+    };
+    match ContractErrorInfo::new(&input) {
+        Ok(info) => TokenStream::from(info.contract_error_impl()),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
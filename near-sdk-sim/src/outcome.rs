@@ -75,6 +75,32 @@ impl ExecutionResult {
         near_sdk::serde_json::from_value(self.unwrap_json_value()).unwrap()
     }
 
+    /// Decode the `ContractError` that the transaction failed with, or `None` if the transaction
+    /// did not fail with an error of type `T`
+    pub fn contract_error<T: DeserializeOwned>(&self) -> Option<T> {
+        decode_contract_error(&self.failure_message()?)
+    }
+
+    /// Decode the `ContractError` that the transaction failed with and panic if there is none
+    pub fn unwrap_contract_error<T: DeserializeOwned>(&self) -> T {
+        match self.contract_error() {
+            Some(error) => error,
+            None => panic!("Expected a contract error but got: {:#?}", self.outcome.status),
+        }
+    }
+
+    /// Code of the `ContractError` that the transaction failed with
+    pub fn contract_error_code(&self) -> Option<String> {
+        near_sdk::parse_contract_error(&self.failure_message()?).map(|(code, _)| code)
+    }
+
+    fn failure_message(&self) -> Option<String> {
+        match &(self.outcome).status {
+            ExecutionStatus::Failure(err) => Some(err.to_string()),
+            _ => None,
+        }
+    }
+
     /// Check if transaction was successful
     pub fn is_ok(&self) -> bool {
         match &(self.outcome).status {
@@ -238,6 +264,31 @@ impl ViewResult {
     pub fn unwrap_json<T: DeserializeOwned>(&self) -> T {
         near_sdk::serde_json::from_value(self.unwrap_json_value()).unwrap()
     }
+
+    /// Decode the `ContractError` that the view call failed with, or `None` if the call did not
+    /// fail with an error of type `T`
+    pub fn contract_error<T: DeserializeOwned>(&self) -> Option<T> {
+        decode_contract_error(&self.result.as_ref().err()?.to_string())
+    }
+
+    /// Decode the `ContractError` that the view call failed with and panic if there is none
+    pub fn unwrap_contract_error<T: DeserializeOwned>(&self) -> T {
+        match self.contract_error() {
+            Some(error) => error,
+            None => panic!("Expected a contract error but got: {:?}", self.result),
+        }
+    }
+
+    /// Code of the `ContractError` that the view call failed with
+    pub fn contract_error_code(&self) -> Option<String> {
+        let message = self.result.as_ref().err()?.to_string();
+        near_sdk::parse_contract_error(&message).map(|(code, _)| code)
+    }
+}
+
+fn decode_contract_error<T: DeserializeOwned>(message: &str) -> Option<T> {
+    let (_, error) = near_sdk::parse_contract_error(message)?;
+    near_sdk::serde_json::from_value(error).ok()
 }
 
 #[cfg(test)]
@@ -246,6 +297,7 @@ mod tests {
     use crate::runtime::init_runtime;
     use near_primitives::transaction::ExecutionStatus::SuccessValue;
     use near_sdk::serde_json::json;
+    use near_sdk::{ContractError, SdkError};
 
     #[test]
     fn value_test() {
@@ -261,4 +313,30 @@ mod tests {
         );
         assert_eq!(value, result.unwrap_json_value());
     }
+
+    #[test]
+    fn contract_error_test() {
+        #[derive(near_sdk::serde::Deserialize, Debug, PartialEq)]
+        #[serde(crate = "near_sdk::serde")]
+        enum Error {
+            NotEnoughBalance { needed: u64 },
+        }
+        let message = r#"Smart contract panicked: {"error_code":"NOT_ENOUGH_BALANCE","error":{"NotEnoughBalance":{"needed":10}}}"#;
+        let result = ViewResult::new(Err(Box::from(message)), vec![]);
+        assert_eq!(result.contract_error_code(), Some("NOT_ENOUGH_BALANCE".to_string()));
+        assert_eq!(result.unwrap_contract_error::<Error>(), Error::NotEnoughBalance { needed: 10 });
+        let result = ViewResult::new(Ok(vec![]), vec![]);
+        assert_eq!(result.contract_error::<Error>(), None);
+    }
+
+    #[test]
+    fn sdk_error_test() {
+        // The message that `near_bindgen` fails a call with when a deposit is attached to a method
+        // that is not `#[payable]`.
+        let error = SdkError::DepositNotAccepted { method: "set_status".to_string() };
+        let message = format!("Smart contract panicked: {}", error.error_message());
+        let result = ViewResult::new(Err(Box::from(message)), vec![]);
+        assert_eq!(result.contract_error_code(), Some("DEPOSIT_NOT_ACCEPTED".to_string()));
+        assert_eq!(result.contract_error::<SdkError>(), Some(error));
+    }
 }
@@ -4,6 +4,13 @@ fn compilation_tests() {
     let t = trybuild::TestCases::new();
     t.pass("compilation_tests/regular.rs");
     t.pass("compilation_tests/private.rs");
+    t.pass("compilation_tests/handle_result.rs");
+    t.pass("compilation_tests/contract_error.rs");
+    t.pass("compilation_tests/display_error.rs");
+    t.pass("compilation_tests/typed_promise.rs");
+    t.compile_fail("compilation_tests/callback_mismatch.rs");
+    t.pass("compilation_tests/callback_result.rs");
+    t.compile_fail("compilation_tests/callback_result_error.rs");
     t.pass("compilation_tests/trait_impl.rs");
     t.pass("compilation_tests/metadata.rs");
     t.compile_fail("compilation_tests/metadata_invalid_rust.rs");
@@ -0,0 +1,31 @@
+//! Typed callbacks can't be scheduled after a promise of another result type.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::{env, ext_contract, near_bindgen, TypedPromise};
+
+#[ext_contract(ext_counter)]
+pub trait Counter {
+    fn get(&self) -> u64;
+}
+
+#[ext_contract(ext_self)]
+pub trait Callbacks {
+    fn on_name(&self, #[callback] name: String) -> String;
+}
+
+#[near_bindgen]
+#[derive(Default, BorshDeserialize, BorshSerialize)]
+struct Contract {}
+
+#[near_bindgen]
+impl Contract {
+    pub fn mismatch(&self) -> TypedPromise<String> {
+        ext_counter::typed::get(&"counter.near", 0, 1_000).then(ext_self::typed::on_name(
+            &env::current_account_id(),
+            0,
+            1_000,
+        ))
+    }
+}
+
+fn main() {}
@@ -0,0 +1,34 @@
+//! Smart contract with callbacks that handle failed promises.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::{near_bindgen, PromiseError};
+
+#[near_bindgen]
+#[derive(Default, BorshDeserialize, BorshSerialize)]
+struct Callback {
+    failures: u32,
+}
+
+#[near_bindgen]
+impl Callback {
+    #[private]
+    pub fn on_get(&mut self, #[callback_result] value: Result<u64, PromiseError>) -> u64 {
+        value.unwrap_or_else(|_| {
+            self.failures += 1;
+            0
+        })
+    }
+
+    #[private]
+    pub fn on_get_all(
+        &mut self,
+        #[callback_vec]
+        #[callback_result]
+        #[serializer(borsh)]
+        values: Vec<Result<u64, PromiseError>>,
+    ) -> u64 {
+        values.into_iter().filter_map(Result::ok).sum()
+    }
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+//! `#[callback_result]` arguments must have `PromiseError` as the error type.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::near_bindgen;
+
+#[near_bindgen]
+#[derive(Default, BorshDeserialize, BorshSerialize)]
+struct Callback {}
+
+#[near_bindgen]
+impl Callback {
+    #[private]
+    pub fn on_get(&mut self, #[callback_result] value: Result<u64, String>) -> u64 {
+        value.unwrap_or_default()
+    }
+}
+
+fn main() {}
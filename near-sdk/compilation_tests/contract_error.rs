@@ -0,0 +1,46 @@
+//! Smart contract with a typed error.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{near_bindgen, ContractError};
+
+#[derive(Serialize, Deserialize, ContractError)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Error {
+    NotEnoughBalance {
+        needed: u32,
+    },
+    #[error_code = "PAUSED"]
+    Stopped,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NotEnoughBalance { needed } => {
+                write!(f, "Not enough balance, needs {} more", needed)
+            }
+            Error::Stopped => f.write_str("The contract is stopped"),
+        }
+    }
+}
+
+#[near_bindgen]
+#[derive(Default, BorshDeserialize, BorshSerialize)]
+struct Account {
+    balance: u32,
+}
+
+#[near_bindgen]
+impl Account {
+    #[handle_result]
+    pub fn withdraw(&mut self, amount: u32) -> Result<u32, Error> {
+        if amount > self.balance {
+            return Err(Error::NotEnoughBalance { needed: amount - self.balance });
+        }
+        self.balance -= amount;
+        Ok(self.balance)
+    }
+}
+
+fn main() {}
@@ -0,0 +1,51 @@
+//! Smart contract with methods that fail with errors that implement `Display`.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::{near_bindgen, DisplayError};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    Overflow,
+    TooLarge(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Overflow => write!(f, "Overflow"),
+            Error::TooLarge(value) => write!(f, "{} is too large", value),
+        }
+    }
+}
+
+#[near_bindgen]
+#[derive(Default, BorshDeserialize, BorshSerialize)]
+struct Incrementer {
+    value: u32,
+}
+
+#[near_bindgen]
+impl Incrementer {
+    #[init]
+    #[handle_result]
+    pub fn new(starting_value: u32) -> Result<Self, DisplayError<Error>> {
+        if starting_value > 100 {
+            return Err(Error::TooLarge(starting_value).into());
+        }
+        Ok(Self { value: starting_value })
+    }
+
+    #[handle_result]
+    pub fn inc(&mut self, by: u32) -> Result<u32, DisplayError<Error>> {
+        self.value = self.value.checked_add(by).ok_or(Error::Overflow)?;
+        Ok(self.value)
+    }
+
+    #[handle_result]
+    pub fn parse(&self, value: String) -> Result<u32, DisplayError<std::num::ParseIntError>> {
+        Ok(value.parse::<u32>()?)
+    }
+}
+
+fn main() {}
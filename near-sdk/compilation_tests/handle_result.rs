@@ -0,0 +1,30 @@
+//! Smart contract with methods that return `Result`.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::near_bindgen;
+
+#[near_bindgen]
+#[derive(Default, BorshDeserialize, BorshSerialize)]
+struct Incrementer {
+    value: u32,
+}
+
+#[near_bindgen]
+impl Incrementer {
+    #[init]
+    #[handle_result]
+    pub fn new(starting_value: u32) -> Result<Self, String> {
+        if starting_value > 100 {
+            return Err("Starting value is too large".to_string());
+        }
+        Ok(Self { value: starting_value })
+    }
+
+    #[handle_result]
+    pub fn inc(&mut self, by: u32) -> Result<u32, String> {
+        self.value = self.value.checked_add(by).ok_or_else(|| "Overflow".to_string())?;
+        Ok(self.value)
+    }
+}
+
+fn main() {}
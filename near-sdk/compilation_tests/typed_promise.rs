@@ -0,0 +1,46 @@
+//! Smart contract that schedules typed promises and callbacks.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::{env, ext_contract, near_bindgen, Promise, PromiseOrValue, TypedPromise};
+
+#[ext_contract(ext_counter)]
+pub trait Counter {
+    fn get(&self) -> u64;
+    fn get_or_fetch(&self) -> PromiseOrValue<u64>;
+}
+
+#[ext_contract(ext_self)]
+pub trait Callbacks {
+    fn on_get(&self, #[callback] value: u64) -> String;
+    fn on_get_both(&self, #[callback] a: u64, #[callback] b: u64) -> u64;
+}
+
+#[near_bindgen]
+#[derive(Default, BorshDeserialize, BorshSerialize)]
+struct Contract {}
+
+#[near_bindgen]
+impl Contract {
+    pub fn single(&self) -> TypedPromise<String> {
+        ext_counter::typed::get(&"counter.near", 0, 1_000).then(ext_self::typed::on_get(
+            &env::current_account_id(),
+            0,
+            1_000,
+        ))
+    }
+
+    pub fn joint(&self) -> PromiseOrValue<u64> {
+        ext_counter::typed::get(&"counter.near", 0, 1_000)
+            .and(ext_counter::typed::get_or_fetch(&"counter.near", 0, 1_000))
+            .then(ext_self::typed::on_get_both(&env::current_account_id(), 0, 1_000))
+            .into()
+    }
+
+    pub fn untyped(&self) -> Promise {
+        ext_counter::get(&"counter.near", 0, 1_000)
+            .and(ext_counter::typed::get(&"counter.near", 0, 1_000))
+            .then(ext_self::on_get_both(&env::current_account_id(), 0, 1_000))
+    }
+}
+
+fn main() {}
@@ -0,0 +1,543 @@
+//! A priority queue implemented with a binary heap on a trie. The elements are stored in
+//! index-addressed slots like in `Vector`, so `push` and `pop` read and write `O(log(N))` slots.
+use std::cmp::Ordering;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::collections::cache::Cacheable;
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{
+    append, Borsh, Decoder, Encoder, Identity, LookupMap, NestedCollection, Vector,
+};
+use crate::{env, IntoStorageKey};
+
+const ERR_INCONSISTENT_STATE: &[u8] = b"The collection is an inconsistent state. Did previous smart contract execution terminate unexpectedly?";
+const ERR_ELEMENT_SERIALIZATION: &[u8] = b"Cannot serialize element";
+
+/// A max-heap that stores its content on the trie. Use `Reverse` to make it a min-heap.
+///
+/// Elements are encoded with the codec `C`, which is Borsh by default. See `with_codec`. The content
+/// is stored in the storage `B`, which is the storage of the contract by default. See
+/// `with_storage`.
+///
+/// ```
+/// # use near_sdk::collections::{BinaryHeap, Reverse};
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut expirations: BinaryHeap<Reverse<u64>> = BinaryHeap::new(b"e");
+/// expirations.push(&Reverse(30));
+/// expirations.push(&Reverse(10));
+/// expirations.push(&Reverse(20));
+/// assert_eq!(expirations.pop(), Some(Reverse(10)));
+/// assert_eq!(expirations.peek(), Some(Reverse(20)));
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct BinaryHeap<T, C = Borsh, B: Storage = EnvStorage> {
+    elements: Vector<T, C, B>,
+}
+
+impl<T> BinaryHeap<T, Borsh> {
+    /// Create new heap with zero elements. Use `prefix` as a unique identifier on the trie.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec(prefix)
+    }
+}
+
+impl<T, B: Storage> BinaryHeap<T, Borsh, B> {
+    /// Create new heap with zero elements that stores its content in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+}
+
+impl<T, C, B: Storage> BinaryHeap<T, C, B> {
+    /// Create new heap with zero elements that encodes its elements with the codec `C`. Use
+    /// `prefix` as a unique identifier on the trie.
+    pub fn with_codec<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, B::default())
+    }
+
+    /// Create new heap with zero elements that encodes its elements with the codec `C` and stores
+    /// them in `storage`. Use `prefix` as a unique identifier in the storage.
+    pub fn with_codec_and_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self { elements: Vector::with_codec_and_storage(prefix, storage) }
+    }
+
+    /// Returns the number of elements in the heap.
+    pub fn len(&self) -> u64 {
+        self.elements.len()
+    }
+
+    /// Returns `true` if the heap contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Removes all elements from the heap.
+    pub fn clear(&mut self) {
+        self.elements.clear();
+    }
+}
+
+impl<T, C, B: Storage> BinaryHeap<T, C, B>
+where
+    T: Ord,
+    C: Encoder<T> + Decoder<T>,
+{
+    /// Returns the greatest element of the heap, or `None` if it is empty. Costs a single storage
+    /// read.
+    pub fn peek(&self) -> Option<T> {
+        self.elements.get(0)
+    }
+
+    /// Pushes an element onto the heap. Costs up to `log2(len)` storage reads and writes.
+    pub fn push(&mut self, element: &T) {
+        let raw_element = Self::serialize_element(element);
+        self.sift_up(self.len(), element, &raw_element, &mut |_, _| {});
+    }
+
+    /// Removes the greatest element from the heap and returns it, or `None` if it is empty. Costs
+    /// up to `2 * log2(len)` storage reads and `log2(len)` storage writes.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.remove_at(0, &mut |_, _| {}))
+        }
+    }
+
+    /// Iterate over deserialized elements in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.elements.iter()
+    }
+
+    /// Copies the elements into an `std::vec::Vec` sorted in ascending order.
+    pub fn to_sorted_vec(&self) -> Vec<T> {
+        let mut result: Vec<T> = self.iter().collect();
+        result.sort();
+        result
+    }
+
+    pub fn extend<IT: IntoIterator<Item = T>>(&mut self, iter: IT) {
+        for el in iter {
+            self.push(&el)
+        }
+    }
+
+    fn serialize_element(element: &T) -> Vec<u8> {
+        match C::encode(element) {
+            Ok(x) => x,
+            Err(_) => env::panic(ERR_ELEMENT_SERIALIZATION),
+        }
+    }
+
+    fn get_at(&self, index: u64) -> T {
+        match self.elements.get(index) {
+            Some(x) => x,
+            None => env::panic(ERR_INCONSISTENT_STATE),
+        }
+    }
+
+    /// Writes the element into the slot at `index`, which is either occupied or the next slot
+    /// after the last one, and reports the new position of the element to `moved`.
+    fn write_at(&mut self, index: u64, element: &T, raw_element: &[u8], moved: Moved<T>) {
+        if index == self.len() {
+            self.elements.push_raw(raw_element);
+        } else {
+            self.elements.replace_raw(index, raw_element);
+        }
+        moved(element, index);
+    }
+
+    /// Puts the element into the hole at `index` and moves it towards the root.
+    fn sift_up(&mut self, mut index: u64, element: &T, raw_element: &[u8], moved: Moved<T>) {
+        while index > 0 {
+            let parent_index = (index - 1) / 2;
+            let parent = self.get_at(parent_index);
+            if parent >= *element {
+                break;
+            }
+            let raw_parent = Self::serialize_element(&parent);
+            self.write_at(index, &parent, &raw_parent, moved);
+            index = parent_index;
+        }
+        self.write_at(index, element, raw_element, moved);
+    }
+
+    /// Puts the element into the hole at `index` and moves it towards the leaves.
+    fn sift_down(&mut self, mut index: u64, element: &T, raw_element: &[u8], moved: Moved<T>) {
+        let len = self.len();
+        loop {
+            let mut child_index = 2 * index + 1;
+            if child_index >= len {
+                break;
+            }
+            let mut child = self.get_at(child_index);
+            if child_index + 1 < len {
+                let right = self.get_at(child_index + 1);
+                if right > child {
+                    child = right;
+                    child_index += 1;
+                }
+            }
+            if child <= *element {
+                break;
+            }
+            let raw_child = Self::serialize_element(&child);
+            self.write_at(index, &child, &raw_child, moved);
+            index = child_index;
+        }
+        self.write_at(index, element, raw_element, moved);
+    }
+
+    /// Replaces the element at `index` and restores the heap order. Returns the old element.
+    pub(crate) fn replace_at(&mut self, index: u64, element: &T, moved: Moved<T>) -> T {
+        let old = self.get_at(index);
+        let raw_element = Self::serialize_element(element);
+        match element.cmp(&old) {
+            Ordering::Greater => self.sift_up(index, element, &raw_element, moved),
+            _ => self.sift_down(index, element, &raw_element, moved),
+        }
+        old
+    }
+
+    /// Removes the element at `index` and restores the heap order.
+    pub(crate) fn remove_at(&mut self, index: u64, moved: Moved<T>) -> T {
+        let last = match self.elements.pop() {
+            Some(x) => x,
+            None => env::panic(ERR_INCONSISTENT_STATE),
+        };
+        if index == self.len() {
+            last
+        } else {
+            self.replace_at(index, &last, moved)
+        }
+    }
+}
+
+/// A callback that receives every element written to the heap and its new index.
+type Moved<'a, T> = &'a mut dyn FnMut(&T, u64);
+
+impl<T, C, B: Storage> NestedCollection<B> for BinaryHeap<T, C, B> {
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, C> Cacheable for BinaryHeap<T, C> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        self.elements.storage_prefixes()
+    }
+}
+
+/// A wrapper that reverses the order of the wrapped value, like `std::cmp::Reverse`, which can be
+/// serialized with Borsh.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Reverse<T>(pub T);
+
+impl<T: BorshSerialize> BorshSerialize for Reverse<T> {
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.0.serialize(writer)
+    }
+}
+
+impl<T: BorshDeserialize> BorshDeserialize for Reverse<T> {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        T::deserialize(buf).map(Reverse)
+    }
+}
+
+impl<T: PartialOrd> PartialOrd for Reverse<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        other.0.partial_cmp(&self.0)
+    }
+}
+
+impl<T: Ord> Ord for Reverse<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.cmp(&self.0)
+    }
+}
+
+/// A handle of an element in a `HandleBinaryHeap`. It stays valid until the element is removed.
+#[derive(
+    BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub struct HeapHandle(u64);
+
+/// An element of a `HandleBinaryHeap` together with its handle. Elements that are equal are
+/// ordered by their handles.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HandleEntry<T> {
+    element: T,
+    handle: HeapHandle,
+}
+
+/// A max-heap that stores its content on the trie and returns a handle for every pushed element,
+/// which can be used to read, change the priority of or remove the element.
+///
+/// Every write of an element also writes its position, so the operations cost about twice as
+/// many storage writes as the operations of `BinaryHeap`. The content is stored in the storage
+/// `B`, which is the storage of the contract by default. See `with_storage`.
+///
+/// ```
+/// # use near_sdk::collections::{HandleBinaryHeap, Reverse};
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut releases: HandleBinaryHeap<Reverse<u64>> = HandleBinaryHeap::new(b"r");
+/// let first = releases.push(&Reverse(30));
+/// releases.push(&Reverse(20));
+/// releases.update(first, &Reverse(10));
+/// assert_eq!(releases.pop(), Some((first, Reverse(10))));
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct HandleBinaryHeap<T, B: Storage = EnvStorage> {
+    heap: BinaryHeap<HandleEntry<T>, Borsh, B>,
+    positions: LookupMap<HeapHandle, u64, Identity, Borsh, B>,
+    next_handle: u64,
+}
+
+impl<T> HandleBinaryHeap<T> {
+    /// Create new heap with zero elements. Use `prefix` as a unique identifier on the trie.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_storage(prefix, EnvStorage)
+    }
+}
+
+impl<T, B: Storage> HandleBinaryHeap<T, B> {
+    /// Create new heap with zero elements that stores its content in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        let prefix = prefix.into_storage_key();
+        Self {
+            heap: BinaryHeap::with_storage(append(&prefix, b'h'), storage.clone()),
+            positions: LookupMap::with_storage(append(&prefix, b'p'), storage),
+            next_handle: 0,
+        }
+    }
+
+    /// Returns the number of elements in the heap.
+    pub fn len(&self) -> u64 {
+        self.heap.len()
+    }
+
+    /// Returns `true` if the heap contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+impl<T, B: Storage> HandleBinaryHeap<T, B>
+where
+    T: Ord + Clone + BorshSerialize + BorshDeserialize,
+{
+    /// Returns the greatest element of the heap and its handle, or `None` if it is empty.
+    pub fn peek(&self) -> Option<(HeapHandle, T)> {
+        self.heap.peek().map(|entry| (entry.handle, entry.element))
+    }
+
+    /// Pushes an element onto the heap and returns its handle.
+    pub fn push(&mut self, element: &T) -> HeapHandle {
+        let handle = HeapHandle(self.next_handle);
+        self.next_handle += 1;
+        let entry = HandleEntry { element: element.clone(), handle };
+        let raw_entry = BinaryHeap::<HandleEntry<T>, Borsh, B>::serialize_element(&entry);
+        let index = self.heap.len();
+        self.heap.sift_up(index, &entry, &raw_entry, &mut track(&mut self.positions));
+        handle
+    }
+
+    /// Removes the greatest element from the heap and returns it with its handle, or `None` if it
+    /// is empty.
+    pub fn pop(&mut self) -> Option<(HeapHandle, T)> {
+        if self.is_empty() {
+            return None;
+        }
+        let entry = self.heap.remove_at(0, &mut track(&mut self.positions));
+        self.positions.remove(&entry.handle);
+        Some((entry.handle, entry.element))
+    }
+
+    /// Returns the element with the given handle, or `None` if it was removed.
+    pub fn get(&self, handle: HeapHandle) -> Option<T> {
+        let index = self.positions.get(&handle)?;
+        Some(self.heap.get_at(index).element)
+    }
+
+    /// Replaces the element with the given handle, which changes its priority, and returns the old
+    /// element, or `None` if the element was removed.
+    pub fn update(&mut self, handle: HeapHandle, element: &T) -> Option<T> {
+        let index = self.positions.get(&handle)?;
+        let entry = HandleEntry { element: element.clone(), handle };
+        let old = self.heap.replace_at(index, &entry, &mut track(&mut self.positions));
+        Some(old.element)
+    }
+
+    /// Removes the element with the given handle from the heap and returns it, or `None` if it was
+    /// already removed.
+    pub fn remove(&mut self, handle: HeapHandle) -> Option<T> {
+        let index = self.positions.remove(&handle)?;
+        let removed = self.heap.remove_at(index, &mut track(&mut self.positions));
+        Some(removed.element)
+    }
+
+    /// Removes all elements from the heap. Handles of the removed elements are not reused.
+    pub fn clear(&mut self) {
+        for entry in self.heap.iter() {
+            self.positions.remove(&entry.handle);
+        }
+        self.heap.clear();
+    }
+
+    /// Iterate over the deserialized elements and their handles in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (HeapHandle, T)> + '_ {
+        self.heap.iter().map(|entry| (entry.handle, entry.element))
+    }
+
+    pub fn extend<IT: IntoIterator<Item = T>>(&mut self, iter: IT) {
+        for el in iter {
+            self.push(&el);
+        }
+    }
+}
+
+/// Returns a callback that stores the positions of the moved entries of a `HandleBinaryHeap`.
+fn track<T, B: Storage>(
+    positions: &mut LookupMap<HeapHandle, u64, Identity, Borsh, B>,
+) -> impl FnMut(&HandleEntry<T>, u64) + '_ {
+    move |entry, index| {
+        positions.insert(&entry.handle, &index);
+    }
+}
+
+impl<T, B: Storage> NestedCollection<B> for HandleBinaryHeap<T, B>
+where
+    T: Ord + Clone + BorshSerialize + BorshDeserialize,
+{
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T> Cacheable for HandleBinaryHeap<T> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        let mut prefixes = self.heap.storage_prefixes();
+        prefixes.extend(self.positions.storage_prefixes());
+        prefixes
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use crate::collections::{BinaryHeap, HandleBinaryHeap, HeapHandle, Reverse};
+    use crate::test_utils::test_env;
+    use rand::{Rng, SeedableRng};
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn test_push_pop() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(0);
+        let mut heap = BinaryHeap::new(b"h");
+        let mut baseline = std::collections::BinaryHeap::new();
+        for _ in 0..1000 {
+            if rng.gen::<u64>() % 3 == 0 {
+                assert_eq!(heap.pop(), baseline.pop());
+            } else {
+                let value = rng.gen::<u64>() % 100;
+                heap.push(&value);
+                baseline.push(value);
+            }
+            assert_eq!(heap.peek(), baseline.peek().cloned());
+            assert_eq!(heap.len(), baseline.len() as u64);
+        }
+        assert_eq!(heap.to_sorted_vec(), baseline.into_sorted_vec());
+        heap.clear();
+        assert!(heap.is_empty());
+        assert_eq!(heap.pop(), None);
+    }
+
+    #[test]
+    pub fn test_min_heap() {
+        test_env::setup();
+        let mut heap = BinaryHeap::new(b"h");
+        heap.extend(vec![Reverse(5u64), Reverse(1), Reverse(3), Reverse(1)]);
+        let mut result = vec![];
+        while let Some(Reverse(x)) = heap.pop() {
+            result.push(x);
+        }
+        assert_eq!(result, vec![1, 1, 3, 5]);
+    }
+
+    #[test]
+    pub fn test_handles() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(1);
+        let mut heap = HandleBinaryHeap::new(b"h");
+        let mut baseline: HashMap<HeapHandle, u64> = HashMap::new();
+        let mut handles = vec![];
+        for _ in 0..1000 {
+            let value = rng.gen::<u64>() % 100;
+            match rng.gen::<u64>() % 5 {
+                0 => {
+                    let expected = baseline.iter().map(|(h, v)| (*v, *h)).max();
+                    let popped = heap.pop();
+                    assert_eq!(popped.map(|(h, v)| (v, h)), expected);
+                    if let Some((handle, _)) = popped {
+                        baseline.remove(&handle);
+                    }
+                }
+                1 if !handles.is_empty() => {
+                    let handle = handles[rng.gen::<usize>() % handles.len()];
+                    assert_eq!(heap.update(handle, &value), baseline.get(&handle).cloned());
+                    if let Some(old) = baseline.get_mut(&handle) {
+                        *old = value;
+                    }
+                }
+                2 if !handles.is_empty() => {
+                    let handle = handles[rng.gen::<usize>() % handles.len()];
+                    assert_eq!(heap.remove(handle), baseline.remove(&handle));
+                }
+                _ => {
+                    let handle = heap.push(&value);
+                    handles.push(handle);
+                    baseline.insert(handle, value);
+                }
+            }
+            assert_eq!(heap.len(), baseline.len() as u64);
+        }
+        for handle in handles {
+            assert_eq!(heap.get(handle), baseline.get(&handle).cloned());
+        }
+        assert_eq!(heap.iter().collect::<HashMap<_, _>>(), baseline);
+        heap.clear();
+        assert!(heap.is_empty());
+        assert!(baseline.keys().all(|handle| heap.get(*handle).is_none()));
+    }
+}
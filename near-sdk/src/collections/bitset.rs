@@ -0,0 +1,340 @@
+//! A set of bits implemented on a trie. The bits are packed into chunks of 1024 bits and every
+//! chunk with set bits is stored under its own key, with only its non-zero 64-bit words, so a flag
+//! costs a bit of storage instead of a storage entry. The chunks are kept in a `TreeMap`, so
+//! iteration and `clear_all` skip the chunks without set bits.
+use borsh::{BorshDeserialize, BorshSerialize};
+use std::io::Write;
+
+use crate::collections::cache::Cacheable;
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{Borsh, NestedCollection, TreeMap};
+use crate::IntoStorageKey;
+
+const WORD_BITS: u64 = 64;
+/// The number of words in a chunk, which is stored under a single key.
+const CHUNK_WORDS: usize = 16;
+const CHUNK_BITS: u64 = WORD_BITS * CHUNK_WORDS as u64;
+
+/// A set of bits that stores its content on the trie. Uses a `TreeMap` with the following map:
+/// index / 1024 -> chunk of 1024 bits. Chunks without set bits are not stored.
+///
+/// Every stored chunk costs two entries of the `TreeMap`: the chunk itself, which takes 2 bytes
+/// plus 8 bytes per 64-bit word with set bits, and a node of the tree, which takes 50 bytes. With
+/// the keys and the storage overhead of the entries, a dense bitset costs about two bits of storage
+/// per flag, while a sparse one costs up to two entries per set bit.
+///
+/// The content is stored in the storage `B`, which is the storage of the contract by default. See
+/// `with_storage`.
+///
+/// ```
+/// # use near_sdk::collections::Bitset;
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut claimed = Bitset::new(b"c");
+/// assert!(!claimed.set(1000));
+/// assert!(claimed.set(1000));
+/// claimed.set(3);
+/// assert!(claimed.get(3));
+/// assert_eq!(claimed.count_ones(), 2);
+/// assert_eq!(claimed.iter().collect::<Vec<_>>(), vec![3, 1000]);
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Bitset<B: Storage = EnvStorage> {
+    chunks: TreeMap<u64, Chunk, Borsh, B>,
+    ones: u64,
+}
+
+/// The words of a chunk. Serialized as a mask of the non-zero words followed by these words.
+#[derive(Clone, Copy, Default)]
+pub struct Chunk([u64; CHUNK_WORDS]);
+
+impl Chunk {
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(|word| *word == 0)
+    }
+}
+
+impl BorshSerialize for Chunk {
+    fn serialize<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let mask = self
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(_, word)| **word != 0)
+            .fold(0u16, |mask, (i, _)| mask | 1 << i);
+        mask.serialize(writer)?;
+        for word in self.0.iter().filter(|word| **word != 0) {
+            word.serialize(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl BorshDeserialize for Chunk {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        let mask = u16::deserialize(buf)?;
+        let mut chunk = Chunk::default();
+        for (i, word) in chunk.0.iter_mut().enumerate() {
+            if mask & 1 << i != 0 {
+                *word = u64::deserialize(buf)?;
+            }
+        }
+        Ok(chunk)
+    }
+}
+
+impl Bitset {
+    /// Create new bitset with all bits cleared. Use `prefix` as a unique identifier on the trie.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_storage(prefix, EnvStorage)
+    }
+}
+
+impl<B: Storage> Bitset<B> {
+    /// Create new bitset with all bits cleared that stores its content in `storage`. Use `prefix`
+    /// as a unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self { chunks: TreeMap::with_storage(prefix, storage), ones: 0 }
+    }
+
+    /// Returns the number of set bits.
+    pub fn count_ones(&self) -> u64 {
+        self.ones
+    }
+
+    /// Returns `true` if no bits are set.
+    pub fn is_empty(&self) -> bool {
+        self.ones == 0
+    }
+
+    fn read_chunk(&self, chunk_index: u64) -> Chunk {
+        self.chunks.get(&chunk_index).unwrap_or_default()
+    }
+
+    fn write_chunk(&mut self, chunk_index: u64, chunk: &Chunk) {
+        if chunk.is_empty() {
+            self.chunks.remove(&chunk_index);
+        } else {
+            self.chunks.insert(&chunk_index, chunk);
+        }
+    }
+
+    /// Returns the chunk, the word in the chunk and the mask of the bit at `index`.
+    fn locate(index: u64) -> (u64, usize, u64) {
+        let word = (index % CHUNK_BITS / WORD_BITS) as usize;
+        (index / CHUNK_BITS, word, 1 << (index % WORD_BITS))
+    }
+
+    /// Returns the value of the bit at `index`. Costs a single storage read.
+    pub fn get(&self, index: u64) -> bool {
+        let (chunk_index, word, mask) = Self::locate(index);
+        self.read_chunk(chunk_index).0[word] & mask != 0
+    }
+
+    /// Sets the bit at `index` and returns its previous value. Costs a storage read, and a storage
+    /// read and write if the bit was not set. Setting the first bit of a chunk also inserts the
+    /// chunk into the tree of chunks with set bits, which costs `O(log(N))` reads and writes.
+    pub fn set(&mut self, index: u64) -> bool {
+        let (chunk_index, word, mask) = Self::locate(index);
+        let mut chunk = self.read_chunk(chunk_index);
+        if chunk.0[word] & mask != 0 {
+            return true;
+        }
+        chunk.0[word] |= mask;
+        self.write_chunk(chunk_index, &chunk);
+        self.ones += 1;
+        false
+    }
+
+    /// Clears the bit at `index` and returns its previous value. Costs a storage read, and a
+    /// storage read and write if the bit was set. Clearing the last bit of a chunk also removes the
+    /// chunk from the tree of chunks with set bits, which costs `O(log(N))` reads and writes.
+    pub fn clear(&mut self, index: u64) -> bool {
+        let (chunk_index, word, mask) = Self::locate(index);
+        let mut chunk = self.read_chunk(chunk_index);
+        if chunk.0[word] & mask == 0 {
+            return false;
+        }
+        chunk.0[word] &= !mask;
+        self.write_chunk(chunk_index, &chunk);
+        self.ones -= 1;
+        true
+    }
+
+    /// Clears all bits, removing the bitset from the trie. Costs a storage removal per chunk with
+    /// set bits.
+    pub fn clear_all(&mut self) {
+        self.chunks.clear();
+        self.ones = 0;
+    }
+
+    /// Iterate over the indices of the set bits in ascending order. Reads only the chunks with set
+    /// bits, at `O(log(N))` reads per chunk.
+    pub fn iter(&self) -> BitsetIter<'_, B> {
+        BitsetIter {
+            bitset: self,
+            next_chunk: self.chunks.min(),
+            chunk_index: 0,
+            chunk: Chunk::default(),
+            word: CHUNK_WORDS,
+        }
+    }
+}
+
+impl<B: Storage> std::fmt::Debug for Bitset<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bitset").field("ones", &self.ones).finish()
+    }
+}
+
+/// An iterator over the indices of the set bits of a `Bitset`.
+pub struct BitsetIter<'a, B: Storage = EnvStorage> {
+    bitset: &'a Bitset<B>,
+    /// The index of the next chunk with set bits to read, or `None` if all chunks are read.
+    next_chunk: Option<u64>,
+    /// The index of the last read chunk.
+    chunk_index: u64,
+    /// The bits of the last read chunk that are not yielded yet.
+    chunk: Chunk,
+    /// The position of the first word of the chunk with bits that are not yielded yet.
+    word: usize,
+}
+
+impl<'a, B: Storage> Iterator for BitsetIter<'a, B> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while self.word < CHUNK_WORDS {
+                let bits = &mut self.chunk.0[self.word];
+                if *bits != 0 {
+                    let bit = u64::from(bits.trailing_zeros());
+                    *bits &= *bits - 1;
+                    let word_index = self.chunk_index * CHUNK_WORDS as u64 + self.word as u64;
+                    return Some(word_index * WORD_BITS + bit);
+                }
+                self.word += 1;
+            }
+            self.chunk_index = self.next_chunk?;
+            self.chunk = self.bitset.read_chunk(self.chunk_index);
+            self.word = 0;
+            self.next_chunk = self.bitset.chunks.higher(&self.chunk_index);
+        }
+    }
+}
+
+impl<'a, B: Storage> IntoIterator for &'a Bitset<B> {
+    type Item = u64;
+    type IntoIter = BitsetIter<'a, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<B: Storage> NestedCollection<B> for Bitset<B> {
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear_all();
+    }
+}
+
+impl Cacheable for Bitset {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        self.chunks.storage_prefixes()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use crate::collections::Bitset;
+    use crate::env;
+    use crate::test_utils::test_env;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    #[test]
+    pub fn test_set_clear() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(0);
+        let mut bitset = Bitset::new(b"b");
+        let mut baseline = BTreeSet::new();
+        for _ in 0..3000 {
+            let index = rng.gen::<u64>() % 5000;
+            if rng.gen::<bool>() {
+                assert_eq!(bitset.set(index), !baseline.insert(index));
+            } else {
+                assert_eq!(bitset.clear(index), baseline.remove(&index));
+            }
+            assert_eq!(bitset.count_ones(), baseline.len() as u64);
+        }
+        for index in 0..5200 {
+            assert_eq!(bitset.get(index), baseline.contains(&index));
+        }
+        assert_eq!(bitset.iter().collect::<Vec<_>>(), baseline.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    pub fn test_word_boundaries() {
+        test_env::setup();
+        let mut bitset = Bitset::new(b"b");
+        let indices = vec![0, 63, 64, 127, 128, 1023, 1024, 2047, u64::MAX];
+        for index in &indices {
+            assert!(!bitset.set(*index));
+        }
+        assert_eq!(bitset.iter().collect::<Vec<_>>(), indices);
+        assert!(bitset.get(u64::MAX));
+        assert!(!bitset.get(u64::MAX - 1));
+
+        // Only the non-zero words of a chunk are stored, after the mask of these words.
+        let chunk_key = [&b"b"[..], &b"v"[..], &0u64.to_le_bytes()[..]].concat();
+        assert_eq!(env::storage_read(&chunk_key).map(|chunk| chunk.len()), Some(2 + 4 * 8));
+
+        // Chunks without set bits are removed from the trie.
+        for index in &[63, 64, 127, 128, 1023] {
+            bitset.clear(*index);
+        }
+        assert_eq!(env::storage_read(&chunk_key).map(|chunk| chunk.len()), Some(2 + 8));
+        bitset.clear(0);
+        assert!(!env::storage_has_key(&chunk_key));
+        assert_eq!(bitset.iter().collect::<Vec<_>>(), vec![1024, 2047, u64::MAX]);
+    }
+
+    #[test]
+    pub fn test_sparse() {
+        test_env::setup();
+        let mut bitset = Bitset::new(b"b");
+        bitset.set(u64::MAX);
+        bitset.set(1 << 40);
+        bitset.set(5);
+        assert_eq!(bitset.iter().collect::<Vec<_>>(), vec![5, 1 << 40, u64::MAX]);
+        bitset.clear(5);
+        assert_eq!(bitset.iter().collect::<Vec<_>>(), vec![1 << 40, u64::MAX]);
+        bitset.clear_all();
+        assert!(bitset.is_empty());
+        assert_eq!(bitset.iter().next(), None);
+        assert!(!bitset.get(u64::MAX));
+    }
+
+    #[test]
+    pub fn test_clear_all() {
+        test_env::setup();
+        let mut bitset = Bitset::new(b"b");
+        for index in (0..500).step_by(7) {
+            bitset.set(index);
+        }
+        bitset.clear_all();
+        assert!(bitset.is_empty());
+        assert_eq!(bitset.iter().next(), None);
+        assert!(!bitset.get(7));
+    }
+}
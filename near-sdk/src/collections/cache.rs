@@ -0,0 +1,419 @@
+//! A write-back cache for the storage of the persistent collections.
+//!
+//! All collections access the trie through the functions of this module. Unless a collection is
+//! wrapped into `Cached`, its storage prefixes are not registered in the cache and every call is
+//! forwarded to `env` as is. Keys under a registered prefix are read from the trie at most once per
+//! contract call, and modifications are kept in memory until the `Cached` collection is flushed,
+//! which happens automatically when it is serialized with the rest of the contract state or dropped.
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::env;
+
+/// A collection that can be put behind a write-back cache with `Cached`.
+pub trait Cacheable {
+    /// Storage prefixes that cover all the keys the collection reads and writes.
+    fn storage_prefixes(&self) -> Vec<&[u8]>;
+}
+
+/// A wrapper around a persistent collection that keeps reads and modifications of the collection
+/// in memory, and writes the modified entries to the trie once, when the wrapper is serialized.
+///
+/// Since the contract state is serialized at the end of every method that modifies it, a cached
+/// collection stored in the contract struct does not need to be flushed explicitly. It has the same
+/// serialized representation as the wrapped collection, so an existing field can be switched to a
+/// cached one without migrating the state.
+///
+/// ```
+/// # use near_sdk::borsh::{self, BorshSerialize, BorshDeserialize};
+/// # use near_sdk::near_bindgen;
+/// use near_sdk::collections::{Cached, TreeMap};
+///
+/// #[near_bindgen]
+/// #[derive(BorshDeserialize, BorshSerialize)]
+/// pub struct OrderBook {
+///     bids: Cached<TreeMap<u128, u64>>,
+/// }
+///
+/// impl Default for OrderBook {
+///     fn default() -> Self {
+///         Self { bids: Cached::new(TreeMap::new(b"b")) }
+///     }
+/// }
+/// ```
+///
+/// Modifications are not visible to code that reads the trie directly through `env` until the
+/// collection is flushed with `Cached::flush`, serialized or dropped. Dropping the wrapper, or
+/// unwrapping it with `Cached::into_inner`, also removes its entries from the cache, so the
+/// collection reads and writes the trie directly from then on.
+pub struct Cached<C: Cacheable> {
+    collection: C,
+    registration: Registration,
+}
+
+impl<C: Cacheable> Cached<C> {
+    /// Puts the given collection behind the write-back cache.
+    pub fn new(collection: C) -> Self {
+        let prefixes: Vec<Vec<u8>> =
+            collection.storage_prefixes().into_iter().map(|prefix| prefix.to_vec()).collect();
+        CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            for prefix in &prefixes {
+                *cache.prefixes.entry(prefix.clone()).or_insert(0) += 1;
+            }
+        });
+        Self { collection, registration: Registration { prefixes } }
+    }
+
+    /// Writes all modified entries of the collection to the trie.
+    pub fn flush(&self) {
+        CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            for prefix in self.collection.storage_prefixes() {
+                cache.flush(prefix);
+            }
+        });
+    }
+
+    /// Flushes the collection, removes its entries from the cache and returns it.
+    pub fn into_inner(self) -> C {
+        let Self { collection, registration } = self;
+        drop(registration);
+        collection
+    }
+}
+
+/// The storage prefixes of a `Cached` collection. Flushes and unregisters them when dropped.
+struct Registration {
+    prefixes: Vec<Vec<u8>>,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        // The cache no longer exists if the collection is dropped when the thread exits.
+        let _ = CACHE.try_with(|cache| cache.borrow_mut().unregister(&self.prefixes));
+    }
+}
+
+impl<C: Cacheable> Deref for Cached<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.collection
+    }
+}
+
+impl<C: Cacheable> DerefMut for Cached<C> {
+    fn deref_mut(&mut self) -> &mut C {
+        &mut self.collection
+    }
+}
+
+impl<C> BorshSerialize for Cached<C>
+where
+    C: Cacheable + BorshSerialize,
+{
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.flush();
+        self.collection.serialize(writer)
+    }
+}
+
+impl<C> BorshDeserialize for Cached<C>
+where
+    C: Cacheable + BorshDeserialize,
+{
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        Ok(Self::new(C::deserialize(buf)?))
+    }
+}
+
+impl<C> std::fmt::Debug for Cached<C>
+where
+    C: Cacheable + std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.collection.fmt(f)
+    }
+}
+
+struct CacheEntry {
+    value: Option<Vec<u8>>,
+    dirty: bool,
+}
+
+#[derive(Default)]
+struct StorageCache {
+    /// The registered prefixes and the number of live `Cached` collections that registered each.
+    prefixes: BTreeMap<Vec<u8>, usize>,
+    entries: BTreeMap<Vec<u8>, CacheEntry>,
+    /// The value evicted by the last cached write or removal. `None` if the last write or removal
+    /// went to the trie, in which case the evicted value is returned by `env`.
+    evicted: Option<Option<Vec<u8>>>,
+}
+
+thread_local! {
+    static CACHE: RefCell<StorageCache> = RefCell::new(StorageCache::default());
+}
+
+impl StorageCache {
+    fn is_cached(&self, key: &[u8]) -> bool {
+        self.prefixes.keys().any(|prefix| key.starts_with(prefix))
+    }
+
+    fn entry(&mut self, key: &[u8]) -> &mut CacheEntry {
+        self.entries
+            .entry(key.to_vec())
+            .or_insert_with(|| CacheEntry { value: env::storage_read(key), dirty: false })
+    }
+
+    fn flush(&mut self, prefix: &[u8]) {
+        for (key, entry) in self.entries.range_mut(prefix.to_vec()..) {
+            if !key.starts_with(prefix) {
+                break;
+            }
+            if entry.dirty {
+                match &entry.value {
+                    Some(value) => env::storage_write(key, value),
+                    None => env::storage_remove(key),
+                };
+                entry.dirty = false;
+            }
+        }
+    }
+
+    fn unregister(&mut self, prefixes: &[Vec<u8>]) {
+        for prefix in prefixes {
+            self.flush(prefix);
+            let count = self.prefixes.get_mut(prefix).expect("registered by `Cached::new`");
+            *count -= 1;
+            if *count == 0 {
+                self.prefixes.remove(prefix);
+            }
+        }
+        for prefix in prefixes {
+            let uncached: Vec<Vec<u8>> = self
+                .entries
+                .range(prefix.clone()..)
+                .map(|(key, _)| key)
+                .take_while(|key| key.starts_with(prefix))
+                .filter(|key| !self.is_cached(key))
+                .cloned()
+                .collect();
+            for key in uncached {
+                self.entries.remove(&key);
+            }
+        }
+    }
+
+    fn replace(&mut self, key: &[u8], value: Option<Vec<u8>>) -> bool {
+        let entry = self.entry(key);
+        let evicted = std::mem::replace(&mut entry.value, value);
+        entry.dirty = true;
+        let existed = evicted.is_some();
+        self.evicted = Some(evicted);
+        existed
+    }
+}
+
+/// Writes the modified entries of all cached collections to the trie and forgets every cached
+/// entry, as happens between contract calls. Called when the mocked blockchain is replaced, so
+/// that unit tests do not read values cached before the storage changed.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn reset() {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let prefixes: Vec<Vec<u8>> = cache.prefixes.keys().cloned().collect();
+        for prefix in prefixes {
+            cache.flush(&prefix);
+        }
+        cache.entries.clear();
+        cache.evicted = None;
+    })
+}
+
+/// Same as `env::storage_read`, but goes through the cache.
+pub(crate) fn storage_read(key: &[u8]) -> Option<Vec<u8>> {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.is_cached(key) {
+            cache.entry(key).value.clone()
+        } else {
+            env::storage_read(key)
+        }
+    })
+}
+
+/// Same as `env::storage_has_key`, but goes through the cache.
+pub(crate) fn storage_has_key(key: &[u8]) -> bool {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.is_cached(key) {
+            cache.entry(key).value.is_some()
+        } else {
+            env::storage_has_key(key)
+        }
+    })
+}
+
+/// Same as `env::storage_write`, but goes through the cache.
+pub(crate) fn storage_write(key: &[u8], value: &[u8]) -> bool {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.is_cached(key) {
+            cache.replace(key, Some(value.to_vec()))
+        } else {
+            cache.evicted = None;
+            env::storage_write(key, value)
+        }
+    })
+}
+
+/// Same as `env::storage_remove`, but goes through the cache.
+pub(crate) fn storage_remove(key: &[u8]) -> bool {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.is_cached(key) {
+            cache.replace(key, None)
+        } else {
+            cache.evicted = None;
+            env::storage_remove(key)
+        }
+    })
+}
+
+/// Same as `env::storage_get_evicted`, but goes through the cache.
+pub(crate) fn storage_get_evicted() -> Option<Vec<u8>> {
+    CACHE.with(|cache| match &cache.borrow().evicted {
+        Some(evicted) => evicted.clone(),
+        None => env::storage_get_evicted(),
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use crate::collections::{Cached, LookupMap, TreeMap, UnorderedMap, Vector};
+    use crate::env;
+    use crate::test_utils::test_env;
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use rand::{Rng, SeedableRng};
+    use std::collections::{BTreeMap, HashMap};
+
+    #[test]
+    pub fn test_lookup_map_write_back() {
+        test_env::setup();
+        let mut map = Cached::new(LookupMap::new(b"c"));
+        let storage_key = [&b"c"[..], &1u64.try_to_vec().unwrap()].concat();
+        map.insert(&1u64, &10u64);
+        map.insert(&1u64, &20u64);
+        assert_eq!(map.get(&1u64), Some(20u64));
+        assert!(!env::storage_has_key(&storage_key));
+
+        let state = map.try_to_vec().unwrap();
+        assert_eq!(env::storage_read(&storage_key), Some(20u64.try_to_vec().unwrap()));
+
+        let mut map = Cached::<LookupMap<u64, u64>>::try_from_slice(&state).unwrap();
+        assert_eq!(map.remove(&1u64), Some(20u64));
+        assert!(env::storage_has_key(&storage_key));
+        map.flush();
+        assert!(!env::storage_has_key(&storage_key));
+    }
+
+    #[test]
+    pub fn test_into_inner_and_drop() {
+        test_env::setup();
+        let storage_key = [&b"m"[..], &1u64.try_to_vec().unwrap()].concat();
+        let mut map = Cached::new(LookupMap::new(b"m"));
+        map.insert(&1u64, &10u64);
+        let mut map = map.into_inner();
+        assert_eq!(env::storage_read(&storage_key), Some(10u64.try_to_vec().unwrap()));
+        map.insert(&1u64, &20u64);
+        assert_eq!(env::storage_read(&storage_key), Some(20u64.try_to_vec().unwrap()));
+
+        let mut map = Cached::new(map);
+        map.insert(&1u64, &30u64);
+        drop(map);
+        assert_eq!(env::storage_read(&storage_key), Some(30u64.try_to_vec().unwrap()));
+        let mut map: LookupMap<u64, u64> = LookupMap::new(b"m");
+        map.insert(&1u64, &40u64);
+        assert_eq!(env::storage_read(&storage_key), Some(40u64.try_to_vec().unwrap()));
+    }
+
+    #[test]
+    pub fn test_reset_on_new_blockchain() {
+        test_env::setup();
+        let storage_key = [&b"m"[..], &1u64.try_to_vec().unwrap()].concat();
+        let mut map = Cached::new(LookupMap::new(b"m"));
+        map.insert(&1u64, &10u64);
+        test_env::setup();
+        assert_eq!(env::storage_read(&storage_key), Some(10u64.try_to_vec().unwrap()));
+        env::storage_write(&storage_key, &20u64.try_to_vec().unwrap());
+        assert_eq!(map.get(&1u64), Some(20u64));
+    }
+
+    #[test]
+    pub fn test_vector() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(0);
+        let mut vec = Cached::new(Vector::new(b"c"));
+        let mut baseline = vec![];
+        for _ in 0..100 {
+            let value = rng.gen::<u64>();
+            vec.push(&value);
+            baseline.push(value);
+        }
+        for _ in 0..50 {
+            let index = rng.gen::<u64>() % vec.len();
+            let old_value = vec.swap_remove(index);
+            assert_eq!(old_value, baseline.swap_remove(index as usize));
+        }
+        let vec = vec.into_inner();
+        assert_eq!(vec.to_vec(), baseline);
+    }
+
+    #[test]
+    pub fn test_unordered_map() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(1);
+        let mut map = Cached::new(UnorderedMap::new(b"c"));
+        let mut key_to_value = HashMap::new();
+        for _ in 0..200 {
+            let key = rng.gen::<u64>() % 50;
+            let value = rng.gen::<u64>();
+            if rng.gen::<bool>() {
+                assert_eq!(map.insert(&key, &value), key_to_value.insert(key, value));
+            } else {
+                assert_eq!(map.remove(&key), key_to_value.remove(&key));
+            }
+        }
+        let state = map.try_to_vec().unwrap();
+        let map = UnorderedMap::<u64, u64>::try_from_slice(&state).unwrap();
+        assert_eq!(map.iter().collect::<HashMap<_, _>>(), key_to_value);
+    }
+
+    #[test]
+    pub fn test_tree_map() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(2);
+        let mut map = Cached::new(TreeMap::new(b"c"));
+        let mut baseline = BTreeMap::new();
+        for _ in 0..200 {
+            let key = rng.gen::<u32>() % 100;
+            let value = rng.gen::<u32>();
+            if rng.gen::<bool>() {
+                assert_eq!(map.insert(&key, &value), baseline.insert(key, value));
+            } else {
+                assert_eq!(map.remove(&key), baseline.remove(&key));
+            }
+        }
+        assert_eq!(map.to_vec(), baseline.clone().into_iter().collect::<Vec<_>>());
+
+        let state = map.try_to_vec().unwrap();
+        let map = TreeMap::<u32, u32>::try_from_slice(&state).unwrap();
+        assert_eq!(map.to_vec(), baseline.into_iter().collect::<Vec<_>>());
+    }
+}
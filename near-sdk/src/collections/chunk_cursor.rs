@@ -0,0 +1,56 @@
+//! A position of an operation that processes a large collection in chunks over several calls, e.g.
+//! a migration of the collection to a new type. The cursor is stored in the contract state between
+//! the calls.
+use std::ops::Range;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// A position in an index-addressed collection that is processed in chunks.
+///
+/// The collection must not be modified until it is processed completely, otherwise the elements
+/// can be skipped or processed twice.
+///
+/// ```
+/// # use near_sdk::collections::{ChunkCursor, LegacyTreeMap, TreeMap};
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut legacy: LegacyTreeMap<u64, u64> = LegacyTreeMap::new(b"l");
+/// legacy.insert(&1, &10);
+/// legacy.insert(&2, &20);
+///
+/// let mut map: TreeMap<u64, u64> = TreeMap::new(b"t");
+/// let mut cursor = ChunkCursor::new();
+/// // Each call of `migrate_in_chunks` can happen in a separate transaction.
+/// while !legacy.migrate_in_chunks(&mut map, &mut cursor, 1) {}
+/// assert_eq!(map.to_vec(), vec![(1, 10), (2, 20)]);
+/// while !legacy.clear_in_chunks(1) {}
+/// ```
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChunkCursor {
+    position: u64,
+}
+
+impl ChunkCursor {
+    /// Creates a cursor at the beginning of a collection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of elements processed so far.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns `true` if all the elements of a collection of length `len` have been processed.
+    pub fn is_finished(&self, len: u64) -> bool {
+        self.position >= len
+    }
+
+    /// Returns the indices of the next chunk of at most `max_items` elements of a collection of
+    /// length `len`, and moves the cursor past them.
+    pub(crate) fn next_range(&mut self, len: u64, max_items: u64) -> Range<u64> {
+        let start = self.position.min(len);
+        let end = start.saturating_add(max_items).min(len);
+        self.position = end;
+        start..end
+    }
+}
@@ -0,0 +1,212 @@
+//! Encoding of the values stored by the collections.
+//!
+//! `Vector`, `Deque`, `PackedVector`, `BinaryHeap`, `UnorderedSet`, `LazyOption`, `LookupMap`,
+//! `UnorderedMap`, `TreeMap`, `StableMap` and `InsertionOrderedMap` take a codec type parameter that
+//! converts their values to and from bytes, `Borsh` by default. A codec is a type that implements
+//! `Encoder<T>` to write values and `Decoder<T>` to read them. Map keys and `LookupSet` elements are
+//! always serialized with Borsh, since lookups rely on a canonical encoding. `UnorderedSet` looks
+//! its elements up by their encoding, so its codec has to encode equal elements to the same bytes.
+//!
+//! ```
+//! # use near_sdk::collections::{Decoder, Encoder, Vector};
+//! # near_sdk::test_utils::test_env::setup();
+//! /// Stores `u64` values as 8 little-endian bytes.
+//! pub struct FixedU64;
+//!
+//! impl Encoder<u64> for FixedU64 {
+//!     fn encode(value: &u64) -> std::io::Result<Vec<u8>> {
+//!         Ok(value.to_le_bytes().to_vec())
+//!     }
+//! }
+//!
+//! impl Decoder<u64> for FixedU64 {
+//!     fn decode(raw: &[u8]) -> std::io::Result<u64> {
+//!         let mut bytes = [0u8; 8];
+//!         if raw.len() != bytes.len() {
+//!             return Err(std::io::ErrorKind::InvalidData.into());
+//!         }
+//!         bytes.copy_from_slice(raw);
+//!         Ok(u64::from_le_bytes(bytes))
+//!     }
+//! }
+//!
+//! let mut balances: Vector<u64, FixedU64> = Vector::with_codec(b"b");
+//! balances.push(&100);
+//! assert_eq!(balances.get(0), Some(100));
+//! ```
+use std::io;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// Converts values of type `T` into the bytes stored on the trie.
+pub trait Encoder<T: ?Sized> {
+    fn encode(value: &T) -> io::Result<Vec<u8>>;
+}
+
+/// Converts the bytes stored on the trie back into values of type `T`.
+pub trait Decoder<T> {
+    fn decode(raw: &[u8]) -> io::Result<T>;
+}
+
+/// The default codec, which stores values serialized with Borsh.
+#[derive(Debug)]
+pub struct Borsh;
+
+impl<T> Encoder<T> for Borsh
+where
+    T: BorshSerialize + ?Sized,
+{
+    fn encode(value: &T) -> io::Result<Vec<u8>> {
+        value.try_to_vec()
+    }
+}
+
+impl<T> Decoder<T> for Borsh
+where
+    T: BorshDeserialize,
+{
+    fn decode(raw: &[u8]) -> io::Result<T> {
+        T::try_from_slice(raw)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use crate::collections::{
+        BinaryHeap, Decoder, Deque, Encoder, Identity, InsertionOrderedMap, LazyOption, LookupMap,
+        PackedVector, StableMap, TreeMap, UnorderedMap, UnorderedSet, Vector,
+    };
+    use crate::env;
+    use crate::test_utils::test_env;
+    use rand::{Rng, SeedableRng};
+    use std::collections::HashMap;
+    use std::io;
+
+    /// Stores `u64` values as 8 little-endian bytes.
+    struct FixedU64;
+
+    impl Encoder<u64> for FixedU64 {
+        fn encode(value: &u64) -> io::Result<Vec<u8>> {
+            Ok(value.to_le_bytes().to_vec())
+        }
+    }
+
+    impl Decoder<u64> for FixedU64 {
+        fn decode(raw: &[u8]) -> io::Result<u64> {
+            let mut bytes = [0u8; 8];
+            if raw.len() != bytes.len() {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+            bytes.copy_from_slice(raw);
+            Ok(u64::from_le_bytes(bytes))
+        }
+    }
+
+    /// Stores strings with a version byte, and reads the unversioned legacy encoding as well.
+    struct Versioned;
+
+    impl Encoder<String> for Versioned {
+        fn encode(value: &String) -> io::Result<Vec<u8>> {
+            Ok([&[1u8][..], value.as_bytes()].concat())
+        }
+    }
+
+    impl Decoder<String> for Versioned {
+        fn decode(raw: &[u8]) -> io::Result<String> {
+            let bytes = match raw.split_first() {
+                Some((1, rest)) => rest,
+                _ => raw,
+            };
+            String::from_utf8(bytes.to_vec()).map_err(|_| io::ErrorKind::InvalidData.into())
+        }
+    }
+
+    #[test]
+    pub fn test_custom_codec() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(0);
+        let mut vec: Vector<u64, FixedU64> = Vector::with_codec(b"v");
+        let mut lookup_map: LookupMap<u32, u64, Identity, FixedU64> = LookupMap::with_codec(b"l");
+        let mut unordered_map: UnorderedMap<u32, u64, FixedU64> = UnorderedMap::with_codec(b"u");
+        let mut baseline = HashMap::new();
+        for _ in 0..500 {
+            let key = rng.gen::<u32>() % 50;
+            let value = rng.gen::<u64>();
+            vec.push(&value);
+            let prev = baseline.insert(key, value);
+            assert_eq!(lookup_map.insert(&key, &value), prev);
+            assert_eq!(unordered_map.insert(&key, &value), prev);
+        }
+        for (key, value) in &baseline {
+            assert_eq!(lookup_map.get(key), Some(*value));
+            assert_eq!(unordered_map.get(key), Some(*value));
+        }
+        assert_eq!(unordered_map.iter().collect::<HashMap<_, _>>(), baseline);
+        *lookup_map.entry(1000).or_insert(1) += 1;
+        assert_eq!(lookup_map.get(&1000), Some(2));
+        assert_eq!(vec.len(), 500);
+        assert_eq!(vec.get_raw(0).unwrap(), vec.get(0).unwrap().to_le_bytes().to_vec());
+    }
+
+    #[test]
+    pub fn test_custom_codec_collections() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(1);
+        let mut deque: Deque<u64, FixedU64> = Deque::with_codec(b"d");
+        let mut packed: PackedVector<u64, FixedU64> = PackedVector::with_codec(b"p");
+        let mut heap: BinaryHeap<u64, FixedU64> = BinaryHeap::with_codec(b"h");
+        let mut set: UnorderedSet<u64, FixedU64> = UnorderedSet::with_codec(b"s");
+        let mut tree: TreeMap<u32, u64, FixedU64> = TreeMap::with_codec(b"t");
+        let mut stable: StableMap<u32, u64, FixedU64> = StableMap::with_codec(b"m");
+        let mut ordered: InsertionOrderedMap<u32, u64, FixedU64> =
+            InsertionOrderedMap::with_codec(b"o");
+        let mut baseline = HashMap::new();
+        for _ in 0..500 {
+            let key = rng.gen::<u32>() % 50;
+            let value = rng.gen::<u64>() % 100;
+            deque.push_front(&value);
+            packed.push(&value);
+            heap.push(&value);
+            set.insert(&value);
+            let prev = baseline.insert(key, value);
+            assert_eq!(tree.insert(&key, &value), prev);
+            assert_eq!(stable.insert(&key, &value), prev);
+            assert_eq!(ordered.insert(&key, &value), prev);
+            if rng.gen::<u32>() % 4 == 0 {
+                let prev = baseline.remove(&key);
+                assert_eq!(tree.remove(&key), prev);
+                assert_eq!(stable.remove(&key), prev);
+                assert_eq!(ordered.remove(&key), prev);
+            }
+        }
+        let mut sorted = packed.to_vec();
+        assert_eq!(deque.iter().rev().collect::<Vec<_>>(), sorted);
+        sorted.sort_unstable();
+        assert_eq!(heap.to_sorted_vec(), sorted);
+        sorted.dedup();
+        let mut elements = set.to_vec();
+        elements.sort_unstable();
+        assert_eq!(elements, sorted);
+        assert!(set.contains(&sorted[0]));
+        assert_eq!(tree.iter().collect::<HashMap<_, _>>(), baseline);
+        assert_eq!(stable.iter().collect::<HashMap<_, _>>(), baseline);
+        assert_eq!(ordered.iter().collect::<HashMap<_, _>>(), baseline);
+        stable.compact();
+        ordered.compact();
+        assert_eq!(stable.iter().collect::<HashMap<_, _>>(), baseline);
+        assert_eq!(ordered.iter().collect::<HashMap<_, _>>(), baseline);
+        assert_eq!(deque.get_raw(0).unwrap(), deque.get(0).unwrap().to_le_bytes().to_vec());
+    }
+
+    #[test]
+    pub fn test_versioned_codec() {
+        test_env::setup();
+        env::storage_write(b"o", b"legacy");
+        let mut option: LazyOption<String, Versioned> = LazyOption::with_codec(b"o", None);
+        assert_eq!(option.get(), Some("legacy".to_string()));
+        assert_eq!(option.replace(&"current".to_string()), Some("legacy".to_string()));
+        assert_eq!(env::storage_read(b"o"), Some(b"\x01current".to_vec()));
+        assert_eq!(option.get(), Some("current".to_string()));
+    }
+}
@@ -0,0 +1,101 @@
+//! Reports of the consistency checks of the collections.
+//!
+//! `UnorderedMap`, `UnorderedSet` and `TreeMap` panic with `ERR_INCONSISTENT_STATE` when their
+//! index maps and backing vectors disagree, which can happen after an upgrade that changed the
+//! layout of a collection or a previous execution that left it half-updated. Their
+//! `check_consistency` methods walk the storage of the collection without panicking and return a
+//! `ConsistencyReport` that lists everything found broken. The report serializes to JSON, so it can
+//! be returned from a diagnostic view method.
+use serde::{Deserialize, Serialize};
+use std::mem::size_of;
+
+use crate::collections::storage::Storage;
+
+/// An inconsistency found in the storage of a collection. Indices are the positions in the backing
+/// vector of the collection, and ids are the positions of the nodes of a `TreeMap`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Inconsistency {
+    /// The stored length does not match the number of elements found, e.g. the keys and the values
+    /// of an `UnorderedMap` have different lengths, or some nodes of a `TreeMap` are not reachable
+    /// from its root.
+    LengthMismatch { expected: u64, actual: u64 },
+    /// The key or element at `index` is missing from the trie.
+    MissingKey { index: u64 },
+    /// The value at `index`, or the value of the key of node `index` of a `TreeMap`, is missing from
+    /// the trie.
+    MissingValue { index: u64 },
+    /// The key or element at `index` has no entry in the index map.
+    OrphanedKey { index: u64 },
+    /// The index map entry of the key or element at `index` points to `points_to` instead. It is
+    /// `None` if the entry cannot be decoded as an index.
+    DanglingIndex { index: u64, points_to: Option<u64> },
+    /// Node `id` of a `TreeMap` is linked to but is missing from the trie or cannot be decoded.
+    MissingNode { id: u64 },
+    /// Node `id` of a `TreeMap` is linked to more than once, so the tree has a cycle or a shared
+    /// subtree.
+    DuplicateLink { id: u64 },
+    /// Node `id` of a `TreeMap` stores a different id than its position.
+    WrongId { id: u64, stored: u64 },
+    /// The key of node `id` of a `TreeMap` is out of order with respect to its ancestors.
+    UnorderedKey { id: u64 },
+    /// The heights of the subtrees of node `id` of a `TreeMap` differ by more than one. `balance`
+    /// is the height of the left subtree minus the height of the right one.
+    UnbalancedNode { id: u64, balance: i64 },
+    /// Node `id` of a `TreeMap` stores a wrong height of its subtree.
+    WrongHeight { id: u64, stored: u64, actual: u64 },
+    /// Node `id` of a `TreeMap` stores a wrong number of nodes in its subtree.
+    WrongSize { id: u64, stored: u64, actual: u64 },
+}
+
+/// The result of a consistency check of a collection.
+///
+/// ```
+/// # use near_sdk::collections::UnorderedMap;
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut map: UnorderedMap<u64, u64> = UnorderedMap::new(b"m");
+/// map.insert(&1, &2);
+/// let report = map.check_consistency();
+/// assert!(report.is_consistent());
+/// assert_eq!(report.checked, 1);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    /// The number of elements that were checked.
+    pub checked: u64,
+    /// The inconsistencies found, in the order they were found.
+    pub inconsistencies: Vec<Inconsistency>,
+}
+
+impl ConsistencyReport {
+    /// Returns `true` if no inconsistencies were found.
+    pub fn is_consistent(&self) -> bool {
+        self.inconsistencies.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, inconsistency: Inconsistency) {
+        self.inconsistencies.push(inconsistency);
+    }
+
+    /// Checks that the index map entry under `index_lookup` points to `index`.
+    pub(crate) fn check_index<B: Storage>(&mut self, storage: &B, index_lookup: &[u8], index: u64) {
+        match storage.storage_read(index_lookup) {
+            None => self.push(Inconsistency::OrphanedKey { index }),
+            Some(raw_index) => {
+                let points_to = decode_index(&raw_index);
+                if points_to != Some(index) {
+                    self.push(Inconsistency::DanglingIndex { index, points_to });
+                }
+            }
+        }
+    }
+}
+
+fn decode_index(raw_index: &[u8]) -> Option<u64> {
+    let mut result = [0u8; size_of::<u64>()];
+    if raw_index.len() != result.len() {
+        return None;
+    }
+    result.copy_from_slice(raw_index);
+    Some(u64::from_le_bytes(result))
+}
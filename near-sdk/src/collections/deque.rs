@@ -0,0 +1,477 @@
+//! A double-ended queue implemented on a trie. The elements are stored in a ring of index-addressed
+//! slots, so elements can be pushed and popped at both ends in `O(1)` without moving the others.
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::Range;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::collections::append_slice;
+use crate::collections::cache::Cacheable;
+use crate::collections::codec::{Borsh, Decoder, Encoder};
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::NestedCollection;
+use crate::{env, IntoStorageKey};
+
+const ERR_INCONSISTENT_STATE: &[u8] = b"The collection is an inconsistent state. Did previous smart contract execution terminate unexpectedly?";
+const ERR_ELEMENT_DESERIALIZATION: &[u8] = b"Cannot deserialize element";
+const ERR_ELEMENT_SERIALIZATION: &[u8] = b"Cannot serialize element";
+const ERR_INDEX_OUT_OF_BOUNDS: &[u8] = b"Index out of bounds";
+
+/// An iterable double-ended queue that stores its content on the trie.
+/// Uses the following map: slot -> element, where the slot of the element at `index` is
+/// `head + index`, wrapping around `u64::MAX`.
+///
+/// Elements are encoded with the codec `C`, which is Borsh by default. See `with_codec`. The content
+/// is stored in the storage `B`, which is the storage of the contract by default. See
+/// `with_storage`.
+///
+/// ```
+/// # use near_sdk::collections::Deque;
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut withdrawals: Deque<u128> = Deque::new(b"w");
+/// withdrawals.push_back(&10);
+/// withdrawals.push_back(&20);
+/// withdrawals.push_front(&5);
+/// assert_eq!(withdrawals.pop_front(), Some(5));
+/// assert_eq!(withdrawals.to_vec(), vec![10, 20]);
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Deque<T, C = Borsh, B: Storage = EnvStorage> {
+    head: u64,
+    len: u64,
+    prefix: Vec<u8>,
+    #[borsh_skip]
+    el: PhantomData<(T, C)>,
+    #[borsh_skip]
+    storage: B,
+}
+
+impl<T> Deque<T, Borsh> {
+    /// Create new deque with zero elements. Use `prefix` as a unique identifier on the trie.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec(prefix)
+    }
+}
+
+impl<T, B: Storage> Deque<T, Borsh, B> {
+    /// Create new deque with zero elements that stores its content in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+}
+
+impl<T, C, B: Storage> Deque<T, C, B> {
+    /// Returns the number of elements in the deque.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the deque contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Create new deque with zero elements that encodes its elements with the codec `C`. Use
+    /// `prefix` as a unique identifier on the trie.
+    pub fn with_codec<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, B::default())
+    }
+
+    /// Create new deque with zero elements that encodes its elements with the codec `C` and stores
+    /// them in `storage`. Use `prefix` as a unique identifier in the storage.
+    pub fn with_codec_and_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self { head: 0, len: 0, prefix: prefix.into_storage_key(), el: PhantomData, storage }
+    }
+
+    fn index_to_lookup_key(&self, index: u64) -> Vec<u8> {
+        let slot = self.head.wrapping_add(index);
+        append_slice(&self.prefix, &slot.to_le_bytes()[..])
+    }
+
+    fn read_raw(&self, index: u64) -> Vec<u8> {
+        let lookup_key = self.index_to_lookup_key(index);
+        match self.storage.storage_read(&lookup_key) {
+            Some(raw_element) => raw_element,
+            None => env::panic(ERR_INCONSISTENT_STATE),
+        }
+    }
+
+    fn remove_slot_raw(&mut self, index: u64) -> Vec<u8> {
+        let lookup_key = self.index_to_lookup_key(index);
+        if self.storage.storage_remove(&lookup_key) {
+            match self.storage.storage_get_evicted() {
+                Some(x) => x,
+                None => env::panic(ERR_INCONSISTENT_STATE),
+            }
+        } else {
+            env::panic(ERR_INCONSISTENT_STATE)
+        }
+    }
+
+    /// Returns the serialized element by index from the front or `None` if it is not present.
+    pub fn get_raw(&self, index: u64) -> Option<Vec<u8>> {
+        if index >= self.len {
+            return None;
+        }
+        Some(self.read_raw(index))
+    }
+
+    /// Replaces the element at `index` with a serialized element, returns a serialized evicted
+    /// element.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn replace_raw(&mut self, index: u64, raw_element: &[u8]) -> Vec<u8> {
+        if index >= self.len {
+            env::panic(ERR_INDEX_OUT_OF_BOUNDS)
+        }
+        let lookup_key = self.index_to_lookup_key(index);
+        if self.storage.storage_write(&lookup_key, raw_element) {
+            match self.storage.storage_get_evicted() {
+                Some(x) => x,
+                None => env::panic(ERR_INCONSISTENT_STATE),
+            }
+        } else {
+            env::panic(ERR_INCONSISTENT_STATE)
+        }
+    }
+
+    /// Appends a serialized element to the back of the deque.
+    pub fn push_back_raw(&mut self, raw_element: &[u8]) {
+        let lookup_key = self.index_to_lookup_key(self.len);
+        self.len += 1;
+        self.storage.storage_write(&lookup_key, raw_element);
+    }
+
+    /// Prepends a serialized element to the front of the deque.
+    pub fn push_front_raw(&mut self, raw_element: &[u8]) {
+        self.head = self.head.wrapping_sub(1);
+        self.len += 1;
+        let lookup_key = self.index_to_lookup_key(0);
+        self.storage.storage_write(&lookup_key, raw_element);
+    }
+
+    /// Removes the last element from the deque and returns it without deserializing, or `None` if
+    /// it is empty.
+    pub fn pop_back_raw(&mut self) -> Option<Vec<u8>> {
+        if self.is_empty() {
+            return None;
+        }
+        let raw_element = self.remove_slot_raw(self.len - 1);
+        self.len -= 1;
+        Some(raw_element)
+    }
+
+    /// Removes the first element from the deque and returns it without deserializing, or `None` if
+    /// it is empty.
+    pub fn pop_front_raw(&mut self) -> Option<Vec<u8>> {
+        if self.is_empty() {
+            return None;
+        }
+        let raw_element = self.remove_slot_raw(0);
+        self.head = self.head.wrapping_add(1);
+        self.len -= 1;
+        Some(raw_element)
+    }
+
+    /// Iterate over raw serialized elements from the front to the back.
+    pub fn iter_raw(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        (0..self.len).map(move |i| self.read_raw(i))
+    }
+
+    /// Removes all elements from the deque.
+    pub fn clear(&mut self) {
+        for i in 0..self.len {
+            let lookup_key = self.index_to_lookup_key(i);
+            self.storage.storage_remove(&lookup_key);
+        }
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+impl<T, C, B: Storage> Deque<T, C, B>
+where
+    C: Encoder<T>,
+{
+    fn serialize_element(element: &T) -> Vec<u8> {
+        match C::encode(element) {
+            Ok(x) => x,
+            Err(_) => env::panic(ERR_ELEMENT_SERIALIZATION),
+        }
+    }
+
+    /// Appends an element to the back of the deque.
+    pub fn push_back(&mut self, element: &T) {
+        self.push_back_raw(&Self::serialize_element(element));
+    }
+
+    /// Prepends an element to the front of the deque.
+    pub fn push_front(&mut self, element: &T) {
+        self.push_front_raw(&Self::serialize_element(element));
+    }
+
+    /// Extends the back of the deque from the given collection.
+    pub fn extend<IT: IntoIterator<Item = T>>(&mut self, iter: IT) {
+        for el in iter {
+            self.push_back(&el)
+        }
+    }
+}
+
+impl<T, C, B: Storage> Deque<T, C, B>
+where
+    C: Decoder<T>,
+{
+    fn deserialize_element(storage: &B, raw_element: &[u8]) -> T {
+        match storage.scope(|| C::decode(raw_element)) {
+            Ok(x) => x,
+            Err(_) => env::panic(ERR_ELEMENT_DESERIALIZATION),
+        }
+    }
+
+    /// Returns the element by index from the front or `None` if it is not present.
+    pub fn get(&self, index: u64) -> Option<T> {
+        self.get_raw(index).map(|x| Self::deserialize_element(&self.storage, &x))
+    }
+
+    /// Returns the first element, or `None` if the deque is empty.
+    pub fn front(&self) -> Option<T> {
+        self.get(0)
+    }
+
+    /// Returns the last element, or `None` if the deque is empty.
+    pub fn back(&self) -> Option<T> {
+        self.len.checked_sub(1).and_then(|index| self.get(index))
+    }
+
+    /// Removes the last element from the deque and returns it, or `None` if it is empty.
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.pop_back_raw().map(|x| Self::deserialize_element(&self.storage, &x))
+    }
+
+    /// Removes the first element from the deque and returns it, or `None` if it is empty.
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.pop_front_raw().map(|x| Self::deserialize_element(&self.storage, &x))
+    }
+
+    /// Iterate over deserialized elements from the front to the back.
+    pub fn iter(&self) -> DequeIter<'_, T, C, B> {
+        DequeIter { deque: self, range: 0..self.len }
+    }
+
+    /// Copies elements into an `std::vec::Vec`, from the front to the back.
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+}
+
+impl<T, C, B: Storage> Deque<T, C, B>
+where
+    C: Encoder<T> + Decoder<T>,
+{
+    /// Replaces the element at `index`, returns an evicted element.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn replace(&mut self, index: u64, element: &T) -> T {
+        let raw_element = Self::serialize_element(element);
+        let raw_evicted = self.replace_raw(index, &raw_element);
+        Self::deserialize_element(&self.storage, &raw_evicted)
+    }
+}
+
+/// An iterator over the elements of a `Deque`. Elements are read from the trie only when they are
+/// yielded, so `nth` and `skip` do not read the elements they skip over.
+pub struct DequeIter<'a, T, C = Borsh, B: Storage = EnvStorage> {
+    deque: &'a Deque<T, C, B>,
+    range: Range<u64>,
+}
+
+impl<'a, T, C, B: Storage> Iterator for DequeIter<'a, T, C, B>
+where
+    C: Decoder<T>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.range.next()?;
+        Some(Deque::<T, C, B>::deserialize_element(
+            &self.deque.storage,
+            &self.deque.read_raw(index),
+        ))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        let index = self.range.nth(n)?;
+        Some(Deque::<T, C, B>::deserialize_element(
+            &self.deque.storage,
+            &self.deque.read_raw(index),
+        ))
+    }
+}
+
+impl<'a, T, C, B: Storage> DoubleEndedIterator for DequeIter<'a, T, C, B>
+where
+    C: Decoder<T>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let index = self.range.next_back()?;
+        Some(Deque::<T, C, B>::deserialize_element(
+            &self.deque.storage,
+            &self.deque.read_raw(index),
+        ))
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
+        let index = self.range.nth_back(n)?;
+        Some(Deque::<T, C, B>::deserialize_element(
+            &self.deque.storage,
+            &self.deque.read_raw(index),
+        ))
+    }
+}
+
+impl<'a, T, C, B: Storage> ExactSizeIterator for DequeIter<'a, T, C, B> where C: Decoder<T> {}
+
+impl<'a, T, C, B: Storage> FusedIterator for DequeIter<'a, T, C, B> where C: Decoder<T> {}
+
+impl<'a, T, C, B: Storage> IntoIterator for &'a Deque<T, C, B>
+where
+    C: Decoder<T>,
+{
+    type Item = T;
+    type IntoIter = DequeIter<'a, T, C, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, C, B: Storage> NestedCollection<B> for Deque<T, C, B> {
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, C> Cacheable for Deque<T, C> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        vec![&self.prefix]
+    }
+}
+
+#[cfg(not(feature = "expensive-debug"))]
+impl<T, C, B: Storage> std::fmt::Debug for Deque<T, C, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Deque")
+            .field("head", &self.head)
+            .field("len", &self.len)
+            .field("prefix", &self.prefix)
+            .field("el", &self.el)
+            .finish()
+    }
+}
+
+#[cfg(feature = "expensive-debug")]
+impl<T: std::fmt::Debug, C: Decoder<T>, B: Storage> std::fmt::Debug for Deque<T, C, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_vec().fmt(f)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use std::collections::VecDeque;
+
+    use crate::collections::Deque;
+    use crate::test_utils::test_env;
+
+    #[test]
+    pub fn test_push_pop() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(0);
+        let mut deque = Deque::new(b"d");
+        let mut baseline = VecDeque::new();
+        for _ in 0..1000 {
+            let value = rng.gen::<u64>();
+            match rng.gen::<u64>() % 4 {
+                0 => assert_eq!(deque.pop_front(), baseline.pop_front()),
+                1 => assert_eq!(deque.pop_back(), baseline.pop_back()),
+                2 => {
+                    deque.push_front(&value);
+                    baseline.push_front(value);
+                }
+                _ => {
+                    deque.push_back(&value);
+                    baseline.push_back(value);
+                }
+            }
+            assert_eq!(deque.len(), baseline.len() as u64);
+            assert_eq!(deque.front(), baseline.front().cloned());
+            assert_eq!(deque.back(), baseline.back().cloned());
+        }
+        assert_eq!(deque.to_vec(), baseline.iter().cloned().collect::<Vec<_>>());
+        for (i, value) in baseline.iter().enumerate() {
+            assert_eq!(deque.get(i as u64), Some(*value));
+        }
+        assert_eq!(deque.get(baseline.len() as u64), None);
+    }
+
+    #[test]
+    pub fn test_iter() {
+        test_env::setup();
+        let mut deque = Deque::new(b"d");
+        for i in 0..10u64 {
+            deque.push_front(&i);
+        }
+        let baseline: Vec<u64> = (0..10).rev().collect();
+        assert_eq!(deque.iter().len(), 10);
+        assert_eq!(deque.iter().rev().collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+        assert_eq!(deque.iter().nth(3), Some(baseline[3]));
+        assert_eq!(deque.iter().nth_back(3), Some(baseline[6]));
+        assert_eq!((&deque).into_iter().skip(8).collect::<Vec<_>>(), baseline[8..].to_vec());
+        assert_eq!(deque.iter_raw().count(), 10);
+    }
+
+    #[test]
+    pub fn test_replace_clear() {
+        test_env::setup();
+        let mut deque = Deque::new(b"d");
+        deque.extend(0..5u64);
+        deque.push_front(&100);
+        assert_eq!(deque.replace(0, &200), 100);
+        assert_eq!(deque.replace(5, &300), 4);
+        assert_eq!(deque.to_vec(), vec![200, 0, 1, 2, 3, 300]);
+        deque.clear();
+        assert!(deque.is_empty());
+        assert_eq!(deque.pop_front(), None);
+        deque.push_back(&1);
+        assert_eq!(deque.to_vec(), vec![1]);
+    }
+}
@@ -0,0 +1,297 @@
+//! Entry API for the persistent maps, similar to `std::collections::hash_map::Entry`.
+//!
+//! Unlike the standard entries, the value of an occupied entry is a deserialized copy of the
+//! value stored on the trie. The copy is written back when the entry is dropped, if it was accessed
+//! mutably, so a read-modify-write of a value costs a single read and a single write.
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+use crate::collections::codec::{Borsh, Encoder};
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::NestedCollection;
+use crate::env;
+
+const ERR_VALUE_SERIALIZATION: &[u8] = b"Cannot serialize value";
+
+/// A map that stores serialized values by serialized keys.
+pub(crate) trait RawMap {
+    fn insert_raw(&mut self, key_raw: &[u8], value_raw: &[u8]) -> Option<Vec<u8>>;
+
+    fn remove_raw(&mut self, key_raw: &[u8]) -> Option<Vec<u8>>;
+
+    /// Returns the prefix of the nested collection stored under the serialized key.
+    fn nested_prefix(&self, key_raw: &[u8]) -> Vec<u8>;
+}
+
+/// A view into a single entry in a map, which may either be vacant or occupied.
+///
+/// This enum is constructed from the `entry` method on `LookupMap` and `UnorderedMap`.
+pub enum Entry<'a, K, V, C = Borsh, B = EnvStorage>
+where
+    C: Encoder<V>,
+{
+    /// An occupied entry.
+    Occupied(OccupiedEntry<'a, K, V, C>),
+    /// A vacant entry.
+    Vacant(VacantEntry<'a, K, V, C, B>),
+}
+
+impl<'a, K, V, C, B> Entry<'a, K, V, C, B>
+where
+    C: Encoder<V>,
+{
+    /// Returns a reference to this entry's key.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the default if empty, and returns the occupied
+    /// entry.
+    ///
+    /// # Note
+    ///
+    /// Unlike `std::collections::hash_map::Entry::or_insert`, this returns the occupied entry
+    /// instead of `&mut V`. The entry holds a deserialized copy of the value, which is written to
+    /// the map only when the entry is dropped, either at the end of the statement or of the scope
+    /// the entry is kept in. An entry that is leaked, e.g. with `std::mem::forget`, is never
+    /// written.
+    ///
+    /// ```
+    /// # use near_sdk::collections::LookupMap;
+    /// # near_sdk::test_utils::test_env::setup();
+    /// let mut map: LookupMap<String, u64> = LookupMap::new(b"m");
+    /// *map.entry("a".to_string()).or_insert(0) += 1;
+    /// {
+    ///     let mut count = map.entry("a".to_string()).or_insert(0);
+    ///     *count += 1;
+    ///     // The value is written when `count` goes out of scope.
+    /// }
+    /// assert_eq!(map.get(&"a".to_string()), Some(2));
+    /// ```
+    pub fn or_insert(self, default: V) -> OccupiedEntry<'a, K, V, C> {
+        self.or_insert_with(|| default)
+    }
+
+    /// Ensures a value is in the entry by inserting the result of the default function if empty,
+    /// and returns the occupied entry.
+    ///
+    /// # Note
+    ///
+    /// The value is written to the map when the returned entry is dropped, see `or_insert`.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> OccupiedEntry<'a, K, V, C> {
+        match self {
+            Entry::Occupied(entry) => entry,
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any potential inserts into the
+    /// map.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K, V, C, B> Entry<'a, K, V, C, B>
+where
+    V: Default,
+    C: Encoder<V>,
+{
+    /// Ensures a value is in the entry by inserting the default value if empty, and returns the
+    /// occupied entry.
+    ///
+    /// # Note
+    ///
+    /// The value is written to the map when the returned entry is dropped, see `or_insert`.
+    pub fn or_default(self) -> OccupiedEntry<'a, K, V, C> {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K, V, C, B> Entry<'a, K, V, C, B>
+where
+    V: NestedCollection<B>,
+    C: Encoder<V>,
+    B: Storage,
+{
+    /// Ensures a collection is in the entry by inserting an empty one if empty, and returns the
+    /// occupied entry. The prefix of the inserted collection is derived from the prefix of the map
+    /// and the key, and the collection is stored in the storage of the map.
+    pub fn or_insert_nested(self) -> OccupiedEntry<'a, K, V, C> {
+        match self {
+            Entry::Occupied(entry) => entry,
+            Entry::Vacant(entry) => entry.insert_nested(),
+        }
+    }
+}
+
+/// A view into an occupied entry in a map. It is a part of the `Entry` enum.
+///
+/// Dereferences to the value of the entry. If the value is accessed mutably, it is written back to
+/// the map when the entry is dropped.
+pub struct OccupiedEntry<'a, K, V, C = Borsh>
+where
+    C: Encoder<V>,
+{
+    /// The key and the value. Always `Some`, except when the entry is being removed.
+    pair: Option<(K, V)>,
+    key_raw: Vec<u8>,
+    modified: bool,
+    map: &'a mut dyn RawMap,
+    codec: PhantomData<C>,
+}
+
+impl<'a, K, V, C> OccupiedEntry<'a, K, V, C>
+where
+    C: Encoder<V>,
+{
+    pub(crate) fn new(key: K, key_raw: Vec<u8>, value: V, map: &'a mut dyn RawMap) -> Self {
+        Self { pair: Some((key, value)), key_raw, modified: false, map, codec: PhantomData }
+    }
+
+    /// Gets a reference to the key in the entry.
+    pub fn key(&self) -> &K {
+        &self.pair.as_ref().unwrap().0
+    }
+
+    /// Gets a reference to the value in the entry.
+    pub fn get(&self) -> &V {
+        &self.pair.as_ref().unwrap().1
+    }
+
+    /// Gets a mutable reference to the value in the entry. The value is written back to the map
+    /// when the entry is dropped.
+    pub fn get_mut(&mut self) -> &mut V {
+        self.modified = true;
+        &mut self.pair.as_mut().unwrap().1
+    }
+
+    /// Sets the value of the entry, and returns the entry's old value.
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// Takes the value out of the entry, and removes it from the map.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Takes the key and the value out of the entry, and removes them from the map.
+    pub fn remove_entry(mut self) -> (K, V) {
+        self.map.remove_raw(&self.key_raw);
+        self.pair.take().unwrap()
+    }
+}
+
+impl<'a, K, V, C> OccupiedEntry<'a, K, V, C>
+where
+    C: Encoder<V>,
+{
+    /// Removes the nested collection of the entry from the map, together with its content.
+    pub fn remove_nested<B: Storage>(self)
+    where
+        V: NestedCollection<B>,
+    {
+        self.remove().clear_nested();
+    }
+}
+
+impl<'a, K, V, C> Deref for OccupiedEntry<'a, K, V, C>
+where
+    C: Encoder<V>,
+{
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.get()
+    }
+}
+
+impl<'a, K, V, C> DerefMut for OccupiedEntry<'a, K, V, C>
+where
+    C: Encoder<V>,
+{
+    fn deref_mut(&mut self) -> &mut V {
+        self.get_mut()
+    }
+}
+
+impl<'a, K, V, C> Drop for OccupiedEntry<'a, K, V, C>
+where
+    C: Encoder<V>,
+{
+    fn drop(&mut self) {
+        if !self.modified {
+            return;
+        }
+        if let Some((_, value)) = &self.pair {
+            let value_raw = match C::encode(value) {
+                Ok(x) => x,
+                Err(_) => env::panic(ERR_VALUE_SERIALIZATION),
+            };
+            self.map.insert_raw(&self.key_raw, &value_raw);
+        }
+    }
+}
+
+/// A view into a vacant entry in a map. It is a part of the `Entry` enum.
+pub struct VacantEntry<'a, K, V, C = Borsh, B = EnvStorage> {
+    key: K,
+    key_raw: Vec<u8>,
+    map: &'a mut dyn RawMap,
+    /// The storage of the map, which is the storage of the nested collections it creates.
+    storage: B,
+    el: PhantomData<(V, C)>,
+}
+
+impl<'a, K, V, C, B> VacantEntry<'a, K, V, C, B>
+where
+    C: Encoder<V>,
+{
+    pub(crate) fn new(key: K, key_raw: Vec<u8>, map: &'a mut dyn RawMap, storage: B) -> Self {
+        Self { key, key_raw, map, storage, el: PhantomData }
+    }
+
+    /// Gets a reference to the key that would be used when inserting a value through the entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes ownership of the key.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Sets the value of the entry, and returns the occupied entry. The value is written to the map
+    /// when the returned entry is dropped.
+    pub fn insert(self, value: V) -> OccupiedEntry<'a, K, V, C> {
+        let mut entry = OccupiedEntry::new(self.key, self.key_raw, value, self.map);
+        entry.modified = true;
+        entry
+    }
+}
+
+impl<'a, K, V, C, B> VacantEntry<'a, K, V, C, B>
+where
+    V: NestedCollection<B>,
+    C: Encoder<V>,
+    B: Storage,
+{
+    /// Sets the value of the entry to an empty collection, and returns the occupied entry. The
+    /// prefix of the collection is derived from the prefix of the map and the key, and the
+    /// collection is stored in the storage of the map.
+    pub fn insert_nested(self) -> OccupiedEntry<'a, K, V, C> {
+        let prefix = self.map.nested_prefix(&self.key_raw);
+        let collection = V::new_nested(prefix, self.storage.clone());
+        self.insert(collection)
+    }
+}
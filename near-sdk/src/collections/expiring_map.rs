@@ -0,0 +1,325 @@
+//! A map whose entries expire at a given block timestamp. Expired entries are treated as absent,
+//! and are removed from the trie in bounded batches with `prune`, using an index of the entries
+//! ordered by their expiry.
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::collections::cache::Cacheable;
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{append, Borsh, Identity, LookupMap, NestedCollection, TreeMap};
+use crate::{env, IntoStorageKey};
+
+const ERR_KEY_SERIALIZATION: &[u8] = b"Cannot serialize key with Borsh";
+const ERR_VALUE_SERIALIZATION: &[u8] = b"Cannot serialize value with Borsh";
+const ERR_VALUE_DESERIALIZATION: &[u8] = b"Cannot deserialize value with Borsh";
+
+/// A value stored together with the block timestamp at which it expires.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ExpiringValue<V> {
+    value: V,
+    expires_at: u64,
+}
+
+/// A map whose entries are valid until a block timestamp, in nanoseconds like
+/// `env::block_timestamp()`. An entry is expired once the block timestamp reaches its expiry, after
+/// which it is treated as absent by every method. Expired entries still occupy storage until they
+/// are removed by `prune`, which costs `O(log(N))` per removed entry and can be called from any
+/// method with a bound on the number of entries to remove.
+///
+/// Uses the following maps: key -> (value, expiry), and an ordered index of (expiry, key). The
+/// content is stored in the storage `B`, which is the storage of the contract by default. See
+/// `with_storage`.
+///
+/// ```
+/// # use near_sdk::collections::ExpiringMap;
+/// # near_sdk::test_utils::test_env::setup();
+/// const MINUTE: u64 = 60 * 1_000_000_000;
+/// let mut sessions: ExpiringMap<String, u64> = ExpiringMap::new(b"s");
+/// sessions.insert(&"alice.near".to_string(), &7, 10 * MINUTE);
+/// assert_eq!(sessions.get(&"alice.near".to_string()), Some(7));
+/// // Removes at most 10 expired sessions.
+/// sessions.prune(10);
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ExpiringMap<K, V, B: Storage = EnvStorage> {
+    entries: LookupMap<K, ExpiringValue<V>, Identity, Borsh, B>,
+    expiries: TreeMap<(u64, Vec<u8>), (), Borsh, B>,
+}
+
+impl<K, V> ExpiringMap<K, V>
+where
+    K: BorshSerialize,
+    V: BorshSerialize + BorshDeserialize,
+{
+    /// Create new map with zero entries. Use `prefix` as a unique identifier on the trie.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_storage(prefix, EnvStorage)
+    }
+}
+
+impl<K, V, B: Storage> ExpiringMap<K, V, B>
+where
+    K: BorshSerialize,
+    V: BorshSerialize + BorshDeserialize,
+{
+    /// Create new map with zero entries that stores its content in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        let prefix = prefix.into_storage_key();
+        Self {
+            entries: LookupMap::with_storage(append(&prefix, b'e'), storage.clone()),
+            expiries: TreeMap::with_storage(append(&prefix, b'x'), storage),
+        }
+    }
+
+    fn serialize_key(key: &K) -> Vec<u8> {
+        match key.try_to_vec() {
+            Ok(x) => x,
+            Err(_) => env::panic(ERR_KEY_SERIALIZATION),
+        }
+    }
+
+    fn serialize_entry(value: &V, expires_at: u64) -> Vec<u8> {
+        match value.try_to_vec() {
+            Ok(mut x) => {
+                x.extend_from_slice(&expires_at.to_le_bytes());
+                x
+            }
+            Err(_) => env::panic(ERR_VALUE_SERIALIZATION),
+        }
+    }
+
+    fn deserialize_entry(&self, raw_entry: &[u8]) -> ExpiringValue<V> {
+        match self.entries.storage().scope(|| ExpiringValue::try_from_slice(raw_entry)) {
+            Ok(x) => x,
+            Err(_) => env::panic(ERR_VALUE_DESERIALIZATION),
+        }
+    }
+
+    /// Returns the value and the expiry of an entry that is not expired.
+    fn live(entry: ExpiringValue<V>) -> Option<(V, u64)> {
+        if entry.expires_at > env::block_timestamp() {
+            Some((entry.value, entry.expires_at))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value corresponding to the key, or `None` if the key is absent or expired.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.get_with_expiry(key).map(|(value, _)| value)
+    }
+
+    /// Returns the value corresponding to the key together with its expiry, or `None` if the key is
+    /// absent or expired.
+    pub fn get_with_expiry(&self, key: &K) -> Option<(V, u64)> {
+        self.entries.get(key).and_then(Self::live)
+    }
+
+    /// Returns `true` if the map contains the key and it is not expired.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_with_expiry(key).is_some()
+    }
+
+    /// Returns the expiry of the key, or `None` if the key is absent or expired.
+    pub fn expires_at(&self, key: &K) -> Option<u64> {
+        self.get_with_expiry(key).map(|(_, expires_at)| expires_at)
+    }
+
+    /// Inserts a key-value pair that expires `ttl` nanoseconds after the current block timestamp.
+    /// Returns the previous value if it was not expired.
+    pub fn insert(&mut self, key: &K, value: &V, ttl: u64) -> Option<V> {
+        self.insert_until(key, value, env::block_timestamp().saturating_add(ttl))
+    }
+
+    /// Inserts a key-value pair that expires at the block timestamp `expires_at`. Returns the
+    /// previous value if it was not expired.
+    pub fn insert_until(&mut self, key: &K, value: &V, expires_at: u64) -> Option<V> {
+        let key_raw = Self::serialize_key(key);
+        let prev = self
+            .entries
+            .insert_raw(&key_raw, &Self::serialize_entry(value, expires_at))
+            .map(|raw_entry| self.deserialize_entry(&raw_entry));
+        if let Some(prev) = &prev {
+            self.expiries.remove(&(prev.expires_at, key_raw.clone()));
+        }
+        self.expiries.insert(&(expires_at, key_raw), &());
+        prev.and_then(Self::live).map(|(value, _)| value)
+    }
+
+    /// Changes the expiry of a key that is not expired to the block timestamp `expires_at`.
+    /// Returns `false` if the key is absent or expired.
+    pub fn set_expiry(&mut self, key: &K, expires_at: u64) -> bool {
+        match self.get(key) {
+            Some(value) => {
+                self.insert_until(key, &value, expires_at);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a key from the map, returning its value if it was not expired.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let key_raw = Self::serialize_key(key);
+        let raw_entry = self.entries.remove_raw(&key_raw)?;
+        let entry = self.deserialize_entry(&raw_entry);
+        self.expiries.remove(&(entry.expires_at, key_raw));
+        Self::live(entry).map(|(value, _)| value)
+    }
+
+    /// Returns the earliest expiry among the stored entries, including the expired entries that
+    /// are not pruned yet.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.expiries.min().map(|(expires_at, _)| expires_at)
+    }
+
+    /// Returns the number of stored entries, including the expired entries that are not pruned
+    /// yet.
+    pub fn stored_len(&self) -> u64 {
+        self.expiries.len()
+    }
+
+    /// Removes at most `max_items` expired entries, earliest expiry first, so that the cost of
+    /// pruning stays bounded. Returns the number of removed entries.
+    pub fn prune(&mut self, max_items: u64) -> u64 {
+        let now = env::block_timestamp();
+        let mut removed = 0;
+        while removed < max_items {
+            match self.expiries.min() {
+                Some((expires_at, key_raw)) if expires_at <= now => {
+                    self.expiries.remove(&(expires_at, key_raw.clone()));
+                    self.entries.remove_raw(&key_raw);
+                    removed += 1;
+                }
+                _ => break,
+            }
+        }
+        removed
+    }
+
+    /// Removes all entries, including the ones that are not expired.
+    pub fn clear(&mut self) {
+        for ((_, key_raw), _) in self.expiries.iter() {
+            self.entries.remove_raw(&key_raw);
+        }
+        self.expiries.clear();
+    }
+}
+
+impl<K, V, B: Storage> NestedCollection<B> for ExpiringMap<K, V, B>
+where
+    K: BorshSerialize,
+    V: BorshSerialize + BorshDeserialize,
+{
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear();
+    }
+}
+
+impl<K, V> Cacheable for ExpiringMap<K, V> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        let mut prefixes = self.entries.storage_prefixes();
+        prefixes.extend(self.expiries.storage_prefixes());
+        prefixes
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use crate::collections::ExpiringMap;
+    use crate::test_utils::{test_env, VMContextBuilder};
+    use rand::{Rng, SeedableRng};
+    use std::collections::HashMap;
+
+    fn set_block_timestamp(block_timestamp: u64) {
+        test_env::setup_with_context(
+            VMContextBuilder::new().block_timestamp(block_timestamp).build(),
+        );
+    }
+
+    #[test]
+    pub fn test_expiry() {
+        test_env::setup();
+        set_block_timestamp(100);
+        let mut map = ExpiringMap::new(b"m");
+        assert_eq!(map.insert(&1u64, &10u64, 50), None);
+        assert_eq!(map.insert_until(&2, &20, 200), None);
+        assert_eq!(map.get_with_expiry(&1), Some((10, 150)));
+        assert_eq!(map.next_expiry(), Some(150));
+
+        set_block_timestamp(149);
+        assert_eq!(map.get(&1), Some(10));
+        set_block_timestamp(150);
+        assert_eq!(map.get(&1), None);
+        assert!(!map.contains_key(&1));
+        assert!(!map.set_expiry(&1, 300));
+        // Replacing an expired entry does not return its value.
+        assert_eq!(map.insert(&1, &11, 10), None);
+        assert_eq!(map.insert(&1, &12, 10), Some(11));
+        assert!(map.set_expiry(&2, 155));
+        assert_eq!(map.expires_at(&2), Some(155));
+        assert_eq!(map.next_expiry(), Some(155));
+        assert_eq!(map.stored_len(), 2);
+
+        set_block_timestamp(170);
+        assert_eq!(map.remove(&1), None);
+        assert_eq!(map.stored_len(), 1);
+        assert_eq!(map.get(&2), None);
+        assert_eq!(map.prune(10), 1);
+        assert_eq!(map.stored_len(), 0);
+        assert_eq!(map.next_expiry(), None);
+    }
+
+    #[test]
+    pub fn test_prune() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(2);
+        let mut map = ExpiringMap::new(b"m");
+        let mut baseline = HashMap::new();
+        for now in 0..300 {
+            set_block_timestamp(now);
+            let key = rng.gen::<u64>() % 50;
+            let value = rng.gen::<u64>();
+            let ttl = rng.gen::<u64>() % 40;
+            let prev = baseline.insert(key, (value, now + ttl)).filter(|&(_, at)| at > now);
+            assert_eq!(map.insert(&key, &value, ttl), prev.map(|(value, _)| value));
+            let expired = baseline.values().filter(|&&(_, at)| at <= now).count() as u64;
+            let removed = map.prune(2);
+            assert_eq!(removed, expired.min(2));
+            if removed == expired {
+                baseline.retain(|_, &mut (_, at)| at > now);
+            } else {
+                // Only the earliest expired entries are removed, so the rest have to be tracked
+                // until they are pruned.
+                let mut expiries: Vec<_> = baseline
+                    .iter()
+                    .filter(|(_, &(_, at))| at <= now)
+                    .map(|(&k, &(_, at))| (at, k.to_le_bytes(), k))
+                    .collect();
+                expiries.sort_unstable();
+                for (_, _, key) in expiries.into_iter().take(removed as usize) {
+                    baseline.remove(&key);
+                }
+            }
+            assert_eq!(map.stored_len(), baseline.len() as u64);
+            for key in 0..50 {
+                let expected = baseline.get(&key).filter(|&&(_, at)| at > now).map(|&(v, _)| v);
+                assert_eq!(map.get(&key), expected);
+            }
+        }
+        map.clear();
+        assert_eq!(map.stored_len(), 0);
+        assert_eq!(map.get(&0), None);
+    }
+}
@@ -0,0 +1,507 @@
+//! An iterable map with secondary indices that are kept up to date by the map itself, so that the
+//! records can be looked up by other fields than their key without maintaining a separate map from
+//! every write path.
+use std::marker::PhantomData;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::collections::cache::Cacheable;
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{append, Borsh, MultiMap, UnorderedMap, UnorderedMapIter};
+use crate::{env, IntoStorageKey};
+
+const ERR_INDEX_KEY_SERIALIZATION: &[u8] = b"Cannot serialize index key with Borsh";
+const ERR_DUPLICATE_INDEX_ID: &[u8] = b"Secondary indices of a map must have different IDs";
+
+/// A secondary index of the records of type `V` of an `IndexedMap`. Implemented by marker types
+/// that are listed in the indices of the map.
+pub trait SecondaryIndex<V> {
+    /// The key under which records are looked up in the index.
+    type Key: BorshSerialize;
+
+    /// Identifies the index in storage. Must differ between the indices of a map and must not
+    /// change once the map has records.
+    const ID: u8;
+
+    /// Returns the keys under which the record appears in the index. A record can appear under
+    /// any number of keys, including none.
+    fn index_keys(value: &V) -> Vec<Self::Key>;
+}
+
+/// The secondary indices of an `IndexedMap`, implemented for `()` and for tuples of up to four
+/// `SecondaryIndex` types.
+pub trait SecondaryIndices<K, V> {
+    /// Returns the `ID`s of the indices.
+    fn ids() -> Vec<u8>;
+
+    /// Updates the indices under `prefix` in `storage` for the record of `key` that changed from
+    /// `old` to `new`, where `None` means that the record is absent.
+    fn update<B: Storage>(storage: &B, prefix: &[u8], key: &K, old: Option<&V>, new: Option<&V>);
+}
+
+/// Implemented by a tuple of secondary indices for every index `X` in it, so that an
+/// `IndexedMap` can only be queried by its own indices. `P` is the position of `X` in the tuple,
+/// which is inferred at the call site:
+///
+/// ```compile_fail
+/// # use borsh::{BorshDeserialize, BorshSerialize};
+/// # use near_sdk::collections::{IndexedMap, SecondaryIndex};
+/// # #[derive(BorshSerialize, BorshDeserialize)]
+/// # pub struct Token {
+/// #     owner: String,
+/// # }
+/// pub struct ByOwner;
+///
+/// impl SecondaryIndex<Token> for ByOwner {
+///     type Key = String;
+///     const ID: u8 = 0;
+///     fn index_keys(token: &Token) -> Vec<String> {
+///         vec![token.owner.clone()]
+///     }
+/// }
+///
+/// let tokens: IndexedMap<u64, Token, ()> = IndexedMap::new(b"t");
+/// // `ByOwner` is not an index of `tokens`.
+/// tokens.keys_by::<ByOwner, _>(&"alice.near".to_string());
+/// ```
+pub trait Contains<X, P> {}
+
+/// The position of an index in the tuple of the indices of an `IndexedMap`.
+pub struct At0;
+/// The position of an index in the tuple of the indices of an `IndexedMap`.
+pub struct At1;
+/// The position of an index in the tuple of the indices of an `IndexedMap`.
+pub struct At2;
+/// The position of an index in the tuple of the indices of an `IndexedMap`.
+pub struct At3;
+
+impl<K, V> SecondaryIndices<K, V> for () {
+    fn ids() -> Vec<u8> {
+        vec![]
+    }
+
+    fn update<B: Storage>(
+        _storage: &B,
+        _prefix: &[u8],
+        _key: &K,
+        _old: Option<&V>,
+        _new: Option<&V>,
+    ) {
+    }
+}
+
+macro_rules! impl_contains {
+    ([$($all:ident),+] $index:ident: $position:ident) => {
+        impl<$($all),+> Contains<$index, $position> for ($($all,)+) {}
+    };
+}
+
+macro_rules! impl_secondary_indices {
+    ($all:tt $($index:ident: $position:ident),+) => {
+        impl<K, V, $($index),+> SecondaryIndices<K, V> for ($($index,)+)
+        where
+            K: BorshSerialize + BorshDeserialize,
+            $($index: SecondaryIndex<V>,)+
+        {
+            fn ids() -> Vec<u8> {
+                vec![$($index::ID),+]
+            }
+
+            fn update<S: Storage>(
+                storage: &S,
+                prefix: &[u8],
+                key: &K,
+                old: Option<&V>,
+                new: Option<&V>,
+            ) {
+                $(update_index::<K, V, $index, S>(storage, prefix, key, old, new);)+
+            }
+        }
+
+        $(impl_contains!($all $index: $position);)+
+    };
+}
+
+impl_secondary_indices!([A] A: At0);
+impl_secondary_indices!([A, B] A: At0, B: At1);
+impl_secondary_indices!([A, B, C] A: At0, B: At1, C: At2);
+impl_secondary_indices!([A, B, C, D] A: At0, B: At1, C: At2, D: At3);
+
+fn index_map<K, V, I, B>(storage: &B, prefix: &[u8]) -> MultiMap<I::Key, K, B>
+where
+    K: BorshSerialize + BorshDeserialize,
+    I: SecondaryIndex<V>,
+    B: Storage,
+{
+    MultiMap::with_storage(append(prefix, I::ID), storage.clone())
+}
+
+fn serialized_index_keys<V, I>(value: Option<&V>) -> Vec<(Vec<u8>, I::Key)>
+where
+    I: SecondaryIndex<V>,
+{
+    let keys = value.map_or_else(Vec::new, I::index_keys);
+    keys.into_iter()
+        .map(|key| match key.try_to_vec() {
+            Ok(raw) => (raw, key),
+            Err(_) => env::panic(ERR_INDEX_KEY_SERIALIZATION),
+        })
+        .collect()
+}
+
+/// Removes the record from the index keys it no longer has, and adds it to the new ones, leaving
+/// the unchanged keys untouched.
+fn update_index<K, V, I, B>(storage: &B, prefix: &[u8], key: &K, old: Option<&V>, new: Option<&V>)
+where
+    K: BorshSerialize + BorshDeserialize,
+    I: SecondaryIndex<V>,
+    B: Storage,
+{
+    let old_keys = serialized_index_keys::<V, I>(old);
+    let new_keys = serialized_index_keys::<V, I>(new);
+    let mut index = index_map::<K, V, I, B>(storage, prefix);
+    for (raw, index_key) in &old_keys {
+        if !new_keys.iter().any(|(new_raw, _)| new_raw == raw) {
+            index.remove(index_key, key);
+        }
+    }
+    for (raw, index_key) in &new_keys {
+        if !old_keys.iter().any(|(old_raw, _)| old_raw == raw) {
+            index.insert(index_key, key);
+        }
+    }
+}
+
+/// An iterable map of records with the secondary indices `I`, which is a tuple of
+/// `SecondaryIndex` types. Every `insert` and `remove` updates the indices of the record, so they
+/// cannot drift out of sync with the records.
+///
+/// Uses the following maps: key -> record, and for every index: index key -> set of keys.
+/// Updating a record costs `O(1)` storage operations per changed index key. The content is stored
+/// in the storage `B`, which is the storage of the contract by default. See `with_storage`.
+///
+/// ```
+/// # use borsh::{BorshDeserialize, BorshSerialize};
+/// # use near_sdk::collections::{IndexedMap, SecondaryIndex};
+/// # near_sdk::test_utils::test_env::setup();
+/// #[derive(BorshSerialize, BorshDeserialize)]
+/// pub struct Token {
+///     owner: String,
+///     tags: Vec<String>,
+/// }
+///
+/// pub struct ByOwner;
+///
+/// impl SecondaryIndex<Token> for ByOwner {
+///     type Key = String;
+///     const ID: u8 = 0;
+///     fn index_keys(token: &Token) -> Vec<String> {
+///         vec![token.owner.clone()]
+///     }
+/// }
+///
+/// pub struct ByTag;
+///
+/// impl SecondaryIndex<Token> for ByTag {
+///     type Key = String;
+///     const ID: u8 = 1;
+///     fn index_keys(token: &Token) -> Vec<String> {
+///         token.tags.clone()
+///     }
+/// }
+///
+/// let mut tokens: IndexedMap<u64, Token, (ByOwner, ByTag)> = IndexedMap::new(b"t");
+/// tokens.insert(&1, &Token { owner: "alice.near".to_string(), tags: vec!["art".to_string()] });
+/// tokens.insert(&2, &Token { owner: "bob.near".to_string(), tags: vec!["art".to_string()] });
+/// assert_eq!(tokens.keys_by::<ByOwner, _>(&"alice.near".to_string()), vec![1]);
+/// assert_eq!(tokens.count_by::<ByTag, _>(&"art".to_string()), 2);
+///
+/// // Transfers the token, which moves it to the new owner in the index.
+/// tokens.insert(&1, &Token { owner: "bob.near".to_string(), tags: vec!["art".to_string()] });
+/// assert_eq!(tokens.count_by::<ByOwner, _>(&"alice.near".to_string()), 0);
+/// assert_eq!(tokens.keys_by::<ByOwner, _>(&"bob.near".to_string()), vec![2, 1]);
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct IndexedMap<K, V, I, B: Storage = EnvStorage> {
+    records: UnorderedMap<K, V, Borsh, B>,
+    index_prefix: Vec<u8>,
+    #[borsh_skip]
+    indices: PhantomData<I>,
+}
+
+impl<K, V, I> IndexedMap<K, V, I>
+where
+    K: BorshSerialize + BorshDeserialize,
+    V: BorshSerialize + BorshDeserialize,
+    I: SecondaryIndices<K, V>,
+{
+    /// Create new map with zero records. Use `prefix` as a unique identifier on the trie.
+    ///
+    /// # Panics
+    ///
+    /// Panics if two indices have the same `ID`, since they would share their storage.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_storage(prefix, EnvStorage)
+    }
+}
+
+impl<K, V, I, B: Storage> IndexedMap<K, V, I, B>
+where
+    K: BorshSerialize + BorshDeserialize,
+    V: BorshSerialize + BorshDeserialize,
+    I: SecondaryIndices<K, V>,
+{
+    /// Create new map with zero records that stores its content in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if two indices have the same `ID`, since they would share their storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        let ids = I::ids();
+        if ids.iter().enumerate().any(|(i, id)| ids[..i].contains(id)) {
+            env::panic(ERR_DUPLICATE_INDEX_ID)
+        }
+        let prefix = prefix.into_storage_key();
+        Self {
+            records: UnorderedMap::with_storage(append(&prefix, b'r'), storage),
+            index_prefix: append(&prefix, b'i'),
+            indices: PhantomData,
+        }
+    }
+
+    /// Returns the number of records in the map.
+    pub fn len(&self) -> u64 {
+        self.records.len()
+    }
+
+    /// Returns `true` if the map contains no records.
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Returns the record of the key.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.records.get(key)
+    }
+
+    /// Returns `true` if the map contains a record for the key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.records.get(key).is_some()
+    }
+
+    /// Inserts a record, moving it between the keys of every index whose keys changed. Returns the
+    /// previous record of the key.
+    pub fn insert(&mut self, key: &K, value: &V) -> Option<V> {
+        let prev = self.records.insert(key, value);
+        I::update(self.records.storage(), &self.index_prefix, key, prev.as_ref(), Some(value));
+        prev
+    }
+
+    /// Removes the record of the key from the map and from every index. Returns the removed
+    /// record.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let prev = self.records.remove(key)?;
+        I::update(self.records.storage(), &self.index_prefix, key, Some(&prev), None);
+        Some(prev)
+    }
+
+    /// Removes all records and their index entries. Costs `O(N)` storage operations.
+    pub fn clear(&mut self) {
+        for (key, value) in self.records.iter() {
+            I::update(self.records.storage(), &self.index_prefix, &key, Some(&value), None);
+        }
+        self.records.clear();
+    }
+
+    /// Iterates over all records.
+    pub fn iter(&self) -> UnorderedMapIter<'_, K, V, Borsh, B> {
+        self.records.iter()
+    }
+
+    /// Returns at most `limit` records, starting at `from_index`.
+    pub fn paginate(&self, from_index: u64, limit: u64) -> UnorderedMapIter<'_, K, V, Borsh, B> {
+        self.records.paginate(from_index, limit)
+    }
+
+    /// Returns the number of records under the key of index `X`. `X` has to be one of the indices
+    /// of the map, and `P` is inferred.
+    pub fn count_by<X, P>(&self, index_key: &X::Key) -> u64
+    where
+        X: SecondaryIndex<V>,
+        I: Contains<X, P>,
+    {
+        index_map::<K, V, X, B>(self.records.storage(), &self.index_prefix).count(index_key)
+    }
+
+    /// Returns the keys of the records under the key of index `X`. `X` has to be one of the indices
+    /// of the map, and `P` is inferred.
+    pub fn keys_by<X, P>(&self, index_key: &X::Key) -> Vec<K>
+    where
+        X: SecondaryIndex<V>,
+        I: Contains<X, P>,
+    {
+        index_map::<K, V, X, B>(self.records.storage(), &self.index_prefix).get(index_key)
+    }
+
+    /// Returns the records under the key of index `X`, as pairs of key and record.
+    pub fn get_by<X, P>(&self, index_key: &X::Key) -> Vec<(K, V)>
+    where
+        X: SecondaryIndex<V>,
+        I: Contains<X, P>,
+    {
+        self.with_records(self.keys_by::<X, P>(index_key))
+    }
+
+    /// Returns at most `limit` records under the key of index `X`, starting at `from_index`.
+    pub fn paginate_by<X, P>(&self, index_key: &X::Key, from_index: u64, limit: u64) -> Vec<(K, V)>
+    where
+        X: SecondaryIndex<V>,
+        I: Contains<X, P>,
+    {
+        let keys = index_map::<K, V, X, B>(self.records.storage(), &self.index_prefix)
+            .paginate(index_key, from_index, limit);
+        self.with_records(keys)
+    }
+
+    fn with_records(&self, keys: Vec<K>) -> Vec<(K, V)> {
+        keys.into_iter()
+            .filter_map(|key| {
+                let value = self.records.get(&key)?;
+                Some((key, value))
+            })
+            .collect()
+    }
+}
+
+impl<K, V, I> Cacheable for IndexedMap<K, V, I> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        let mut prefixes = self.records.storage_prefixes();
+        prefixes.push(&self.index_prefix);
+        prefixes
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use crate::collections::{IndexedMap, SecondaryIndex};
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use rand::{Rng, SeedableRng};
+    use std::collections::{BTreeSet, HashMap};
+
+    use crate::test_utils::test_env;
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+    struct Record {
+        owner: u8,
+        tags: Vec<u8>,
+    }
+
+    struct ByOwner;
+
+    impl SecondaryIndex<Record> for ByOwner {
+        type Key = u8;
+        const ID: u8 = 0;
+        fn index_keys(record: &Record) -> Vec<u8> {
+            vec![record.owner]
+        }
+    }
+
+    struct ByTag;
+
+    impl SecondaryIndex<Record> for ByTag {
+        type Key = u8;
+        const ID: u8 = 1;
+        fn index_keys(record: &Record) -> Vec<u8> {
+            record.tags.clone()
+        }
+    }
+
+    fn expected_keys(
+        baseline: &HashMap<u64, Record>,
+        matches: impl Fn(&Record) -> bool,
+    ) -> BTreeSet<u64> {
+        baseline.iter().filter(|(_, record)| matches(record)).map(|(&key, _)| key).collect()
+    }
+
+    #[test]
+    pub fn test_indices() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(4);
+        let mut map: IndexedMap<u64, Record, (ByOwner, ByTag)> = IndexedMap::new(b"m");
+        let mut baseline = HashMap::new();
+        for _ in 0..500 {
+            let key = rng.gen::<u64>() % 30;
+            if rng.gen::<u64>() % 4 == 0 {
+                assert_eq!(map.remove(&key), baseline.remove(&key));
+            } else {
+                let tags = (0..rng.gen::<u8>() % 3).map(|_| rng.gen::<u8>() % 5).collect();
+                let record = Record { owner: rng.gen::<u8>() % 4, tags };
+                assert_eq!(map.insert(&key, &record), baseline.insert(key, record));
+            }
+            assert_eq!(map.len(), baseline.len() as u64);
+            for owner in 0..4 {
+                let expected = expected_keys(&baseline, |r| r.owner == owner);
+                assert_eq!(
+                    map.keys_by::<ByOwner, _>(&owner).into_iter().collect::<BTreeSet<_>>(),
+                    expected
+                );
+                assert_eq!(map.count_by::<ByOwner, _>(&owner), expected.len() as u64);
+            }
+            for tag in 0..5 {
+                let expected = expected_keys(&baseline, |r| r.tags.contains(&tag));
+                let records = map.get_by::<ByTag, _>(&tag);
+                assert_eq!(records.iter().map(|(k, _)| *k).collect::<BTreeSet<_>>(), expected);
+                for (key, record) in records {
+                    assert_eq!(baseline.get(&key), Some(&record));
+                }
+            }
+        }
+        map.clear();
+        assert!(map.is_empty());
+        for owner in 0..4 {
+            assert_eq!(map.count_by::<ByOwner, _>(&owner), 0);
+        }
+        for tag in 0..5 {
+            assert_eq!(map.count_by::<ByTag, _>(&tag), 0);
+        }
+    }
+
+    struct ByFirstTag;
+
+    impl SecondaryIndex<Record> for ByFirstTag {
+        type Key = u8;
+        const ID: u8 = 1;
+        fn index_keys(record: &Record) -> Vec<u8> {
+            record.tags.first().cloned().into_iter().collect()
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Secondary indices of a map must have different IDs")]
+    pub fn test_duplicate_ids() {
+        test_env::setup();
+        let _map: IndexedMap<u64, Record, (ByOwner, ByTag, ByFirstTag)> = IndexedMap::new(b"m");
+    }
+
+    #[test]
+    pub fn test_paginate_by() {
+        test_env::setup();
+        let mut map: IndexedMap<u64, Record, (ByOwner,)> = IndexedMap::new(b"m");
+        for key in 0..10 {
+            map.insert(&key, &Record { owner: (key % 2) as u8, tags: vec![] });
+        }
+        let keys: Vec<u64> =
+            map.paginate_by::<ByOwner, _>(&1, 1, 3).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![3, 5, 7]);
+        assert_eq!(map.iter().count(), 10);
+        assert_eq!(map.paginate(8, 5).map(|(k, _)| k).collect::<Vec<_>>(), vec![8, 9]);
+    }
+}
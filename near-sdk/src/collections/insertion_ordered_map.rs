@@ -0,0 +1,289 @@
+//! An iterable map that iterates over its elements in insertion order, similar to the `IndexMap`
+//! from the `indexmap` crate. Removed elements leave tombstones, so the indices of the remaining
+//! elements are stable until the map is compacted.
+use std::ops::RangeBounds;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::collections::cache::Cacheable;
+use crate::collections::stable_map::Slots;
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{Borsh, Decoder, Encoder, NestedCollection};
+use crate::IntoStorageKey;
+
+/// An iterable map that stores its content on the trie and iterates over it in insertion order.
+/// Replacing the value of an existing key does not change its position.
+///
+/// Every insertion of a new key takes a new slot and removed elements leave tombstones. Maps with
+/// many removals should be compacted with `compact` from time to time.
+///
+/// Values are encoded with the codec `C`, which is Borsh by default. See `with_codec`. Keys are
+/// always serialized with Borsh. The content is stored in the storage `B`, which is the storage of
+/// the contract by default. See `with_storage`.
+///
+/// ```
+/// # use near_sdk::collections::InsertionOrderedMap;
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut map: InsertionOrderedMap<u64, String> = InsertionOrderedMap::new(b"o");
+/// map.insert(&3, &"c".to_string());
+/// map.insert(&1, &"a".to_string());
+/// map.insert(&2, &"b".to_string());
+/// map.remove(&1);
+/// assert_eq!(map.keys().collect::<Vec<_>>(), vec![3, 2]);
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct InsertionOrderedMap<K, V, C = Borsh, B: Storage = EnvStorage> {
+    slots: Slots<K, V, C, B>,
+}
+
+impl<K, V> InsertionOrderedMap<K, V, Borsh> {
+    /// Create new map with zero elements. Use `prefix` as a unique identifier.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec(prefix)
+    }
+}
+
+impl<K, V, B: Storage> InsertionOrderedMap<K, V, Borsh, B> {
+    /// Create new map with zero elements that stores its content in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+}
+
+impl<K, V, C, B: Storage> InsertionOrderedMap<K, V, C, B> {
+    /// Create new map with zero elements that encodes its values with the codec `C`. Use `prefix`
+    /// as a unique identifier.
+    pub fn with_codec<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, B::default())
+    }
+
+    /// Create new map with zero elements that encodes its values with the codec `C` and stores its
+    /// content in `storage`. Use `prefix` as a unique identifier in the storage.
+    pub fn with_codec_and_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self { slots: Slots::new(prefix.into_storage_key(), storage) }
+    }
+
+    /// Returns the number of elements in the map, also referred to as its size.
+    pub fn len(&self) -> u64 {
+        self.slots.len()
+    }
+
+    /// Returns `true` if the map contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.slots.len() == 0
+    }
+
+    /// Returns the number of slots, including the tombstones. All indices are less than the
+    /// number of slots.
+    pub fn slot_count(&self) -> u64 {
+        self.slots.slot_count()
+    }
+}
+
+impl<K, V, C, B: Storage> InsertionOrderedMap<K, V, C, B>
+where
+    K: BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
+{
+    /// Returns the value corresponding to the key.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.slots.get(key)
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.slots.index_of(key).is_some()
+    }
+
+    /// Returns the index of the key, which does not change until the key is removed or the map is
+    /// compacted.
+    pub fn index_of(&self, key: &K) -> Option<u64> {
+        self.slots.index_of(key)
+    }
+
+    /// Returns the key-value pair at the index, or `None` if the slot is a tombstone or the index
+    /// is out of bounds.
+    pub fn get_index(&self, index: u64) -> Option<(K, V)> {
+        self.slots.get_slot(index)
+    }
+
+    /// Inserts a key-value pair into the map. A new key is placed after all other elements.
+    /// If the map did not have this key present, `None` is returned. Otherwise returns
+    /// a value.
+    pub fn insert(&mut self, key: &K, value: &V) -> Option<V> {
+        self.slots.insert(key, value, |_| None)
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key was previously in the
+    /// map. Leaves a tombstone in the slot of the key.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.slots.remove(key).map(|(_, value)| value)
+    }
+
+    /// Clears the map, removing all elements and tombstones.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+    }
+
+    /// Removes all tombstones by moving the elements towards the beginning of the map, preserving
+    /// their order. Changes the indices of all elements after the first tombstone.
+    ///
+    /// Costs a storage read for every slot, and a few storage writes for every moved element.
+    pub fn compact(&mut self) {
+        let mut retained = 0;
+        for index in 0..self.slots.slot_count() {
+            if self.slots.get_slot(index).is_some() {
+                if retained != index {
+                    self.slots.move_slot(index, retained);
+                }
+                retained += 1;
+            }
+        }
+        self.slots.truncate(retained);
+    }
+
+    /// Iterate over deserialized keys and values in insertion order.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (K, V)> + '_ {
+        self.slots.iter()
+    }
+
+    /// Iterate over deserialized keys and values with indices within the given range, skipping
+    /// the tombstones. The range is clamped to the number of slots.
+    pub fn range<'a, R: RangeBounds<u64> + 'a>(
+        &'a self,
+        range: R,
+    ) -> impl DoubleEndedIterator<Item = (K, V)> + 'a {
+        self.slots.range(range)
+    }
+
+    /// Iterate over deserialized keys and values with indices from `from_index` to
+    /// `from_index + limit`, skipping the tombstones. The next page starts at `from_index + limit`.
+    pub fn paginate(
+        &self,
+        from_index: u64,
+        limit: u64,
+    ) -> impl DoubleEndedIterator<Item = (K, V)> + '_ {
+        self.slots.range(from_index..from_index.saturating_add(limit))
+    }
+
+    /// An iterator visiting all keys in insertion order. The iterator element type is `K`.
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = K> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// An iterator visiting all values in insertion order. The iterator element type is `V`.
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = V> + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Copies elements into an `std::vec::Vec`.
+    pub fn to_vec(&self) -> Vec<(K, V)> {
+        self.iter().collect()
+    }
+
+    pub fn extend<IT: IntoIterator<Item = (K, V)>>(&mut self, iter: IT) {
+        for (el_key, el_value) in iter {
+            self.insert(&el_key, &el_value);
+        }
+    }
+}
+
+impl<K, V, C, B: Storage> NestedCollection<B> for InsertionOrderedMap<K, V, C, B>
+where
+    K: BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
+{
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear();
+    }
+}
+
+impl<K, V, C> Cacheable for InsertionOrderedMap<K, V, C> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        self.slots.storage_prefixes()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use crate::collections::InsertionOrderedMap;
+    use crate::test_utils::test_env;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    pub fn test_insertion_order() {
+        test_env::setup();
+        let mut map = InsertionOrderedMap::new(b"m");
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(0);
+        let mut baseline: Vec<(u64, u64)> = vec![];
+        for i in 0..500 {
+            let key = rng.gen::<u64>() % 100;
+            let value = rng.gen::<u64>();
+            let position = baseline.iter().position(|(k, _)| *k == key);
+            if rng.gen::<bool>() {
+                let old_value = match position {
+                    Some(position) => Some(std::mem::replace(&mut baseline[position].1, value)),
+                    None => {
+                        baseline.push((key, value));
+                        None
+                    }
+                };
+                assert_eq!(map.insert(&key, &value), old_value);
+            } else {
+                assert_eq!(map.remove(&key), position.map(|p| baseline.remove(p).1));
+            }
+            if i % 100 == 0 {
+                map.compact();
+                assert_eq!(map.slot_count(), map.len());
+            }
+            assert_eq!(map.len(), baseline.len() as u64);
+        }
+        assert_eq!(map.to_vec(), baseline);
+        assert_eq!(
+            map.iter().rev().collect::<Vec<_>>(),
+            baseline.iter().rev().cloned().collect::<Vec<_>>()
+        );
+        map.compact();
+        assert_eq!(map.to_vec(), baseline);
+        for (index, (key, value)) in baseline.iter().enumerate() {
+            assert_eq!(map.index_of(key), Some(index as u64));
+            assert_eq!(map.get(key), Some(*value));
+        }
+    }
+
+    #[test]
+    pub fn test_paginate_during_removal() {
+        test_env::setup();
+        let mut map = InsertionOrderedMap::new(b"m");
+        map.extend((0..20u64).map(|x| (x, x)));
+        let mut seen = vec![];
+        let mut from_index = 0;
+        while from_index < map.slot_count() {
+            seen.extend(map.paginate(from_index, 5).map(|(key, _)| key));
+            from_index += 5;
+            // Remove an element that was already seen and one that was not.
+            map.remove(&(from_index - 3));
+            map.remove(&(from_index + 1));
+        }
+        let expected: Vec<u64> = (0..20).filter(|x| x % 5 != 1 || *x < 5).collect();
+        assert_eq!(seen, expected);
+    }
+}
@@ -0,0 +1,72 @@
+//! Hash algorithms, and conversion of serialized keys into storage keys for `LookupMap` and
+//! `LookupSet`.
+use crate::collections::append_slice;
+
+/// Converts a serialized key into the storage key under the prefix of a collection.
+pub trait ToKey {
+    fn to_key(prefix: &[u8], key_raw: &[u8]) -> Vec<u8>;
+}
+
+/// A hash algorithm with 32-byte digests, used to hash keys and to build a `MerkleTree`.
+///
+/// In a contract `Sha256` and `Keccak256` compute hashes through `env`. On other targets they use the
+/// `sha2` and `sha3` crates, so off-chain code can verify proofs without a mocked blockchain.
+pub trait HashAlgorithm {
+    fn hash(value: &[u8]) -> Vec<u8>;
+}
+
+/// Uses the serialized key as is, so storage keys are as long as the serialized keys. This is the
+/// default for `LookupMap` and `LookupSet`.
+pub struct Identity;
+
+impl ToKey for Identity {
+    fn to_key(prefix: &[u8], key_raw: &[u8]) -> Vec<u8> {
+        append_slice(prefix, key_raw)
+    }
+}
+
+/// Hashes the serialized key with SHA-256, so storage keys have a fixed size of 32 bytes after
+/// the prefix.
+pub struct Sha256;
+
+impl ToKey for Sha256 {
+    fn to_key(prefix: &[u8], key_raw: &[u8]) -> Vec<u8> {
+        append_slice(prefix, &Self::hash(key_raw))
+    }
+}
+
+impl HashAlgorithm for Sha256 {
+    #[cfg(target_arch = "wasm32")]
+    fn hash(value: &[u8]) -> Vec<u8> {
+        crate::env::sha256(value)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn hash(value: &[u8]) -> Vec<u8> {
+        use sha2::Digest;
+        sha2::Sha256::digest(value).to_vec()
+    }
+}
+
+/// Hashes the serialized key with Keccak-256, so storage keys have a fixed size of 32 bytes
+/// after the prefix.
+pub struct Keccak256;
+
+impl ToKey for Keccak256 {
+    fn to_key(prefix: &[u8], key_raw: &[u8]) -> Vec<u8> {
+        append_slice(prefix, &Self::hash(key_raw))
+    }
+}
+
+impl HashAlgorithm for Keccak256 {
+    #[cfg(target_arch = "wasm32")]
+    fn hash(value: &[u8]) -> Vec<u8> {
+        crate::env::keccak256(value)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn hash(value: &[u8]) -> Vec<u8> {
+        use sha3::Digest;
+        sha3::Keccak256::digest(value).to_vec()
+    }
+}
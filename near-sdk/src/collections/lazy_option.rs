@@ -7,24 +7,34 @@ use std::marker::PhantomData;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 
+use crate::collections::cache::Cacheable;
+use crate::collections::codec::{Borsh, Decoder, Encoder};
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::NestedCollection;
 use crate::env;
 use crate::IntoStorageKey;
 
-const ERR_VALUE_SERIALIZATION: &[u8] = b"Cannot serialize value with Borsh";
-const ERR_VALUE_DESERIALIZATION: &[u8] = b"Cannot deserialize value with Borsh";
+const ERR_VALUE_SERIALIZATION: &[u8] = b"Cannot serialize value";
+const ERR_VALUE_DESERIALIZATION: &[u8] = b"Cannot deserialize value";
 
 /// An persistent lazy option, that stores a value in the storage.
+///
+/// The value is encoded with the codec `C`, which is Borsh by default. See `with_codec`. The value
+/// is stored in the storage `B`, which is the storage of the contract by default. See
+/// `with_storage`.
 #[derive(BorshSerialize, BorshDeserialize)]
-pub struct LazyOption<T> {
+pub struct LazyOption<T, C = Borsh, B: Storage = EnvStorage> {
     storage_key: Vec<u8>,
     #[borsh_skip]
-    el: PhantomData<T>,
+    el: PhantomData<(T, C)>,
+    #[borsh_skip]
+    storage: B,
 }
 
-impl<T> LazyOption<T> {
+impl<T, C, B: Storage> LazyOption<T, C, B> {
     /// Returns `true` if the value is present in the storage.
     pub fn is_some(&self) -> bool {
-        env::storage_has_key(&self.storage_key)
+        self.storage.storage_has_key(&self.storage_key)
     }
 
     /// Returns `true` if the value is not present in the storage.
@@ -34,38 +44,38 @@ impl<T> LazyOption<T> {
 
     /// Reads the raw value from the storage
     fn get_raw(&self) -> Option<Vec<u8>> {
-        env::storage_read(&self.storage_key)
+        self.storage.storage_read(&self.storage_key)
     }
 
     /// Removes the value from the storage.
     /// Returns true if the element was present.
     fn remove_raw(&mut self) -> bool {
-        env::storage_remove(&self.storage_key)
+        self.storage.storage_remove(&self.storage_key)
     }
 
     /// Removes the raw value from the storage and returns it as an option.
     fn take_raw(&mut self) -> Option<Vec<u8>> {
         if self.remove_raw() {
-            Some(env::storage_get_evicted().unwrap())
+            Some(self.storage.storage_get_evicted().unwrap())
         } else {
             None
         }
     }
 
     fn set_raw(&mut self, raw_value: &[u8]) -> bool {
-        env::storage_write(&self.storage_key, &raw_value)
+        self.storage.storage_write(&self.storage_key, &raw_value)
     }
 
     fn replace_raw(&mut self, raw_value: &[u8]) -> Option<Vec<u8>> {
         if self.set_raw(raw_value) {
-            Some(env::storage_get_evicted().unwrap())
+            Some(self.storage.storage_get_evicted().unwrap())
         } else {
             None
         }
     }
 }
 
-impl<T> LazyOption<T>
+impl<T> LazyOption<T, Borsh>
 where
     T: BorshSerialize + BorshDeserialize,
 {
@@ -74,7 +84,45 @@ where
     where
         S: IntoStorageKey,
     {
-        let mut this = Self { storage_key: storage_key.into_storage_key(), el: PhantomData };
+        Self::with_codec(storage_key, value)
+    }
+}
+
+impl<T, B: Storage> LazyOption<T, Borsh, B>
+where
+    T: BorshSerialize + BorshDeserialize,
+{
+    /// Create a new lazy option with the given `storage_key` and the initial value, that stores the
+    /// value in `storage`.
+    pub fn with_storage<S>(storage_key: S, value: Option<&T>, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(storage_key, value, storage)
+    }
+}
+
+impl<T, C, B: Storage> LazyOption<T, C, B>
+where
+    C: Encoder<T> + Decoder<T>,
+{
+    /// Create a new lazy option with the given `storage_key` and the initial value, that encodes
+    /// the value with the codec `C`.
+    pub fn with_codec<S>(storage_key: S, value: Option<&T>) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(storage_key, value, B::default())
+    }
+
+    /// Create a new lazy option with the given `storage_key` and the initial value, that encodes
+    /// the value with the codec `C` and stores it in `storage`.
+    pub fn with_codec_and_storage<S>(storage_key: S, value: Option<&T>, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        let mut this =
+            Self { storage_key: storage_key.into_storage_key(), el: PhantomData, storage };
         if let Some(value) = value {
             this.set(&value);
         }
@@ -82,14 +130,14 @@ where
     }
 
     fn serialize_value(value: &T) -> Vec<u8> {
-        match value.try_to_vec() {
+        match C::encode(value) {
             Ok(x) => x,
             Err(_) => env::panic(ERR_VALUE_SERIALIZATION),
         }
     }
 
-    fn deserialize_value(raw_value: &[u8]) -> T {
-        match T::try_from_slice(&raw_value) {
+    fn deserialize_value(&self, raw_value: &[u8]) -> T {
+        match self.storage.scope(|| C::decode(raw_value)) {
             Ok(x) => x,
             Err(_) => env::panic(ERR_VALUE_DESERIALIZATION),
         }
@@ -103,12 +151,12 @@ where
 
     /// Removes the value from storage and returns it as an option.
     pub fn take(&mut self) -> Option<T> {
-        self.take_raw().map(|v| Self::deserialize_value(&v))
+        self.take_raw().map(|v| self.deserialize_value(&v))
     }
 
     /// Gets the value from storage and returns it as an option.
     pub fn get(&self) -> Option<T> {
-        self.get_raw().map(|v| Self::deserialize_value(&v))
+        self.get_raw().map(|v| self.deserialize_value(&v))
     }
 
     /// Sets the value into the storage without reading the previous value and returns whether the
@@ -119,7 +167,26 @@ where
 
     /// Replaces the value in the storage and returns the previous value as an option.
     pub fn replace(&mut self, value: &T) -> Option<T> {
-        self.replace_raw(&Self::serialize_value(value)).map(|v| Self::deserialize_value(&v))
+        self.replace_raw(&Self::serialize_value(value)).map(|v| self.deserialize_value(&v))
+    }
+}
+
+impl<T, C, B: Storage> NestedCollection<B> for LazyOption<T, C, B>
+where
+    C: Encoder<T> + Decoder<T>,
+{
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_codec_and_storage(prefix, None, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.remove();
+    }
+}
+
+impl<T, C> Cacheable for LazyOption<T, C> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        vec![&self.storage_key]
     }
 }
 
@@ -4,8 +4,9 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use std::ops::Bound;
 
+use crate::collections::cache::Cacheable;
 use crate::collections::UnorderedMap;
-use crate::collections::{append, Vector};
+use crate::collections::{append, ChunkCursor, TreeMap, Vector};
 use crate::IntoStorageKey;
 
 /// TreeMap based on AVL-tree
@@ -69,6 +70,38 @@ where
         self.tree.clear();
     }
 
+    /// Removes at most `max_items` entries from the map, so that a large map can be cleared over
+    /// several calls without running out of gas. Returns `true` if the map is empty.
+    ///
+    /// The nodes are removed without rebalancing the tree, so until this method returns `true`
+    /// the map can only be cleared further.
+    pub fn clear_in_chunks(&mut self, max_items: u64) -> bool {
+        self.val.clear_in_chunks(max_items);
+        if self.tree.clear_in_chunks(max_items) {
+            self.root = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Copies at most `max_items` entries starting from the position of the `cursor` into `target`,
+    /// and moves the cursor past them. Returns `true` once all the entries are copied, after which
+    /// the legacy map can be removed with `clear_in_chunks`.
+    ///
+    /// The legacy map must not be modified until the migration is finished.
+    pub fn migrate_in_chunks(
+        &self,
+        target: &mut TreeMap<K, V>,
+        cursor: &mut ChunkCursor,
+        max_items: u64,
+    ) -> bool {
+        for (key, value) in self.val.next_chunk(cursor, max_items) {
+            target.insert(&key, &value);
+        }
+        cursor.is_finished(self.len())
+    }
+
     fn node(&self, id: u64) -> Option<Node<K>> {
         self.tree.get(id)
     }
@@ -591,6 +624,14 @@ where
     }
 }
 
+impl<K, V> Cacheable for LegacyTreeMap<K, V> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        let mut prefixes = self.val.storage_prefixes();
+        prefixes.extend(self.tree.storage_prefixes());
+        prefixes
+    }
+}
+
 impl<'a, K, V> IntoIterator for &'a LegacyTreeMap<K, V>
 where
     K: Ord + Clone + BorshSerialize + BorshDeserialize,
@@ -1771,4 +1812,31 @@ mod tests {
 
         QuickCheck::new().tests(300).quickcheck(prop as Prop);
     }
+
+    #[test]
+    fn test_migrate_in_chunks() {
+        test_env::setup();
+
+        let mut legacy: LegacyTreeMap<u32, u32> = LegacyTreeMap::new(next_trie_id());
+        let mut map: TreeMap<u32, u32> = TreeMap::new(next_trie_id());
+        for k in random(100) {
+            legacy.insert(&k, &(k + 1));
+        }
+        let expected = legacy.to_vec();
+
+        let mut cursor = ChunkCursor::new();
+        let mut calls = 0;
+        while !legacy.migrate_in_chunks(&mut map, &mut cursor, 7) {
+            calls += 1;
+        }
+        assert_eq!(calls, (legacy.len() - 1) / 7);
+        assert_eq!(map.to_vec(), expected);
+
+        while !legacy.clear_in_chunks(7) {}
+        assert_eq!(legacy.len(), 0);
+        for (k, _) in &expected {
+            assert!(!legacy.contains_key(k));
+        }
+        assert_eq!(map.to_vec(), expected);
+    }
 }
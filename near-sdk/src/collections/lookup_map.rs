@@ -5,44 +5,118 @@ use std::marker::PhantomData;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 
-use crate::collections::append_slice;
+use crate::collections::cache::Cacheable;
+use crate::collections::codec::{Borsh, Decoder, Encoder};
+use crate::collections::entry::{Entry, OccupiedEntry, RawMap, VacantEntry};
+use crate::collections::key::{Identity, ToKey};
+use crate::collections::nested::{nested_prefix, NestedCollection};
+use crate::collections::storage::{EnvStorage, Storage};
 use crate::{env, IntoStorageKey};
 
 const ERR_KEY_SERIALIZATION: &[u8] = b"Cannot serialize key with Borsh";
-const ERR_VALUE_DESERIALIZATION: &[u8] = b"Cannot deserialize value with Borsh";
-const ERR_VALUE_SERIALIZATION: &[u8] = b"Cannot serialize value with Borsh";
+const ERR_VALUE_DESERIALIZATION: &[u8] = b"Cannot deserialize value";
+const ERR_VALUE_SERIALIZATION: &[u8] = b"Cannot serialize value";
 
 /// An non-iterable implementation of a map that stores its content directly on the trie.
+///
+/// The storage key of an entry is the prefix followed by the serialized key. Use `with_hasher` to
+/// create a map that hashes the serialized keys with `Sha256` or `Keccak256` instead, which makes
+/// storage keys short and of a fixed size. Values are encoded with the codec `C`, which is Borsh
+/// by default. See `with_codec`. The content is stored in the storage `B`, which is the storage of
+/// the contract by default. See `with_storage`.
+///
+/// ```
+/// # use near_sdk::collections::{LookupMap, Sha256};
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut profiles: LookupMap<String, String, Sha256> = LookupMap::with_hasher(b"p");
+/// profiles.insert(&"a very long account id.near".to_string(), &"Alice".to_string());
+/// assert_eq!(profiles.get(&"a very long account id.near".to_string()), Some("Alice".to_string()));
+/// ```
 #[derive(BorshSerialize, BorshDeserialize)]
-pub struct LookupMap<K, V> {
+pub struct LookupMap<K, V, H = Identity, C = Borsh, B: Storage = EnvStorage> {
     key_prefix: Vec<u8>,
     #[borsh_skip]
-    el: PhantomData<(K, V)>,
+    el: PhantomData<(K, V, H, C)>,
+    #[borsh_skip]
+    storage: B,
 }
 
-impl<K, V> LookupMap<K, V> {
+impl<K, V> LookupMap<K, V, Identity, Borsh> {
     /// Create a new map. Use `key_prefix` as a unique prefix for keys.
     pub fn new<S>(key_prefix: S) -> Self
     where
         S: IntoStorageKey,
     {
-        Self { key_prefix: key_prefix.into_storage_key(), el: PhantomData }
+        Self::with_hasher(key_prefix)
+    }
+}
+
+impl<K, V, B: Storage> LookupMap<K, V, Identity, Borsh, B> {
+    /// Create a new map that stores its content in `storage`. Use `key_prefix` as a unique prefix
+    /// for keys.
+    pub fn with_storage<S>(key_prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(key_prefix, storage)
+    }
+}
+
+impl<K, V, H> LookupMap<K, V, H, Borsh>
+where
+    H: ToKey,
+{
+    /// Create a new map that converts serialized keys into storage keys with `H`. Use `key_prefix`
+    /// as a unique prefix for keys.
+    pub fn with_hasher<S>(key_prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec(key_prefix)
+    }
+}
+
+impl<K, V, H, C, B: Storage> LookupMap<K, V, H, C, B>
+where
+    H: ToKey,
+{
+    /// Create a new map that converts serialized keys into storage keys with `H` and encodes values
+    /// with the codec `C`. Use `key_prefix` as a unique prefix for keys.
+    pub fn with_codec<S>(key_prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(key_prefix, B::default())
+    }
+
+    /// Create a new map that converts serialized keys into storage keys with `H`, encodes values
+    /// with the codec `C` and stores its content in `storage`. Use `key_prefix` as a unique prefix
+    /// for keys.
+    pub fn with_codec_and_storage<S>(key_prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self { key_prefix: key_prefix.into_storage_key(), el: PhantomData, storage }
+    }
+
+    pub(crate) fn storage(&self) -> &B {
+        &self.storage
     }
 
     fn raw_key_to_storage_key(&self, raw_key: &[u8]) -> Vec<u8> {
-        append_slice(&self.key_prefix, raw_key)
+        H::to_key(&self.key_prefix, raw_key)
     }
 
     /// Returns `true` if the serialized key is present in the map.
     fn contains_key_raw(&self, key_raw: &[u8]) -> bool {
         let storage_key = self.raw_key_to_storage_key(key_raw);
-        env::storage_has_key(&storage_key)
+        self.storage.storage_has_key(&storage_key)
     }
 
     /// Returns the serialized value corresponding to the serialized key.
     fn get_raw(&self, key_raw: &[u8]) -> Option<Vec<u8>> {
         let storage_key = self.raw_key_to_storage_key(key_raw);
-        env::storage_read(&storage_key)
+        self.storage.storage_read(&storage_key)
     }
 
     /// Inserts a serialized key-value pair into the map.
@@ -51,8 +125,8 @@ impl<K, V> LookupMap<K, V> {
     /// the implementation.
     pub fn insert_raw(&mut self, key_raw: &[u8], value_raw: &[u8]) -> Option<Vec<u8>> {
         let storage_key = self.raw_key_to_storage_key(key_raw);
-        if env::storage_write(&storage_key, value_raw) {
-            Some(env::storage_get_evicted().unwrap())
+        if self.storage.storage_write(&storage_key, value_raw) {
+            Some(self.storage.storage_get_evicted().unwrap())
         } else {
             None
         }
@@ -62,18 +136,19 @@ impl<K, V> LookupMap<K, V> {
     /// was previously in the map.
     pub fn remove_raw(&mut self, key_raw: &[u8]) -> Option<Vec<u8>> {
         let storage_key = self.raw_key_to_storage_key(key_raw);
-        if env::storage_remove(&storage_key) {
-            Some(env::storage_get_evicted().unwrap())
+        if self.storage.storage_remove(&storage_key) {
+            Some(self.storage.storage_get_evicted().unwrap())
         } else {
             None
         }
     }
 }
 
-impl<K, V> LookupMap<K, V>
+impl<K, V, H, C, B: Storage> LookupMap<K, V, H, C, B>
 where
     K: BorshSerialize,
-    V: BorshSerialize + BorshDeserialize,
+    H: ToKey,
+    C: Encoder<V> + Decoder<V>,
 {
     fn serialize_key(key: &K) -> Vec<u8> {
         match key.try_to_vec() {
@@ -82,15 +157,15 @@ where
         }
     }
 
-    fn deserialize_value(raw_value: &[u8]) -> V {
-        match V::try_from_slice(&raw_value) {
+    fn deserialize_value(&self, raw_value: &[u8]) -> V {
+        match self.storage.scope(|| C::decode(raw_value)) {
             Ok(x) => x,
             Err(_) => env::panic(ERR_VALUE_DESERIALIZATION),
         }
     }
 
     fn serialize_value(value: &V) -> Vec<u8> {
-        match value.try_to_vec() {
+        match C::encode(value) {
             Ok(x) => x,
             Err(_) => env::panic(ERR_VALUE_SERIALIZATION),
         }
@@ -103,14 +178,14 @@ where
 
     /// Returns the value corresponding to the key.
     pub fn get(&self, key: &K) -> Option<V> {
-        self.get_raw(&Self::serialize_key(key)).map(|value_raw| Self::deserialize_value(&value_raw))
+        self.get_raw(&Self::serialize_key(key)).map(|value_raw| self.deserialize_value(&value_raw))
     }
 
     /// Removes a key from the map, returning the value at the key if the key was previously in the
     /// map.
     pub fn remove(&mut self, key: &K) -> Option<V> {
         self.remove_raw(&Self::serialize_key(key))
-            .map(|value_raw| Self::deserialize_value(&value_raw))
+            .map(|value_raw| self.deserialize_value(&value_raw))
     }
 
     /// Inserts a key-value pair into the map.
@@ -119,7 +194,31 @@ where
     /// the implementation.
     pub fn insert(&mut self, key: &K, value: &V) -> Option<V> {
         self.insert_raw(&Self::serialize_key(key), &Self::serialize_value(&value))
-            .map(|value_raw| Self::deserialize_value(&value_raw))
+            .map(|value_raw| self.deserialize_value(&value_raw))
+    }
+
+    /// Gets the given key's corresponding entry in the map for in-place manipulation.
+    ///
+    /// ```
+    /// # use near_sdk::collections::LookupMap;
+    /// # near_sdk::test_utils::test_env::setup();
+    /// let mut balances: LookupMap<String, u128> = LookupMap::new(b"b");
+    /// *balances.entry("alice.near".to_string()).or_insert(0) += 10;
+    /// *balances.entry("alice.near".to_string()).or_insert(0) += 5;
+    /// assert_eq!(balances.get(&"alice.near".to_string()), Some(15));
+    /// ```
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, C, B> {
+        let key_raw = Self::serialize_key(&key);
+        match self.get_raw(&key_raw) {
+            Some(value_raw) => {
+                let value = self.deserialize_value(&value_raw);
+                Entry::Occupied(OccupiedEntry::new(key, key_raw, value, self))
+            }
+            None => {
+                let storage = self.storage.clone();
+                Entry::Vacant(VacantEntry::new(key, key_raw, self, storage))
+            }
+        }
     }
 
     pub fn extend<IT: IntoIterator<Item = (K, V)>>(&mut self, iter: IT) {
@@ -129,14 +228,58 @@ where
     }
 }
 
+impl<K, V, H, C, B: Storage> LookupMap<K, V, H, C, B>
+where
+    K: BorshSerialize,
+    V: NestedCollection<B>,
+    H: ToKey,
+    C: Encoder<V> + Decoder<V>,
+{
+    /// Removes a nested collection from the map together with its content. Returns `true` if the
+    /// key was previously in the map.
+    pub fn remove_nested(&mut self, key: &K) -> bool {
+        match self.remove(key) {
+            Some(mut collection) => {
+                collection.clear_nested();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<K, V, H, C, B: Storage> RawMap for LookupMap<K, V, H, C, B>
+where
+    H: ToKey,
+{
+    fn insert_raw(&mut self, key_raw: &[u8], value_raw: &[u8]) -> Option<Vec<u8>> {
+        LookupMap::insert_raw(self, key_raw, value_raw)
+    }
+
+    fn remove_raw(&mut self, key_raw: &[u8]) -> Option<Vec<u8>> {
+        LookupMap::remove_raw(self, key_raw)
+    }
+
+    fn nested_prefix(&self, key_raw: &[u8]) -> Vec<u8> {
+        nested_prefix(&self.raw_key_to_storage_key(key_raw), &[])
+    }
+}
+
+impl<K, V, H, C> Cacheable for LookupMap<K, V, H, C> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        vec![&self.key_prefix]
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 #[cfg(test)]
 mod tests {
-    use crate::collections::LookupMap;
+    use crate::collections::{Entry, Keccak256, LookupMap, Sha256, UnorderedSet};
+    use crate::env;
     use crate::test_utils::test_env;
     use rand::seq::SliceRandom;
     use rand::{Rng, SeedableRng};
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
 
     #[test]
     pub fn test_insert() {
@@ -285,4 +428,125 @@ mod tests {
             assert_eq!(map.get(&key).unwrap(), value);
         }
     }
+
+    #[test]
+    pub fn test_entry() {
+        test_env::setup();
+        let mut map = LookupMap::new(b"m");
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(5);
+        let mut key_to_value = HashMap::new();
+        for _ in 0..500 {
+            let key = rng.gen::<u64>() % 50;
+            let value = rng.gen::<u64>() % 1000;
+            *map.entry(key).or_insert(0) += value;
+            *key_to_value.entry(key).or_insert(0) += value;
+        }
+        for (key, value) in &key_to_value {
+            assert_eq!(map.get(key), Some(*value));
+        }
+
+        map.entry(0).and_modify(|v| *v = 1).or_insert(2);
+        key_to_value.entry(0).and_modify(|v| *v = 1).or_insert(2);
+        assert_eq!(map.get(&0), key_to_value.get(&0).cloned());
+
+        // The value of an entry is written when the entry goes out of scope.
+        {
+            let mut value = map.entry(100).or_insert_with(|| 5);
+            {
+                let value: &mut u64 = &mut value;
+                *value += 1;
+            }
+            *value += 1;
+        }
+        assert_eq!(map.get(&100), Some(7));
+        {
+            let mut value = map.entry(100).or_insert(0);
+            *value *= 2;
+            assert_eq!(*value, 14);
+        }
+        assert_eq!(map.remove(&100), Some(14));
+
+        for key in 0..50 {
+            match map.entry(key) {
+                Entry::Occupied(entry) => {
+                    assert_eq!(entry.remove(), key_to_value.remove(&key).unwrap());
+                }
+                Entry::Vacant(entry) => {
+                    assert!(!key_to_value.contains_key(entry.key()));
+                }
+            }
+            assert!(!map.contains_key(&key));
+        }
+    }
+
+    #[test]
+    pub fn test_nested() {
+        test_env::setup();
+        let mut map: LookupMap<u64, UnorderedSet<u64>> = LookupMap::new(b"m");
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(5);
+        let mut baseline: HashMap<u64, HashSet<u64>> = HashMap::new();
+        for _ in 0..500 {
+            let key = rng.gen::<u64>() % 10;
+            let element = rng.gen::<u64>() % 20;
+            match rng.gen::<u64>() % 10 {
+                0 => {
+                    assert_eq!(map.remove_nested(&key), baseline.remove(&key).is_some());
+                }
+                1..=3 => {
+                    if let Entry::Occupied(mut entry) = map.entry(key) {
+                        assert_eq!(
+                            entry.get_mut().remove(&element),
+                            baseline.get_mut(&key).unwrap().remove(&element)
+                        );
+                    }
+                }
+                _ => {
+                    let mut entry = map.entry(key).or_insert_nested();
+                    assert_eq!(
+                        entry.get_mut().insert(&element),
+                        baseline.entry(key).or_default().insert(element)
+                    );
+                }
+            }
+        }
+        for key in 0..10 {
+            assert_eq!(
+                map.get(&key).map(|set| set.iter().collect::<HashSet<_>>()),
+                baseline.get(&key).cloned()
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_hashed_keys() {
+        test_env::setup();
+        let mut sha_map: LookupMap<Vec<u8>, u64, Sha256> = LookupMap::with_hasher(b"s");
+        let mut keccak_map: LookupMap<Vec<u8>, u64, Keccak256> = LookupMap::with_hasher(b"k");
+        let mut baseline = HashMap::new();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(5);
+        for _ in 0..500 {
+            let key = vec![rng.gen::<u8>() % 20; (rng.gen::<u64>() % 100) as usize];
+            let value = rng.gen::<u64>();
+            if rng.gen::<bool>() {
+                let prev = baseline.insert(key.clone(), value);
+                assert_eq!(sha_map.insert(&key, &value), prev);
+                assert_eq!(keccak_map.insert(&key, &value), prev);
+            } else {
+                let prev = baseline.remove(&key);
+                assert_eq!(sha_map.remove(&key), prev);
+                assert_eq!(keccak_map.remove(&key), prev);
+            }
+        }
+        for (key, value) in &baseline {
+            assert_eq!(sha_map.get(key), Some(*value));
+            assert_eq!(keccak_map.get(key), Some(*value));
+        }
+
+        // Storage keys are the prefix followed by the hash of the serialized key.
+        let key = vec![7u8; 1000];
+        sha_map.insert(&key, &1);
+        let key_raw = borsh::BorshSerialize::try_to_vec(&key).unwrap();
+        let storage_key = [&b"s"[..], &env::sha256(&key_raw)].concat();
+        assert!(env::storage_has_key(&storage_key));
+    }
 }
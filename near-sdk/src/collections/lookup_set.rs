@@ -5,36 +5,84 @@ use std::marker::PhantomData;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 
-use crate::collections::append_slice;
+use crate::collections::cache::Cacheable;
+use crate::collections::key::{Identity, ToKey};
+use crate::collections::storage::{EnvStorage, Storage};
 use crate::{env, IntoStorageKey};
 
 const ERR_ELEMENT_SERIALIZATION: &[u8] = b"Cannot serialize element with Borsh";
 
 /// An non-iterable implementation of a set that stores its content directly on the trie.
+///
+/// The storage key of an element is the prefix followed by the serialized element. Use
+/// `with_hasher` to create a set that hashes the serialized elements with `Sha256` or `Keccak256`
+/// instead. The content is stored in the storage `B`, which is the storage of the contract by
+/// default. See `with_storage` and `with_hasher_and_storage`.
 #[derive(BorshSerialize, BorshDeserialize)]
-pub struct LookupSet<T> {
+pub struct LookupSet<T, H = Identity, B: Storage = EnvStorage> {
     element_prefix: Vec<u8>,
     #[borsh_skip]
-    el: PhantomData<T>,
+    el: PhantomData<(T, H)>,
+    #[borsh_skip]
+    storage: B,
 }
 
-impl<T> LookupSet<T> {
+impl<T> LookupSet<T, Identity> {
     /// Create a new map. Use `element_prefix` as a unique prefix for trie keys.
     pub fn new<S>(element_prefix: S) -> Self
     where
         S: IntoStorageKey,
     {
-        Self { element_prefix: element_prefix.into_storage_key(), el: PhantomData }
+        Self::with_hasher(element_prefix)
+    }
+}
+
+impl<T, B: Storage> LookupSet<T, Identity, B> {
+    /// Create a new set that stores its content in `storage`. Use `element_prefix` as a unique
+    /// prefix for keys.
+    pub fn with_storage<S>(element_prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_hasher_and_storage(element_prefix, storage)
+    }
+}
+
+impl<T, H> LookupSet<T, H>
+where
+    H: ToKey,
+{
+    /// Create a new set that converts serialized elements into storage keys with `H`. Use
+    /// `element_prefix` as a unique prefix for trie keys.
+    pub fn with_hasher<S>(element_prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_hasher_and_storage(element_prefix, EnvStorage)
+    }
+}
+
+impl<T, H, B: Storage> LookupSet<T, H, B>
+where
+    H: ToKey,
+{
+    /// Create a new set that converts serialized elements into storage keys with `H` and stores
+    /// its content in `storage`. Use `element_prefix` as a unique prefix for keys.
+    pub fn with_hasher_and_storage<S>(element_prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self { element_prefix: element_prefix.into_storage_key(), el: PhantomData, storage }
     }
 
     fn raw_element_to_storage_key(&self, element_raw: &[u8]) -> Vec<u8> {
-        append_slice(&self.element_prefix, element_raw)
+        H::to_key(&self.element_prefix, element_raw)
     }
 
     /// Returns `true` if the serialized key is present in the map.
     fn contains_raw(&self, element_raw: &[u8]) -> bool {
         let storage_key = self.raw_element_to_storage_key(element_raw);
-        env::storage_has_key(&storage_key)
+        self.storage.storage_has_key(&storage_key)
     }
 
     /// Inserts a serialized element into the set.
@@ -42,20 +90,21 @@ impl<T> LookupSet<T> {
     /// If the set did have this value present, `false` is returned.
     pub fn insert_raw(&mut self, element_raw: &[u8]) -> bool {
         let storage_key = self.raw_element_to_storage_key(element_raw);
-        !env::storage_write(&storage_key, b"")
+        !self.storage.storage_write(&storage_key, b"")
     }
 
     /// Removes a serialized element from the set.
     /// Returns true if the element was present in the set.
     pub fn remove_raw(&mut self, element_raw: &[u8]) -> bool {
         let storage_key = self.raw_element_to_storage_key(element_raw);
-        env::storage_remove(&storage_key)
+        self.storage.storage_remove(&storage_key)
     }
 }
 
-impl<T> LookupSet<T>
+impl<T, H, B: Storage> LookupSet<T, H, B>
 where
     T: BorshSerialize,
+    H: ToKey,
 {
     fn serialize_element(element: &T) -> Vec<u8> {
         match element.try_to_vec() {
@@ -88,11 +137,19 @@ where
     }
 }
 
+impl<T, H> Cacheable for LookupSet<T, H> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        vec![&self.element_prefix]
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 #[cfg(test)]
 mod tests {
-    use crate::collections::LookupSet;
+    use crate::collections::{InMemoryStorage, Keccak256, LookupSet, Sha256, Storage, ToKey};
+    use crate::env;
     use crate::test_utils::test_env;
+    use borsh::BorshSerialize;
     use rand::seq::SliceRandom;
     use rand::{Rng, SeedableRng};
     use std::collections::HashSet;
@@ -204,4 +261,44 @@ mod tests {
             assert!(set.contains(&key));
         }
     }
+
+    #[test]
+    pub fn test_hashed_elements() {
+        test_env::setup();
+        let mut sha_set: LookupSet<String, Sha256> = LookupSet::with_hasher(b"s");
+        let mut keccak_set: LookupSet<String, Keccak256> = LookupSet::with_hasher(b"k");
+        let mut baseline = HashSet::new();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(5);
+        for _ in 0..500 {
+            let element = format!("account-{}.near", rng.gen::<u64>() % 50);
+            if rng.gen::<bool>() {
+                let inserted = baseline.insert(element.clone());
+                assert_eq!(sha_set.insert(&element), inserted);
+                assert_eq!(keccak_set.insert(&element), inserted);
+            } else {
+                let removed = baseline.remove(&element);
+                assert_eq!(sha_set.remove(&element), removed);
+                assert_eq!(keccak_set.remove(&element), removed);
+            }
+        }
+        for index in 0..50 {
+            let element = format!("account-{}.near", index);
+            assert_eq!(sha_set.contains(&element), baseline.contains(&element));
+            assert_eq!(keccak_set.contains(&element), baseline.contains(&element));
+        }
+    }
+
+    #[test]
+    pub fn test_hasher_and_storage() {
+        test_env::setup();
+        let storage = InMemoryStorage::new();
+        let mut set: LookupSet<String, Sha256, InMemoryStorage> =
+            LookupSet::with_hasher_and_storage(b"s", storage.clone());
+        let element = "alice.near".to_string();
+        assert!(set.insert(&element));
+        assert!(set.contains(&element));
+        let key = Sha256::to_key(b"s", &element.try_to_vec().unwrap());
+        assert!(storage.storage_has_key(&key));
+        assert!(!env::storage_has_key(&key));
+    }
 }
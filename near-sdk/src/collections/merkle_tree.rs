@@ -0,0 +1,380 @@
+//! An append-only Merkle tree implemented on a trie, with inclusion proofs that can be verified
+//! on-chain and off-chain.
+//!
+//! Leaves are hashed as `H(0x00 || leaf)` and internal nodes as `H(0x01 || left || right)`, so a
+//! leaf can never be passed off as an internal node. The leaves are split into complete subtrees of
+//! decreasing sizes, one per bit of the number of leaves, and the root combines the roots of the
+//! subtrees from right to left. This gives the same tree as RFC 6962 for any number of leaves.
+use std::marker::PhantomData;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+
+use crate::collections::append;
+use crate::collections::cache::Cacheable;
+use crate::collections::key::HashAlgorithm;
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{Borsh, NestedCollection, Vector};
+use crate::{env, IntoStorageKey};
+
+const ERR_INCONSISTENT_STATE: &[u8] = b"The collection is an inconsistent state. Did previous smart contract execution terminate unexpectedly?";
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// A proof that a leaf is included in a `MerkleTree` of `len` leaves at position `index`. The
+/// siblings are the hashes of the nodes next to the path from the leaf to the root, from the leaf
+/// level up, skipping the levels where the path has no sibling.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub index: u64,
+    pub len: u64,
+    pub siblings: Vec<Vec<u8>>,
+}
+
+/// An append-only Merkle tree that stores its leaf hashes and internal nodes on the trie.
+/// Uses a `Vector` of the leaf hashes and of the roots of the complete subtrees, in the order in
+/// which the subtrees are completed, so every node is written once and never moved.
+///
+/// `push` costs `O(log(N))` reads and writes in the worst case and two writes on average. `root`
+/// and `proof` cost `O(log(N))` reads. The leaves themselves are not stored, only their hashes.
+/// The nodes are stored in the storage `B`, which is the storage of the contract by default. See
+/// `with_storage`.
+///
+/// ```
+/// # use near_sdk::collections::{verify_proof, MerkleTree, Sha256};
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut tree: MerkleTree<Sha256> = MerkleTree::new(b"t");
+/// tree.push(b"alice.near:100");
+/// let index = tree.push(b"bob.near:200");
+/// let root = tree.root().unwrap();
+/// let proof = tree.proof(index).unwrap();
+/// assert!(verify_proof::<Sha256>(&root, tree.len(), b"bob.near:200", &proof));
+/// assert!(!verify_proof::<Sha256>(&root, tree.len(), b"bob.near:300", &proof));
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct MerkleTree<H, B: Storage = EnvStorage> {
+    len: u64,
+    nodes: Vector<Vec<u8>, Borsh, B>,
+    #[borsh_skip]
+    hasher: PhantomData<H>,
+}
+
+impl<H> MerkleTree<H> {
+    /// Create new tree with zero leaves. Use `prefix` as a unique identifier on the trie.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_storage(prefix, EnvStorage)
+    }
+}
+
+impl<H, B: Storage> MerkleTree<H, B> {
+    /// Create new tree with zero leaves that stores its nodes in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        let nodes_prefix = append(&prefix.into_storage_key(), b'n');
+        Self { len: 0, nodes: Vector::with_storage(nodes_prefix, storage), hasher: PhantomData }
+    }
+
+    /// Returns the number of leaves in the tree.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the tree contains no leaves.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn read_node(&self, position: u64) -> Vec<u8> {
+        match self.nodes.get_raw(position) {
+            Some(hash) => hash,
+            None => env::panic(ERR_INCONSISTENT_STATE),
+        }
+    }
+
+    /// Returns the hash of the leaf at `index`, or `None` if it is out of bounds.
+    pub fn leaf_hash(&self, index: u64) -> Option<Vec<u8>> {
+        if index >= self.len {
+            return None;
+        }
+        Some(self.read_node(leaf_position(index)))
+    }
+
+    /// Returns the hash of the complete subtrees with the given root positions, combined from
+    /// right to left.
+    fn bag_peaks(&self, peaks: &[Peak]) -> Option<Vec<u8>>
+    where
+        H: HashAlgorithm,
+    {
+        let mut peaks = peaks.iter().rev();
+        let mut hash = self.read_node(peaks.next()?.position);
+        for peak in peaks {
+            hash = hash_node::<H>(&self.read_node(peak.position), &hash);
+        }
+        Some(hash)
+    }
+
+    /// Removes all leaves and nodes from the tree.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.len = 0;
+    }
+}
+
+impl<H: HashAlgorithm, B: Storage> MerkleTree<H, B> {
+    /// Returns the root of the tree, or `None` if the tree is empty.
+    pub fn root(&self) -> Option<Vec<u8>> {
+        self.bag_peaks(&peaks(self.len))
+    }
+
+    /// Returns the proof that the leaf at `index` is included in the tree with its current root,
+    /// or `None` if `index` is out of bounds.
+    pub fn proof(&self, index: u64) -> Option<MerkleProof> {
+        if index >= self.len {
+            return None;
+        }
+        let peaks = peaks(self.len);
+        let peak = peaks
+            .iter()
+            .position(|peak| index < peak.first_leaf + (1 << peak.height))
+            .expect("checked `index < len` above, so a subtree contains the leaf");
+        let mut siblings = Vec::new();
+        let mut position = leaf_position(index);
+        let local_index = index - peaks[peak].first_leaf;
+        for height in 0..peaks[peak].height {
+            // The subtree of a child at `height` has `2^(height + 1) - 1` nodes, and its parent
+            // follows the right child.
+            let subtree_len = (2 << height) - 1;
+            if (local_index >> height) & 1 == 0 {
+                siblings.push(self.read_node(position + subtree_len));
+                position += subtree_len + 1;
+            } else {
+                siblings.push(self.read_node(position - subtree_len));
+                position += 1;
+            }
+        }
+        siblings.extend(self.bag_peaks(&peaks[peak + 1..]));
+        for left in peaks[..peak].iter().rev() {
+            siblings.push(self.read_node(left.position));
+        }
+        Some(MerkleProof { index, len: self.len, siblings })
+    }
+
+    /// Appends a leaf to the tree and updates the nodes on its path to the root. Returns the index
+    /// of the leaf.
+    pub fn push(&mut self, leaf: &[u8]) -> u64 {
+        self.push_hash(&hash_leaf::<H>(leaf))
+    }
+
+    /// Appends a leaf that is already hashed with `hash_leaf`. Returns the index of the leaf.
+    pub fn push_hash(&mut self, leaf_hash: &[u8]) -> u64 {
+        let index = self.len;
+        self.len += 1;
+        self.nodes.push_raw(leaf_hash);
+        let mut hash = leaf_hash.to_vec();
+        let mut height = 0;
+        // The new leaf completes a subtree for every trailing one bit of its index.
+        while (index >> height) & 1 == 1 {
+            let left = self.read_node(self.nodes.len() - (2 << height));
+            hash = hash_node::<H>(&left, &hash);
+            self.nodes.push_raw(&hash);
+            height += 1;
+        }
+        index
+    }
+}
+
+impl<H, B: Storage> NestedCollection<B> for MerkleTree<H, B> {
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear();
+    }
+}
+
+impl<H> Cacheable for MerkleTree<H> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        self.nodes.storage_prefixes()
+    }
+}
+
+/// The root of a complete subtree of `2^height` leaves starting at `first_leaf`.
+struct Peak {
+    position: u64,
+    first_leaf: u64,
+    height: u32,
+}
+
+/// Returns the complete subtrees of a tree of `len` leaves from left to right.
+fn peaks(len: u64) -> Vec<Peak> {
+    let mut peaks = Vec::new();
+    let mut first_leaf = 0;
+    for height in (0..64).rev() {
+        if (len >> height) & 1 == 1 {
+            let position = leaf_position(first_leaf) + (2 << height) - 2;
+            peaks.push(Peak { position, first_leaf, height });
+            first_leaf += 1 << height;
+        }
+    }
+    peaks
+}
+
+/// Returns the position of the hash of the leaf at `index` among the nodes. Every leaf before it
+/// is followed by the roots of the subtrees it completes.
+fn leaf_position(index: u64) -> u64 {
+    2 * index - u64::from(index.count_ones())
+}
+
+/// Returns the hash of a leaf as it is stored in a `MerkleTree`.
+pub fn hash_leaf<H: HashAlgorithm>(leaf: &[u8]) -> Vec<u8> {
+    H::hash(&[&[LEAF_PREFIX], leaf].concat())
+}
+
+fn hash_node<H: HashAlgorithm>(left: &[u8], right: &[u8]) -> Vec<u8> {
+    H::hash(&[&[NODE_PREFIX], left, right].concat())
+}
+
+/// Returns `true` if `proof` shows that `leaf` is included in a `MerkleTree` of `len` leaves with
+/// the given root. `len` must come from the same trusted source as the root: the same leaf can be
+/// proven at different indices of trees of different sizes, so a proof made for another size is
+/// rejected. Does not access the trie, so it can be used off-chain.
+pub fn verify_proof<H: HashAlgorithm>(
+    root: &[u8],
+    len: u64,
+    leaf: &[u8],
+    proof: &MerkleProof,
+) -> bool {
+    if proof.len != len || proof.index >= len {
+        return false;
+    }
+    let mut siblings = proof.siblings.iter();
+    let mut hash = hash_leaf::<H>(leaf);
+    let mut position = proof.index;
+    let mut level_len = len;
+    while level_len > 1 {
+        if position ^ 1 < level_len {
+            let sibling = match siblings.next() {
+                Some(sibling) => sibling,
+                None => return false,
+            };
+            hash = if position % 2 == 0 {
+                hash_node::<H>(&hash, sibling)
+            } else {
+                hash_node::<H>(sibling, &hash)
+            };
+        }
+        position /= 2;
+        level_len = level_len / 2 + level_len % 2;
+    }
+    siblings.next().is_none() && hash == root
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use super::{hash_leaf, hash_node};
+    use crate::collections::{verify_proof, Keccak256, MerkleProof, MerkleTree, Sha256};
+    use crate::test_utils::test_env;
+
+    /// Computes the root as defined by RFC 6962, splitting at the largest power of two below the
+    /// number of leaves.
+    fn reference_root(leaves: &[Vec<u8>]) -> Vec<u8> {
+        if leaves.len() == 1 {
+            return hash_leaf::<Sha256>(&leaves[0]);
+        }
+        let mut split = 1;
+        while split * 2 < leaves.len() {
+            split *= 2;
+        }
+        hash_node::<Sha256>(&reference_root(&leaves[..split]), &reference_root(&leaves[split..]))
+    }
+
+    #[test]
+    pub fn test_root_and_proofs() {
+        test_env::setup();
+        let mut tree: MerkleTree<Sha256> = MerkleTree::new(b"t");
+        assert_eq!(tree.root(), None);
+        assert_eq!(tree.proof(0), None);
+        let mut leaves = vec![];
+        for i in 0..40u64 {
+            let leaf = i.to_le_bytes().to_vec();
+            assert_eq!(tree.push(&leaf), i);
+            leaves.push(leaf);
+            let root = tree.root().unwrap();
+            assert_eq!(root, reference_root(&leaves));
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(index as u64).unwrap();
+                assert!(verify_proof::<Sha256>(&root, tree.len(), leaf, &proof));
+            }
+        }
+        assert_eq!(tree.len(), 40);
+        assert_eq!(tree.leaf_hash(3), Some(hash_leaf::<Sha256>(&leaves[3])));
+        assert_eq!(tree.leaf_hash(40), None);
+        tree.clear();
+        assert!(tree.is_empty());
+        assert_eq!(tree.root(), None);
+    }
+
+    #[test]
+    pub fn test_invalid_proofs() {
+        test_env::setup();
+        let mut tree: MerkleTree<Keccak256> = MerkleTree::new(b"t");
+        for i in 0..11u8 {
+            tree.push(&[i]);
+        }
+        let root = tree.root().unwrap();
+        let proof = tree.proof(6).unwrap();
+        assert!(verify_proof::<Keccak256>(&root, 11, &[6], &proof));
+        assert!(!verify_proof::<Sha256>(&root, 11, &[6], &proof));
+        assert!(!verify_proof::<Keccak256>(&root, 11, &[7], &proof));
+
+        let mut wrong_index = proof.clone();
+        wrong_index.index = 7;
+        assert!(!verify_proof::<Keccak256>(&root, 11, &[6], &wrong_index));
+        let mut out_of_bounds = proof.clone();
+        out_of_bounds.index = 11;
+        assert!(!verify_proof::<Keccak256>(&root, 11, &[6], &out_of_bounds));
+        let mut tampered = proof.clone();
+        tampered.siblings[1][0] ^= 1;
+        assert!(!verify_proof::<Keccak256>(&root, 11, &[6], &tampered));
+        let mut extra = proof.clone();
+        extra.siblings.push(root.clone());
+        assert!(!verify_proof::<Keccak256>(&root, 11, &[6], &extra));
+        let mut missing = proof;
+        missing.siblings.pop();
+        assert!(!verify_proof::<Keccak256>(&root, 11, &[6], &missing));
+
+        // A proof is tied to the size of the tree it was made for.
+        let old_proof = tree.proof(2).unwrap();
+        tree.push(&[11]);
+        let root = tree.root().unwrap();
+        assert!(!verify_proof::<Keccak256>(&root, 12, &[2], &old_proof));
+        assert!(verify_proof::<Keccak256>(&root, 12, &[2], &tree.proof(2).unwrap()));
+    }
+
+    #[test]
+    pub fn test_proof_for_other_len() {
+        // Does not set up the mocked blockchain, since proofs are verified without `env`.
+        let leaves = [[0u8], [1], [2]];
+        let pair =
+            hash_node::<Sha256>(&hash_leaf::<Sha256>(&leaves[0]), &hash_leaf::<Sha256>(&leaves[1]));
+        let root = hash_node::<Sha256>(&pair, &hash_leaf::<Sha256>(&leaves[2]));
+        let proof = MerkleProof { index: 2, len: 3, siblings: vec![pair.clone()] };
+        assert!(verify_proof::<Sha256>(&root, 3, &leaves[2], &proof));
+
+        // The same sibling proves the last leaf at index 1 of a tree of 2 leaves.
+        let shifted = MerkleProof { index: 1, len: 2, siblings: vec![pair] };
+        assert!(!verify_proof::<Sha256>(&root, 3, &leaves[2], &shifted));
+        let mut relabeled = shifted;
+        relabeled.len = 3;
+        assert!(!verify_proof::<Sha256>(&root, 3, &leaves[2], &relabeled));
+    }
+}
@@ -34,10 +34,23 @@
 //!
 //! The efficiency of `LookupMap` comes at the cost, since it has fewer methods than `HashMap` and is not
 //! that seemlessly integrated with the rest of the Rust standard library.
+//!
+//! Collections that are accessed many times within a single call, e.g. the nodes of a `TreeMap`,
+//! can be wrapped into `Cached`. It keeps the entries that were read or modified in memory and
+//! writes the modified ones to the trie once, when the contract state is written.
 
 mod legacy_tree_map;
 pub use legacy_tree_map::LegacyTreeMap;
 
+mod consistency;
+pub use consistency::{ConsistencyReport, Inconsistency};
+
+mod codec;
+pub use codec::{Borsh, Decoder, Encoder};
+
+mod key;
+pub use key::{HashAlgorithm, Identity, Keccak256, Sha256, ToKey};
+
 mod lookup_map;
 pub use lookup_map::LookupMap;
 
@@ -45,10 +58,10 @@ mod lookup_set;
 pub use lookup_set::LookupSet;
 
 mod vector;
-pub use vector::Vector;
+pub use vector::{Vector, VectorIter};
 
 mod unordered_map;
-pub use unordered_map::UnorderedMap;
+pub use unordered_map::{UnorderedMap, UnorderedMapIter};
 
 mod unordered_set;
 pub use unordered_set::UnorderedSet;
@@ -59,9 +72,57 @@ pub use lazy_option::LazyOption;
 mod tree_map;
 pub use tree_map::TreeMap;
 
+pub(crate) mod cache;
+pub use cache::{Cacheable, Cached};
+
+mod entry;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+
+mod chunk_cursor;
+pub use chunk_cursor::ChunkCursor;
+
+mod stable_map;
+pub use stable_map::StableMap;
+
+mod insertion_ordered_map;
+pub use insertion_ordered_map::InsertionOrderedMap;
+
+mod nested;
+pub use nested::NestedCollection;
+
+mod binary_heap;
+pub use binary_heap::{BinaryHeap, HandleBinaryHeap, HeapHandle, Reverse};
+
+mod deque;
+pub use deque::{Deque, DequeIter};
+
+mod packed_vector;
+pub use packed_vector::{PackedVector, PackedVectorIter, DEFAULT_CHUNK_SIZE};
+
+mod bitset;
+pub use bitset::{Bitset, BitsetIter};
+
+mod expiring_map;
+pub use expiring_map::ExpiringMap;
+
+mod versioned_map;
+pub use versioned_map::{Retention, VersionedMap};
+
+mod merkle_tree;
+pub use merkle_tree::{hash_leaf, verify_proof, MerkleProof, MerkleTree};
+
+mod multi_map;
+pub use multi_map::MultiMap;
+
+mod indexed_map;
+pub use indexed_map::{Contains, IndexedMap, SecondaryIndex, SecondaryIndices};
+
+mod storage;
+pub use storage::{EnvStorage, InMemoryStorage, Storage};
+
 pub const ERR_INCONSISTENT_STATE: &[u8] = b"The collection is an inconsistent state. Did previous smart contract execution terminate unexpectedly?";
-pub const ERR_ELEMENT_SERIALIZATION: &[u8] = b"Cannot serialize element with Borsh.";
-pub const ERR_ELEMENT_DESERIALIZATION: &[u8] = b"Cannot deserialize element with Borsh.";
+pub const ERR_ELEMENT_SERIALIZATION: &[u8] = b"Cannot serialize element.";
+pub const ERR_ELEMENT_DESERIALIZATION: &[u8] = b"Cannot deserialize element.";
 
 pub(crate) fn append(id: &[u8], chr: u8) -> Vec<u8> {
     append_slice(id, &[chr])
@@ -0,0 +1,213 @@
+//! A map from keys to sets of values, e.g. from an owner to the ids of their tokens. The values of
+//! every key are stored in a nested `UnorderedSet`, so adding, removing and looking up a value is
+//! `O(1)` regardless of how many values the key has.
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::collections::cache::Cacheable;
+use crate::collections::entry::RawMap;
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{Borsh, Identity, LookupMap, UnorderedSet};
+use crate::{env, IntoStorageKey};
+
+const ERR_KEY_SERIALIZATION: &[u8] = b"Cannot serialize key with Borsh";
+
+/// A non-iterable map from keys to sets of values. A key is present as long as it has at least one
+/// value.
+///
+/// Uses the following maps: key -> set of values, where every set is an `UnorderedSet` under a
+/// prefix derived from the key. The content is stored in the storage `B`, which is the storage of
+/// the contract by default. See `with_storage`.
+///
+/// ```
+/// # use near_sdk::collections::MultiMap;
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut tokens: MultiMap<String, u64> = MultiMap::new(b"t");
+/// tokens.insert(&"alice.near".to_string(), &1);
+/// tokens.insert(&"alice.near".to_string(), &2);
+/// assert_eq!(tokens.get(&"alice.near".to_string()), vec![1, 2]);
+/// assert!(tokens.remove(&"alice.near".to_string(), &1));
+/// assert_eq!(tokens.count(&"alice.near".to_string()), 1);
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct MultiMap<K, V, B: Storage = EnvStorage> {
+    sets: LookupMap<K, UnorderedSet<V, Borsh, B>, Identity, Borsh, B>,
+}
+
+impl<K, V> MultiMap<K, V>
+where
+    K: BorshSerialize,
+    V: BorshSerialize + BorshDeserialize,
+{
+    /// Create new map with zero entries. Use `prefix` as a unique identifier on the trie.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_storage(prefix, EnvStorage)
+    }
+}
+
+impl<K, V, B: Storage> MultiMap<K, V, B>
+where
+    K: BorshSerialize,
+    V: BorshSerialize + BorshDeserialize,
+{
+    /// Create new map with zero entries that stores its content in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self { sets: LookupMap::with_storage(prefix, storage) }
+    }
+
+    fn serialize_key(key: &K) -> Vec<u8> {
+        match key.try_to_vec() {
+            Ok(x) => x,
+            Err(_) => env::panic(ERR_KEY_SERIALIZATION),
+        }
+    }
+
+    /// Returns `true` if the key has at least one value.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.sets.contains_key(key)
+    }
+
+    /// Returns `true` if the value is one of the values of the key.
+    pub fn contains(&self, key: &K, value: &V) -> bool {
+        match self.sets.get(key) {
+            Some(set) => set.contains(value),
+            None => false,
+        }
+    }
+
+    /// Returns the number of values of the key.
+    pub fn count(&self, key: &K) -> u64 {
+        self.sets.get(key).map_or(0, |set| set.len())
+    }
+
+    /// Returns all values of the key. The order of the values is not stable across removals.
+    pub fn get(&self, key: &K) -> Vec<V> {
+        self.sets.get(key).map_or_else(Vec::new, |set| set.to_vec())
+    }
+
+    /// Returns at most `limit` values of the key, starting at `from_index`.
+    pub fn paginate(&self, key: &K, from_index: u64, limit: u64) -> Vec<V> {
+        match self.sets.get(key) {
+            Some(set) => set.paginate(from_index, limit).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Adds a value to the key. Returns `false` if the key already had the value.
+    pub fn insert(&mut self, key: &K, value: &V) -> bool {
+        let mut set = match self.sets.get(key) {
+            Some(set) => set,
+            None => UnorderedSet::with_storage(
+                self.sets.nested_prefix(&Self::serialize_key(key)),
+                self.sets.storage().clone(),
+            ),
+        };
+        if !set.insert(value) {
+            return false;
+        }
+        self.sets.insert(key, &set);
+        true
+    }
+
+    /// Removes a value from the key, and the key once it has no values. Returns `false` if the key
+    /// did not have the value.
+    pub fn remove(&mut self, key: &K, value: &V) -> bool {
+        let mut set = match self.sets.get(key) {
+            Some(set) => set,
+            None => return false,
+        };
+        if !set.remove(value) {
+            return false;
+        }
+        if set.is_empty() {
+            self.sets.remove(key);
+        } else {
+            self.sets.insert(key, &set);
+        }
+        true
+    }
+
+    /// Removes the key together with all its values. Returns the number of removed values.
+    pub fn remove_all(&mut self, key: &K) -> u64 {
+        match self.sets.remove(key) {
+            Some(mut set) => {
+                let len = set.len();
+                set.clear();
+                len
+            }
+            None => 0,
+        }
+    }
+}
+
+impl<K, V> Cacheable for MultiMap<K, V> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        // The sets are stored under prefixes that start with the prefix of the map.
+        self.sets.storage_prefixes()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use crate::collections::MultiMap;
+    use crate::test_utils::test_env;
+    use rand::{Rng, SeedableRng};
+    use std::collections::{BTreeSet, HashMap};
+
+    #[test]
+    pub fn test_insert_remove() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(3);
+        let mut map = MultiMap::new(b"m");
+        let mut baseline: HashMap<u64, BTreeSet<u64>> = HashMap::new();
+        for _ in 0..1000 {
+            let key = rng.gen::<u64>() % 10;
+            let value = rng.gen::<u64>() % 20;
+            match rng.gen::<u64>() % 8 {
+                0 => {
+                    let removed = baseline.remove(&key).map_or(0, |set| set.len() as u64);
+                    assert_eq!(map.remove_all(&key), removed);
+                }
+                1..=3 => {
+                    let set = baseline.entry(key).or_default();
+                    assert_eq!(map.remove(&key, &value), set.remove(&value));
+                    if set.is_empty() {
+                        baseline.remove(&key);
+                    }
+                }
+                _ => {
+                    let set = baseline.entry(key).or_default();
+                    assert_eq!(map.insert(&key, &value), set.insert(value));
+                }
+            }
+            for key in 0..10 {
+                let expected = baseline.get(&key).cloned().unwrap_or_default();
+                assert_eq!(map.contains_key(&key), !expected.is_empty());
+                assert_eq!(map.count(&key), expected.len() as u64);
+                assert_eq!(map.get(&key).into_iter().collect::<BTreeSet<_>>(), expected);
+                assert_eq!(map.contains(&key, &value), expected.contains(&value));
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_paginate() {
+        test_env::setup();
+        let mut map = MultiMap::new(b"m");
+        for value in 0..10u64 {
+            map.insert(&1u8, &value);
+        }
+        map.insert(&2, &100);
+        assert_eq!(map.paginate(&1, 3, 4), vec![3, 4, 5, 6]);
+        assert_eq!(map.paginate(&1, 8, 4), vec![8, 9]);
+        assert_eq!(map.paginate(&2, 0, 4), vec![100]);
+        assert_eq!(map.paginate(&3, 0, 4), Vec::<u64>::new());
+    }
+}
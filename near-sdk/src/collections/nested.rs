@@ -0,0 +1,43 @@
+//! Collections stored as values of maps.
+//!
+//! Every persistent collection stores its content under a unique prefix, so a collection that is
+//! a value of a map needs a prefix that differs from the prefixes of the other values. Maps that
+//! support the `entry` API derive the prefix of a nested collection from their own prefix and the
+//! serialized key. Borsh serialization of keys of the same type is prefix-free, so the prefixes of
+//! nested collections never overlap with each other or with the entries of the map.
+//!
+//! A nested collection is stored in the storage of its map. The map passes its storage to the
+//! collections it creates, and decodes the collections it reads within `Storage::scope`, so that
+//! they are deserialized with its storage as well.
+use crate::collections::append_slice;
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::IntoStorageKey;
+
+/// A collection that can be a value of a map and be created and removed by it.
+///
+/// ```
+/// # use near_sdk::collections::{LookupMap, UnorderedSet};
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut tokens: LookupMap<String, UnorderedSet<u64>> = LookupMap::new(b"t");
+/// tokens.entry("alice.near".to_string()).or_insert_nested().get_mut().insert(&1);
+/// tokens.entry("alice.near".to_string()).or_insert_nested().get_mut().insert(&2);
+/// tokens.entry("bob.near".to_string()).or_insert_nested().get_mut().insert(&3);
+/// assert_eq!(tokens.get(&"alice.near".to_string()).unwrap().to_vec(), vec![1, 2]);
+///
+/// // Removes the set together with its elements.
+/// assert!(tokens.remove_nested(&"alice.near".to_string()));
+/// ```
+pub trait NestedCollection<B: Storage = EnvStorage>: Sized {
+    /// Creates an empty collection that stores its content under the given prefix in `storage`,
+    /// which is the storage of the map that the collection is a value of.
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self;
+
+    /// Removes the content of the collection from the trie.
+    fn clear_nested(&mut self);
+}
+
+/// Returns the prefix of the collection stored under the serialized key in a map that stores its
+/// entries under `map_prefix`.
+pub(crate) fn nested_prefix(map_prefix: &[u8], key_raw: &[u8]) -> Vec<u8> {
+    append_slice(&append_slice(map_prefix, key_raw), b"n")
+}
@@ -0,0 +1,1067 @@
+//! A vector implemented on a trie that stores several elements per storage slot. Every slot holds
+//! a chunk of up to `chunk_size` consecutive serialized elements as a Borsh `Vec<Vec<u8>>`, so
+//! small elements do not pay the storage overhead of a key each, and iteration reads a chunk at a
+//! time.
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::{Range, RangeBounds};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::collections::append_slice;
+use crate::collections::cache::Cacheable;
+use crate::collections::codec::{Borsh, Decoder, Encoder};
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::vector::{bounds_to_range, clamp_range};
+use crate::collections::{ChunkCursor, NestedCollection};
+use crate::{env, IntoStorageKey};
+
+const ERR_INCONSISTENT_STATE: &[u8] = b"The collection is an inconsistent state. Did previous smart contract execution terminate unexpectedly?";
+const ERR_ELEMENT_DESERIALIZATION: &[u8] = b"Cannot deserialize element";
+const ERR_ELEMENT_SERIALIZATION: &[u8] = b"Cannot serialize element";
+const ERR_INDEX_OUT_OF_BOUNDS: &[u8] = b"Index out of bounds";
+const ERR_ZERO_CHUNK_SIZE: &[u8] = b"Chunk size must be positive";
+
+/// The number of elements per storage slot of a `PackedVector` created with `new`.
+pub const DEFAULT_CHUNK_SIZE: u64 = 32;
+
+/// An iterable implementation of vector that stores its content on the trie in chunks of
+/// `chunk_size` elements. Uses the following map: index / chunk_size -> chunk.
+///
+/// Every operation reads and writes a whole chunk, so elements should be small and of similar
+/// size. Consecutive modifications of the same chunk are cheaper when the vector is wrapped into
+/// `Cached`.
+///
+/// Elements are encoded with the codec `C`, which is Borsh by default. See `with_codec`. The content
+/// is stored in the storage `B`, which is the storage of the contract by default. See
+/// `with_storage`.
+///
+/// ```
+/// # use near_sdk::collections::PackedVector;
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut timestamps: PackedVector<u64> = PackedVector::new(b"t");
+/// timestamps.extend(0..100);
+/// assert_eq!(timestamps.get(42), Some(42));
+/// // Reads 4 storage slots instead of 100.
+/// assert_eq!(timestamps.iter().sum::<u64>(), 4950);
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct PackedVector<T, C = Borsh, B: Storage = EnvStorage> {
+    len: u64,
+    chunk_size: u64,
+    prefix: Vec<u8>,
+    #[borsh_skip]
+    el: PhantomData<(T, C)>,
+    #[borsh_skip]
+    storage: B,
+}
+
+impl<T> PackedVector<T, Borsh> {
+    /// Create new vector with zero elements and `DEFAULT_CHUNK_SIZE` elements per storage slot.
+    /// Use `prefix` as a unique identifier on the trie.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_chunk_size(prefix, DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Create new vector with zero elements and `chunk_size` elements per storage slot. Use
+    /// `prefix` as a unique identifier on the trie.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn with_chunk_size<S>(prefix: S, chunk_size: u64) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_chunk_size_and_storage(prefix, chunk_size, EnvStorage)
+    }
+}
+
+impl<T, B: Storage> PackedVector<T, Borsh, B> {
+    /// Create new vector with zero elements and `DEFAULT_CHUNK_SIZE` elements per storage slot,
+    /// that stores its content in `storage`. Use `prefix` as a unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+
+    /// Create new vector with zero elements and `chunk_size` elements per storage slot, that
+    /// stores its content in `storage`. Use `prefix` as a unique identifier in the storage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn with_chunk_size_and_storage<S>(prefix: S, chunk_size: u64, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_chunk_size_codec_and_storage(prefix, chunk_size, storage)
+    }
+}
+
+impl<T, C, B: Storage> PackedVector<T, C, B> {
+    /// Returns the number of elements in the vector, also referred to as its size.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the vector contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of elements stored per storage slot.
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    /// Create new vector with zero elements and `DEFAULT_CHUNK_SIZE` elements per storage slot,
+    /// that encodes its elements with the codec `C`. Use `prefix` as a unique identifier on the
+    /// trie.
+    pub fn with_codec<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, B::default())
+    }
+
+    /// Create new vector with zero elements and `DEFAULT_CHUNK_SIZE` elements per storage slot,
+    /// that encodes its elements with the codec `C` and stores them in `storage`. Use `prefix` as
+    /// a unique identifier in the storage.
+    pub fn with_codec_and_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_chunk_size_codec_and_storage(prefix, DEFAULT_CHUNK_SIZE, storage)
+    }
+
+    /// Create new vector with zero elements and `chunk_size` elements per storage slot, that
+    /// encodes its elements with the codec `C` and stores them in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn with_chunk_size_codec_and_storage<S>(prefix: S, chunk_size: u64, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        if chunk_size == 0 {
+            env::panic(ERR_ZERO_CHUNK_SIZE)
+        }
+        Self { len: 0, chunk_size, prefix: prefix.into_storage_key(), el: PhantomData, storage }
+    }
+
+    fn chunk_to_lookup_key(&self, chunk: u64) -> Vec<u8> {
+        append_slice(&self.prefix, &chunk.to_le_bytes()[..])
+    }
+
+    fn chunk_count(&self) -> u64 {
+        self.len / self.chunk_size + (self.len % self.chunk_size != 0) as u64
+    }
+
+    fn read_chunk(&self, chunk: u64) -> Vec<Vec<u8>> {
+        let raw_chunk = match self.storage.storage_read(&self.chunk_to_lookup_key(chunk)) {
+            Some(x) => x,
+            None => env::panic(ERR_INCONSISTENT_STATE),
+        };
+        match Vec::<Vec<u8>>::try_from_slice(&raw_chunk) {
+            Ok(x) => x,
+            Err(_) => env::panic(ERR_INCONSISTENT_STATE),
+        }
+    }
+
+    /// Writes the chunk, or removes it from the trie if it has no elements.
+    fn write_chunk(&mut self, chunk: u64, raw_elements: &[Vec<u8>]) {
+        let lookup_key = self.chunk_to_lookup_key(chunk);
+        if raw_elements.is_empty() {
+            self.storage.storage_remove(&lookup_key);
+            return;
+        }
+        match raw_elements.try_to_vec() {
+            Ok(raw_chunk) => self.storage.storage_write(&lookup_key, &raw_chunk),
+            Err(_) => env::panic(ERR_INCONSISTENT_STATE),
+        };
+    }
+
+    /// Returns the serialized element by index or `None` if it is not present. Costs a storage
+    /// read of the chunk of the element.
+    pub fn get_raw(&self, index: u64) -> Option<Vec<u8>> {
+        if index >= self.len {
+            return None;
+        }
+        let mut raw_elements = self.read_chunk(index / self.chunk_size);
+        let offset = (index % self.chunk_size) as usize;
+        if offset >= raw_elements.len() {
+            env::panic(ERR_INCONSISTENT_STATE)
+        }
+        Some(raw_elements.swap_remove(offset))
+    }
+
+    /// Appends a serialized element to the back of the collection. Costs a storage read and a
+    /// storage write, or a single storage write if the element starts a new chunk. The other
+    /// elements of the chunk are not deserialized.
+    pub fn push_raw(&mut self, raw_element: &[u8]) {
+        let chunk = self.len / self.chunk_size;
+        let offset = self.len % self.chunk_size;
+        let lookup_key = self.chunk_to_lookup_key(chunk);
+        let mut raw_chunk = if offset == 0 {
+            vec![0; 4]
+        } else {
+            match self.storage.storage_read(&lookup_key) {
+                Some(x) => x,
+                None => env::panic(ERR_INCONSISTENT_STATE),
+            }
+        };
+        // A chunk is a Borsh `Vec` of `Vec<u8>`, which starts with the number of elements as a
+        // `u32`, and every element starts with its length as a `u32`.
+        raw_chunk[..4].copy_from_slice(&(offset as u32 + 1).to_le_bytes());
+        raw_chunk.extend_from_slice(&(raw_element.len() as u32).to_le_bytes());
+        raw_chunk.extend_from_slice(raw_element);
+        self.storage.storage_write(&lookup_key, &raw_chunk);
+        self.len += 1;
+    }
+
+    /// Removes the last element from a vector and returns it without deserializing, or `None` if
+    /// it is empty. Costs a storage read and a storage write or removal.
+    pub fn pop_raw(&mut self) -> Option<Vec<u8>> {
+        if self.is_empty() {
+            return None;
+        }
+        self.len -= 1;
+        let chunk = self.len / self.chunk_size;
+        let mut raw_elements = self.read_chunk(chunk);
+        let last = match raw_elements.pop() {
+            Some(x) => x,
+            None => env::panic(ERR_INCONSISTENT_STATE),
+        };
+        self.write_chunk(chunk, &raw_elements);
+        Some(last)
+    }
+
+    /// Replaces the element at `index` with a serialized element, returns a serialized evicted
+    /// element. Costs a storage read and a storage write.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn replace_raw(&mut self, index: u64, raw_element: &[u8]) -> Vec<u8> {
+        if index >= self.len {
+            env::panic(ERR_INDEX_OUT_OF_BOUNDS)
+        }
+        let chunk = index / self.chunk_size;
+        let offset = (index % self.chunk_size) as usize;
+        let mut raw_elements = self.read_chunk(chunk);
+        if offset >= raw_elements.len() {
+            env::panic(ERR_INCONSISTENT_STATE)
+        }
+        let evicted = std::mem::replace(&mut raw_elements[offset], raw_element.to_vec());
+        self.write_chunk(chunk, &raw_elements);
+        evicted
+    }
+
+    /// Removes an element from the vector and returns it in serialized form.
+    /// The removed element is replaced by the last element of the vector.
+    /// Does not preserve ordering, but is `O(1)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn swap_remove_raw(&mut self, index: u64) -> Vec<u8> {
+        if index >= self.len {
+            env::panic(ERR_INDEX_OUT_OF_BOUNDS)
+        }
+        let last = self.pop_raw().expect("checked `index < len` above, so `len > 0`");
+        if index == self.len {
+            last
+        } else {
+            self.replace_raw(index, &last)
+        }
+    }
+
+    /// Inserts a serialized element at `index`, shifting all elements after it to the right.
+    ///
+    /// Costs a storage read and a storage write for every chunk from the one of `index` to the
+    /// last one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert_raw(&mut self, index: u64, raw_element: &[u8]) {
+        if index > self.len {
+            env::panic(ERR_INDEX_OUT_OF_BOUNDS)
+        }
+        let mut carry = raw_element.to_vec();
+        let mut offset = (index % self.chunk_size) as usize;
+        let mut chunk = index / self.chunk_size;
+        // Every full chunk passes its last element on to the front of the next one.
+        while chunk < self.chunk_count() {
+            let mut raw_elements = self.read_chunk(chunk);
+            raw_elements.insert(offset, carry);
+            let overflow =
+                if raw_elements.len() as u64 > self.chunk_size { raw_elements.pop() } else { None };
+            self.write_chunk(chunk, &raw_elements);
+            match overflow {
+                Some(x) => carry = x,
+                None => {
+                    self.len += 1;
+                    return;
+                }
+            }
+            offset = 0;
+            chunk += 1;
+        }
+        self.write_chunk(chunk, &[carry]);
+        self.len += 1;
+    }
+
+    /// Removes an element from the vector and returns it in serialized form, shifting all
+    /// elements after it to the left. Preserves ordering, unlike `swap_remove_raw`.
+    ///
+    /// Costs a storage read and a storage write or removal for every chunk from the one of
+    /// `index` to the last one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove_raw(&mut self, index: u64) -> Vec<u8> {
+        if index >= self.len {
+            env::panic(ERR_INDEX_OUT_OF_BOUNDS)
+        }
+        let first_chunk = index / self.chunk_size;
+        let offset = (index % self.chunk_size) as usize;
+        let mut raw_elements = self.read_chunk(first_chunk);
+        if offset >= raw_elements.len() {
+            env::panic(ERR_INCONSISTENT_STATE)
+        }
+        let removed = raw_elements.remove(offset);
+        // Every chunk after the one of `index` passes its first element on to the previous one.
+        for chunk in first_chunk + 1..self.chunk_count() {
+            let mut next = self.read_chunk(chunk);
+            if next.is_empty() {
+                env::panic(ERR_INCONSISTENT_STATE)
+            }
+            raw_elements.push(next.remove(0));
+            self.write_chunk(chunk - 1, &raw_elements);
+            raw_elements = next;
+        }
+        let last_chunk = self.chunk_count() - 1;
+        self.write_chunk(last_chunk, &raw_elements);
+        self.len -= 1;
+        removed
+    }
+
+    /// Retains only the serialized elements for which the predicate returns `true`, preserving
+    /// their order.
+    ///
+    /// Costs a storage read for every chunk, a storage write for every chunk from the one of the
+    /// first removed element on and a storage removal for every chunk left empty.
+    pub fn retain_raw<F: FnMut(&[u8]) -> bool>(&mut self, mut f: F) {
+        self.retain_from(0, |_, raw_element| f(raw_element));
+    }
+
+    /// Removes the elements with indices within the given range from the vector and returns them
+    /// in serialized form, shifting all elements after the range to the left.
+    ///
+    /// Costs a storage read and a storage write or removal for every chunk from the one of the
+    /// start of the range to the last one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end or the end is out of bounds.
+    pub fn drain_raw<R: RangeBounds<u64>>(&mut self, range: R) -> Vec<Vec<u8>> {
+        let Range { start, end } = bounds_to_range(range, self.len);
+        if start > end || end > self.len {
+            env::panic(ERR_INDEX_OUT_OF_BOUNDS)
+        }
+        let mut drained = Vec::with_capacity((end - start) as usize);
+        if start == end {
+            return drained;
+        }
+        self.retain_from(start / self.chunk_size, |index, raw_element| {
+            if index < start || index >= end {
+                return true;
+            }
+            drained.push(raw_element.to_vec());
+            false
+        });
+        drained
+    }
+
+    /// Rewrites the chunks from `first_chunk` to the last one with only the serialized elements
+    /// for which the predicate of the element and its index returns `true`. The chunks before the
+    /// first removed element are not written.
+    fn retain_from<F: FnMut(u64, &[u8]) -> bool>(&mut self, first_chunk: u64, mut f: F) {
+        let chunk_count = self.chunk_count();
+        // The number of retained elements in the chunks that are already written.
+        let mut len = first_chunk * self.chunk_size;
+        let mut retained = Vec::new();
+        let mut changed = false;
+        for chunk in first_chunk..chunk_count {
+            for (offset, raw_element) in self.read_chunk(chunk).into_iter().enumerate() {
+                if !f(chunk * self.chunk_size + offset as u64, &raw_element) {
+                    changed = true;
+                    continue;
+                }
+                retained.push(raw_element);
+                if retained.len() as u64 == self.chunk_size {
+                    if changed {
+                        self.write_chunk(len / self.chunk_size, &retained);
+                    }
+                    len += self.chunk_size;
+                    retained.clear();
+                }
+            }
+        }
+        if !changed {
+            return;
+        }
+        let last_chunk = len / self.chunk_size;
+        self.write_chunk(last_chunk, &retained);
+        self.remove_chunks(last_chunk + 1..chunk_count);
+        self.len = len + retained.len() as u64;
+    }
+
+    /// Removes the chunks with the given indices from the trie.
+    fn remove_chunks(&mut self, chunks: Range<u64>) {
+        for chunk in chunks {
+            self.storage.storage_remove(&self.chunk_to_lookup_key(chunk));
+        }
+    }
+
+    /// Iterate over raw serialized elements. Reads every chunk once.
+    pub fn iter_raw(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        RawIter { vec: self, range: 0..self.len, front: None, back: None }
+    }
+
+    /// Extends vector from the given collection of serialized elements.
+    pub fn extend_raw<IT: IntoIterator<Item = Vec<u8>>>(&mut self, iter: IT) {
+        for el in iter {
+            self.push_raw(&el)
+        }
+    }
+
+    /// Removes all elements from the collection.
+    pub fn clear(&mut self) {
+        self.remove_chunks(0..self.chunk_count());
+        self.len = 0;
+    }
+
+    /// Removes at most `max_items` elements from the back of the vector, so that a large vector can
+    /// be cleared over several calls without running out of gas. Returns `true` if the vector is
+    /// empty.
+    ///
+    /// Costs a storage removal for every removed chunk, and a storage read and a storage write if
+    /// the new last chunk keeps some of its elements.
+    pub fn clear_in_chunks(&mut self, max_items: u64) -> bool {
+        self.truncate(self.len.saturating_sub(max_items));
+        self.is_empty()
+    }
+
+    /// Shortens the vector, keeping the first `len` elements and removing the rest. Has no effect
+    /// if `len` is greater than or equal to the vector's current length.
+    ///
+    /// Costs a storage removal for every removed chunk, and a storage read and a storage write if
+    /// the new last chunk keeps some of its elements. The other removed elements are not read.
+    pub fn truncate(&mut self, len: u64) {
+        if len >= self.len {
+            return;
+        }
+        let chunk_count = self.chunk_count();
+        let mut first_removed = len / self.chunk_size;
+        let offset = (len % self.chunk_size) as usize;
+        if offset != 0 {
+            let mut raw_elements = self.read_chunk(first_removed);
+            raw_elements.truncate(offset);
+            self.write_chunk(first_removed, &raw_elements);
+            first_removed += 1;
+        }
+        self.remove_chunks(first_removed..chunk_count);
+        self.len = len;
+    }
+
+    /// Swaps two elements in the vector.
+    ///
+    /// Costs a storage read and a storage write of the chunk of every element, or nothing if
+    /// `a == b`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` or `b` are out of bounds.
+    pub fn swap(&mut self, a: u64, b: u64) {
+        if a >= self.len || b >= self.len {
+            env::panic(ERR_INDEX_OUT_OF_BOUNDS)
+        }
+        if a == b {
+            return;
+        }
+        let (chunk_a, offset_a) = (a / self.chunk_size, (a % self.chunk_size) as usize);
+        let (chunk_b, offset_b) = (b / self.chunk_size, (b % self.chunk_size) as usize);
+        let mut raw_a = self.read_chunk(chunk_a);
+        if chunk_a == chunk_b {
+            if offset_a.max(offset_b) >= raw_a.len() {
+                env::panic(ERR_INCONSISTENT_STATE)
+            }
+            raw_a.swap(offset_a, offset_b);
+            self.write_chunk(chunk_a, &raw_a);
+            return;
+        }
+        let mut raw_b = self.read_chunk(chunk_b);
+        if offset_a >= raw_a.len() || offset_b >= raw_b.len() {
+            env::panic(ERR_INCONSISTENT_STATE)
+        }
+        std::mem::swap(&mut raw_a[offset_a], &mut raw_b[offset_b]);
+        self.write_chunk(chunk_a, &raw_a);
+        self.write_chunk(chunk_b, &raw_b);
+    }
+}
+
+impl<T, C, B: Storage> PackedVector<T, C, B>
+where
+    C: Encoder<T>,
+{
+    fn serialize_element(element: &T) -> Vec<u8> {
+        match C::encode(element) {
+            Ok(x) => x,
+            Err(_) => env::panic(ERR_ELEMENT_SERIALIZATION),
+        }
+    }
+
+    /// Appends an element to the back of the collection. Costs a storage read and a storage write,
+    /// or a single storage write if the element starts a new chunk. The other elements of the
+    /// chunk are not deserialized.
+    pub fn push(&mut self, element: &T) {
+        self.push_raw(&Self::serialize_element(element));
+    }
+
+    /// Extends vector from the given collection.
+    pub fn extend<IT: IntoIterator<Item = T>>(&mut self, iter: IT) {
+        for el in iter {
+            self.push(&el)
+        }
+    }
+
+    /// Inserts an element at `index`, shifting all elements after it to the right.
+    ///
+    /// Costs a storage read and a storage write for every chunk from the one of `index` to the
+    /// last one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: u64, element: &T) {
+        self.insert_raw(index, &Self::serialize_element(element));
+    }
+}
+
+impl<T, C, B: Storage> PackedVector<T, C, B>
+where
+    C: Decoder<T>,
+{
+    fn deserialize_element(storage: &B, raw_element: &[u8]) -> T {
+        match storage.scope(|| C::decode(raw_element)) {
+            Ok(x) => x,
+            Err(_) => env::panic(ERR_ELEMENT_DESERIALIZATION),
+        }
+    }
+
+    /// Returns the element by index or `None` if it is not present. Costs a storage read of the
+    /// chunk of the element.
+    pub fn get(&self, index: u64) -> Option<T> {
+        self.get_raw(index).map(|x| Self::deserialize_element(&self.storage, &x))
+    }
+
+    /// Removes the last element from a vector and returns it, or `None` if it is empty. Costs a
+    /// storage read and a storage write or removal.
+    pub fn pop(&mut self) -> Option<T> {
+        self.pop_raw().map(|x| Self::deserialize_element(&self.storage, &x))
+    }
+
+    /// Removes an element from the vector and returns it.
+    /// The removed element is replaced by the last element of the vector.
+    /// Does not preserve ordering, but is `O(1)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn swap_remove(&mut self, index: u64) -> T {
+        let raw_element = self.swap_remove_raw(index);
+        Self::deserialize_element(&self.storage, &raw_element)
+    }
+
+    /// Removes an element from the vector and returns it, shifting all elements after it to the
+    /// left. Preserves ordering, unlike `swap_remove`.
+    ///
+    /// Costs a storage read and a storage write or removal for every chunk from the one of
+    /// `index` to the last one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: u64) -> T {
+        let raw_element = self.remove_raw(index);
+        Self::deserialize_element(&self.storage, &raw_element)
+    }
+
+    /// Retains only the elements for which the predicate returns `true`, preserving their order.
+    ///
+    /// Costs a storage read for every chunk, a storage write for every chunk from the one of the
+    /// first removed element on and a storage removal for every chunk left empty.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        let storage = self.storage.clone();
+        self.retain_raw(|raw_element| f(&Self::deserialize_element(&storage, raw_element)))
+    }
+
+    /// Removes the elements with indices within the given range from the vector and returns them,
+    /// shifting all elements after the range to the left. Unlike `Vec::drain`, the elements are
+    /// removed eagerly.
+    ///
+    /// Costs a storage read and a storage write or removal for every chunk from the one of the
+    /// start of the range to the last one. Only the removed elements are deserialized.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end or the end is out of bounds.
+    pub fn drain<R: RangeBounds<u64>>(&mut self, range: R) -> Vec<T> {
+        let raw_elements = self.drain_raw(range);
+        raw_elements.iter().map(|x| Self::deserialize_element(&self.storage, x)).collect()
+    }
+
+    /// Iterate over deserialized elements.
+    pub fn iter(&self) -> PackedVectorIter<'_, T, C, B> {
+        self.range(..)
+    }
+
+    /// Iterate over deserialized elements with indices within the given range. The range is
+    /// clamped to the length of the vector.
+    pub fn range<R: RangeBounds<u64>>(&self, range: R) -> PackedVectorIter<'_, T, C, B> {
+        PackedVectorIter {
+            raw: RawIter {
+                vec: self,
+                range: clamp_range(range, self.len),
+                front: None,
+                back: None,
+            },
+        }
+    }
+
+    /// Iterate over at most `limit` deserialized elements starting from `from_index`.
+    pub fn paginate(&self, from_index: u64, limit: u64) -> PackedVectorIter<'_, T, C, B> {
+        self.range(from_index..from_index.saturating_add(limit))
+    }
+
+    pub fn to_vec(&self) -> Vec<T> {
+        self.iter().collect()
+    }
+
+    /// Returns at most `max_items` elements starting from the position of the `cursor`, and moves
+    /// the cursor past them. Returns an empty vector once all elements are processed. Reads every
+    /// chunk of the returned elements once.
+    pub fn next_chunk(&self, cursor: &mut ChunkCursor, max_items: u64) -> Vec<T> {
+        self.range(cursor.next_range(self.len, max_items)).collect()
+    }
+}
+
+impl<T, C, B: Storage> PackedVector<T, C, B>
+where
+    C: Encoder<T> + Decoder<T>,
+{
+    /// Replaces the element at `index`, returns an evicted element. Costs a storage read and a
+    /// storage write.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is out of bounds.
+    pub fn replace(&mut self, index: u64, element: &T) -> T {
+        let raw_evicted = self.replace_raw(index, &Self::serialize_element(element));
+        Self::deserialize_element(&self.storage, &raw_evicted)
+    }
+}
+
+/// An iterator over the serialized elements of a `PackedVector`. It reads every chunk once when
+/// iterated in one direction, and `nth` does not read the chunks it skips over.
+struct RawIter<'a, T, C, B: Storage> {
+    vec: &'a PackedVector<T, C, B>,
+    range: Range<u64>,
+    /// The last chunk read from the front and its elements that are not yielded yet.
+    front: Option<(u64, Vec<Option<Vec<u8>>>)>,
+    /// The last chunk read from the back and its elements that are not yielded yet.
+    back: Option<(u64, Vec<Option<Vec<u8>>>)>,
+}
+
+impl<'a, T, C, B: Storage> RawIter<'a, T, C, B> {
+    fn take(
+        vec: &PackedVector<T, C, B>,
+        buffer: &mut Option<(u64, Vec<Option<Vec<u8>>>)>,
+        index: u64,
+    ) -> Vec<u8> {
+        let chunk = index / vec.chunk_size;
+        if buffer.as_ref().map(|(buffered, _)| *buffered) != Some(chunk) {
+            *buffer = Some((chunk, vec.read_chunk(chunk).into_iter().map(Some).collect()));
+        }
+        let (_, raw_elements) = buffer.as_mut().expect("the chunk is buffered above");
+        match raw_elements.get_mut((index % vec.chunk_size) as usize).and_then(Option::take) {
+            Some(x) => x,
+            None => env::panic(ERR_INCONSISTENT_STATE),
+        }
+    }
+}
+
+impl<'a, T, C, B: Storage> Iterator for RawIter<'a, T, C, B> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.range.next()?;
+        Some(Self::take(self.vec, &mut self.front, index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        let index = self.range.nth(n)?;
+        Some(Self::take(self.vec, &mut self.front, index))
+    }
+}
+
+impl<'a, T, C, B: Storage> DoubleEndedIterator for RawIter<'a, T, C, B> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let index = self.range.next_back()?;
+        Some(Self::take(self.vec, &mut self.back, index))
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
+        let index = self.range.nth_back(n)?;
+        Some(Self::take(self.vec, &mut self.back, index))
+    }
+}
+
+/// An iterator over the elements of a `PackedVector`. It reads every chunk once when iterated in
+/// one direction, and `nth` does not read the chunks it skips over.
+pub struct PackedVectorIter<'a, T, C = Borsh, B: Storage = EnvStorage> {
+    raw: RawIter<'a, T, C, B>,
+}
+
+impl<'a, T, C, B: Storage> Iterator for PackedVectorIter<'a, T, C, B>
+where
+    C: Decoder<T>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.raw
+            .next()
+            .map(|x| PackedVector::<T, C, B>::deserialize_element(&self.raw.vec.storage, &x))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.raw.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.raw
+            .nth(n)
+            .map(|x| PackedVector::<T, C, B>::deserialize_element(&self.raw.vec.storage, &x))
+    }
+}
+
+impl<'a, T, C, B: Storage> DoubleEndedIterator for PackedVectorIter<'a, T, C, B>
+where
+    C: Decoder<T>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.raw
+            .next_back()
+            .map(|x| PackedVector::<T, C, B>::deserialize_element(&self.raw.vec.storage, &x))
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
+        self.raw
+            .nth_back(n)
+            .map(|x| PackedVector::<T, C, B>::deserialize_element(&self.raw.vec.storage, &x))
+    }
+}
+
+impl<'a, T, C, B: Storage> ExactSizeIterator for PackedVectorIter<'a, T, C, B> where C: Decoder<T> {}
+
+impl<'a, T, C, B: Storage> FusedIterator for PackedVectorIter<'a, T, C, B> where C: Decoder<T> {}
+
+impl<'a, T, C, B: Storage> IntoIterator for &'a PackedVector<T, C, B>
+where
+    C: Decoder<T>,
+{
+    type Item = T;
+    type IntoIter = PackedVectorIter<'a, T, C, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, C, B: Storage> NestedCollection<B> for PackedVector<T, C, B> {
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, C> Cacheable for PackedVector<T, C> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        vec![&self.prefix]
+    }
+}
+
+#[cfg(not(feature = "expensive-debug"))]
+impl<T, C, B: Storage> std::fmt::Debug for PackedVector<T, C, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PackedVector")
+            .field("len", &self.len)
+            .field("chunk_size", &self.chunk_size)
+            .field("prefix", &self.prefix)
+            .field("el", &self.el)
+            .finish()
+    }
+}
+
+#[cfg(feature = "expensive-debug")]
+impl<T: std::fmt::Debug, C: Decoder<T>, B: Storage> std::fmt::Debug for PackedVector<T, C, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_vec().fmt(f)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use crate::collections::{ChunkCursor, PackedVector};
+    use crate::env;
+    use crate::test_utils::test_env;
+
+    #[test]
+    pub fn test_push_pop_replace() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(0);
+        let mut vec = PackedVector::with_chunk_size(b"p".to_vec(), 5);
+        let mut baseline = vec![];
+        for _ in 0..1000 {
+            let value = rng.gen::<u64>();
+            match rng.gen::<u64>() % 5 {
+                0 => assert_eq!(vec.pop(), baseline.pop()),
+                1 if !baseline.is_empty() => {
+                    let index = rng.gen::<u64>() % vec.len();
+                    assert_eq!(vec.replace(index, &value), baseline[index as usize]);
+                    baseline[index as usize] = value;
+                }
+                2 if !baseline.is_empty() => {
+                    let index = rng.gen::<u64>() % vec.len();
+                    assert_eq!(vec.swap_remove(index), baseline.swap_remove(index as usize));
+                }
+                _ => {
+                    vec.push(&value);
+                    baseline.push(value);
+                }
+            }
+            assert_eq!(vec.len(), baseline.len() as u64);
+        }
+        assert_eq!(vec.to_vec(), baseline);
+        for (i, value) in baseline.iter().enumerate() {
+            assert_eq!(vec.get(i as u64), Some(*value));
+        }
+        assert_eq!(vec.get(baseline.len() as u64), None);
+    }
+
+    #[test]
+    pub fn test_insert_remove() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(1);
+        let mut vec = PackedVector::with_chunk_size(b"p".to_vec(), 3);
+        let mut baseline = vec![];
+        for _ in 0..500 {
+            let value = rng.gen::<u64>();
+            if rng.gen::<u64>() % 3 == 0 && !baseline.is_empty() {
+                let index = rng.gen::<u64>() % vec.len();
+                assert_eq!(vec.remove(index), baseline.remove(index as usize));
+            } else {
+                let index = rng.gen::<u64>() % (vec.len() + 1);
+                vec.insert(index, &value);
+                baseline.insert(index as usize, value);
+            }
+            assert_eq!(vec.len(), baseline.len() as u64);
+        }
+        assert_eq!(vec.to_vec(), baseline);
+        while !baseline.is_empty() {
+            assert_eq!(vec.remove(0), baseline.remove(0));
+        }
+        assert!(vec.is_empty());
+        assert!(!env::storage_has_key(&[&b"p"[..], &0u64.to_le_bytes()[..]].concat()));
+    }
+
+    #[test]
+    pub fn test_raw() {
+        test_env::setup();
+        let mut vec = PackedVector::<Vec<u8>>::with_chunk_size(b"p".to_vec(), 2);
+        vec.extend_raw((0..5u8).map(|x| vec![x; x as usize]));
+        assert_eq!(vec.get_raw(3), Some(vec![3; 3]));
+        assert_eq!(vec.get_raw(5), None);
+        assert_eq!(vec.replace_raw(0, &[7]), Vec::<u8>::new());
+        assert_eq!(vec.swap_remove_raw(1), vec![1]);
+        assert_eq!(vec.pop_raw(), Some(vec![3; 3]));
+        vec.push_raw(&[9, 9]);
+        assert_eq!(
+            vec.iter_raw().collect::<Vec<_>>(),
+            vec![vec![7], vec![4; 4], vec![2; 2], vec![9, 9]]
+        );
+    }
+
+    #[test]
+    pub fn test_large_chunk_size() {
+        test_env::setup();
+        let mut vec = PackedVector::with_chunk_size(b"p".to_vec(), u64::MAX);
+        vec.extend(0..10u32);
+        assert_eq!(vec.remove(3), 3);
+        vec.insert(0, &42);
+        assert_eq!(vec.to_vec(), vec![42, 0, 1, 2, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(vec.drain(1..3), vec![0, 1]);
+        vec.retain(|x| x % 2 == 0);
+        vec.truncate(3);
+        vec.swap(0, 2);
+        assert_eq!(vec.to_vec(), vec![4, 2, 42]);
+        vec.clear();
+        assert!(vec.is_empty());
+        assert!(!env::storage_has_key(&[&b"p"[..], &0u64.to_le_bytes()[..]].concat()));
+    }
+
+    #[test]
+    pub fn test_iter() {
+        test_env::setup();
+        let mut vec = PackedVector::with_chunk_size(b"p".to_vec(), 4);
+        let baseline: Vec<String> = (0..23).map(|x| x.to_string()).collect();
+        vec.extend(baseline.iter().cloned());
+        assert_eq!(vec.iter().len(), 23);
+        assert_eq!(
+            vec.iter().rev().collect::<Vec<_>>(),
+            baseline.iter().rev().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(vec.range(3..9).collect::<Vec<_>>(), baseline[3..9].to_vec());
+        assert_eq!(vec.paginate(20, 10).collect::<Vec<_>>(), baseline[20..].to_vec());
+
+        let mut iter = vec.iter();
+        assert_eq!(iter.nth(5), Some(baseline[5].clone()));
+        assert_eq!(iter.next_back(), Some(baseline[22].clone()));
+        assert_eq!(iter.nth_back(1), Some(baseline[20].clone()));
+        let rest: Vec<_> = iter.by_ref().collect();
+        assert_eq!(rest, baseline[6..20].to_vec());
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    pub fn test_clear() {
+        test_env::setup();
+        let mut vec = PackedVector::new(b"p".to_vec());
+        vec.extend(0..100u8);
+        vec.clear();
+        assert!(vec.is_empty());
+        vec.push(&7);
+        assert_eq!(vec.to_vec(), vec![7]);
+    }
+
+    #[test]
+    pub fn test_swap_truncate() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(2);
+        let mut vec = PackedVector::with_chunk_size(b"p".to_vec(), 7);
+        let mut baseline: Vec<u64> = (0..100).collect();
+        vec.extend(baseline.iter().cloned());
+        for _ in 0..100 {
+            let a = rng.gen::<u64>() % vec.len();
+            let b = rng.gen::<u64>() % vec.len();
+            vec.swap(a, b);
+            baseline.swap(a as usize, b as usize);
+        }
+        assert_eq!(vec.to_vec(), baseline);
+        vec.truncate(200);
+        assert_eq!(vec.len(), 100);
+        vec.truncate(40);
+        baseline.truncate(40);
+        assert_eq!(vec.to_vec(), baseline);
+        assert_eq!(vec.get_raw(40), None);
+        assert!(!env::storage_has_key(&[&b"p"[..], &6u64.to_le_bytes()[..]].concat()));
+        vec.truncate(35);
+        baseline.truncate(35);
+        assert_eq!(vec.to_vec(), baseline);
+        assert!(!env::storage_has_key(&[&b"p"[..], &5u64.to_le_bytes()[..]].concat()));
+        vec.push(&7);
+        baseline.push(7);
+        assert_eq!(vec.to_vec(), baseline);
+    }
+
+    #[test]
+    pub fn test_retain_drain() {
+        test_env::setup();
+        let mut vec = PackedVector::with_chunk_size(b"p".to_vec(), 6);
+        let mut baseline: Vec<u64> = (0..100).collect();
+        vec.extend(baseline.iter().cloned());
+        vec.retain(|x| *x >= 12);
+        baseline.retain(|x| *x >= 12);
+        assert_eq!(vec.to_vec(), baseline);
+        vec.retain(|x| x % 3 != 0);
+        baseline.retain(|x| x % 3 != 0);
+        assert_eq!(vec.to_vec(), baseline);
+
+        assert_eq!(vec.drain(10..20), baseline.drain(10..20).collect::<Vec<_>>());
+        assert_eq!(vec.drain(..=2), baseline.drain(..=2).collect::<Vec<_>>());
+        assert_eq!(vec.drain(40..), baseline.drain(40..).collect::<Vec<_>>());
+        assert_eq!(vec.drain(5..5), Vec::<u64>::new());
+        assert_eq!(vec.to_vec(), baseline);
+        vec.push(&7);
+        baseline.push(7);
+        assert_eq!(vec.to_vec(), baseline);
+
+        vec.retain_raw(|_| false);
+        assert!(vec.is_empty());
+        assert!(!env::storage_has_key(&[&b"p"[..], &0u64.to_le_bytes()[..]].concat()));
+    }
+
+    #[test]
+    pub fn test_clear_in_chunks() {
+        test_env::setup();
+        let mut vec = PackedVector::with_chunk_size(b"p".to_vec(), 8);
+        let mut baseline: Vec<u64> = (0..100).collect();
+        vec.extend(baseline.iter().cloned());
+        assert!(!vec.clear_in_chunks(30));
+        baseline.truncate(70);
+        assert_eq!(vec.to_vec(), baseline);
+        assert!(!vec.clear_in_chunks(69));
+        assert_eq!(vec.to_vec(), vec![0]);
+        assert!(vec.clear_in_chunks(30));
+        assert!(vec.is_empty());
+        assert!(!env::storage_has_key(&[&b"p"[..], &0u64.to_le_bytes()[..]].concat()));
+        assert!(vec.clear_in_chunks(30));
+    }
+
+    #[test]
+    pub fn test_next_chunk() {
+        test_env::setup();
+        let mut vec = PackedVector::with_chunk_size(b"p".to_vec(), 3);
+        vec.extend(0..10u64);
+        let mut cursor = ChunkCursor::new();
+        assert_eq!(vec.next_chunk(&mut cursor, 4), vec![0, 1, 2, 3]);
+        assert_eq!(vec.next_chunk(&mut cursor, 4), vec![4, 5, 6, 7]);
+        assert!(!cursor.is_finished(vec.len()));
+        assert_eq!(vec.next_chunk(&mut cursor, 4), vec![8, 9]);
+        assert!(cursor.is_finished(vec.len()));
+        assert_eq!(vec.next_chunk(&mut cursor, 4), Vec::<u64>::new());
+        assert_eq!(cursor.position(), 10);
+    }
+}
@@ -0,0 +1,542 @@
+//! An iterable map that keeps the index of every element stable across removals. Unlike
+//! `UnorderedMap`, which fills the gap left by a removed element with the last element, removed
+//! elements leave tombstones, so that readers that paginate over the map by index do not skip or
+//! see elements twice while the map is modified.
+use std::io;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::collections::cache::Cacheable;
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{
+    append, Borsh, Decoder, Encoder, Identity, LookupMap, NestedCollection, Vector,
+};
+use crate::{env, IntoStorageKey};
+
+const ERR_INCONSISTENT_STATE: &[u8] = b"The collection is an inconsistent state. Did previous smart contract execution terminate unexpectedly?";
+
+/// Storage shared by `StableMap` and `InsertionOrderedMap`: a vector of slots that are either
+/// occupied by a key-value pair or are tombstones, and a lookup from keys to their slots. Values
+/// are encoded with the codec `C`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Slots<K, V, C = Borsh, B: Storage = EnvStorage> {
+    index: LookupMap<K, u64, Identity, Borsh, B>,
+    entries: Vector<Option<(K, V)>, SlotCodec<C>, B>,
+    len: u64,
+}
+
+/// Encodes a slot like Borsh encodes `Option<(K, V)>`, except that the value is encoded with the
+/// codec `C`. Slots encoded with `Borsh` are the Borsh encoding of `Option<(K, V)>`.
+pub struct SlotCodec<C>(PhantomData<C>);
+
+impl<C> SlotCodec<C> {
+    fn encode_pair<K, V>(key: &K, value: &V) -> io::Result<Vec<u8>>
+    where
+        K: BorshSerialize,
+        C: Encoder<V>,
+    {
+        let mut raw = vec![1];
+        key.serialize(&mut raw)?;
+        raw.extend(C::encode(value)?);
+        Ok(raw)
+    }
+}
+
+impl<K, V, C> Encoder<Option<(K, V)>> for SlotCodec<C>
+where
+    K: BorshSerialize,
+    C: Encoder<V>,
+{
+    fn encode(slot: &Option<(K, V)>) -> io::Result<Vec<u8>> {
+        match slot {
+            Some((key, value)) => Self::encode_pair(key, value),
+            None => Ok(vec![0]),
+        }
+    }
+}
+
+impl<K, V, C> Decoder<Option<(K, V)>> for SlotCodec<C>
+where
+    K: BorshDeserialize,
+    C: Decoder<V>,
+{
+    fn decode(raw: &[u8]) -> io::Result<Option<(K, V)>> {
+        match raw.split_first() {
+            Some((0, rest)) if rest.is_empty() => Ok(None),
+            Some((1, rest)) => {
+                let mut rest = rest;
+                let key = K::deserialize(&mut rest)?;
+                Ok(Some((key, C::decode(rest)?)))
+            }
+            _ => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+}
+
+impl<K, V, C, B: Storage> Slots<K, V, C, B> {
+    pub(crate) fn new(prefix: Vec<u8>, storage: B) -> Self {
+        Self {
+            index: LookupMap::with_storage(append(&prefix, b'i'), storage.clone()),
+            entries: Vector::with_codec_and_storage(append(&prefix, b'e'), storage),
+            len: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub(crate) fn slot_count(&self) -> u64 {
+        self.entries.len()
+    }
+}
+
+impl<K, V, C> Slots<K, V, C> {
+    pub(crate) fn storage_prefixes(&self) -> Vec<&[u8]> {
+        let mut prefixes = self.index.storage_prefixes();
+        prefixes.extend(self.entries.storage_prefixes());
+        prefixes
+    }
+}
+
+impl<K, V, C, B: Storage> Slots<K, V, C, B>
+where
+    K: BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
+{
+    pub(crate) fn index_of(&self, key: &K) -> Option<u64> {
+        self.index.get(key)
+    }
+
+    pub(crate) fn get(&self, key: &K) -> Option<V> {
+        self.index_of(key).map(|index| self.get_index(index).1)
+    }
+
+    /// Returns the pair in an occupied slot.
+    fn get_index(&self, index: u64) -> (K, V) {
+        match self.entries.get(index) {
+            Some(Some(pair)) => pair,
+            _ => env::panic(ERR_INCONSISTENT_STATE),
+        }
+    }
+
+    pub(crate) fn get_slot(&self, index: u64) -> Option<(K, V)> {
+        self.entries.get(index).flatten()
+    }
+
+    /// Replaces the value of an existing key, or puts a new key into the slot chosen by `slot`,
+    /// which is called with the number of slots and returns `None` to append a new slot.
+    pub(crate) fn insert<F>(&mut self, key: &K, value: &V, slot: F) -> Option<V>
+    where
+        F: FnOnce(u64) -> Option<u64>,
+    {
+        let raw_pair = match SlotCodec::<C>::encode_pair(key, value) {
+            Ok(x) => x,
+            Err(_) => env::panic(crate::collections::ERR_ELEMENT_SERIALIZATION),
+        };
+        match self.index_of(key) {
+            Some(index) => {
+                let raw_evicted = self.entries.replace_raw(index, &raw_pair);
+                let decode = || <SlotCodec<C> as Decoder<Option<(K, V)>>>::decode(&raw_evicted);
+                match self.entries.storage().scope(decode) {
+                    Ok(Some((_, old_value))) => Some(old_value),
+                    Ok(None) => env::panic(ERR_INCONSISTENT_STATE),
+                    Err(_) => env::panic(crate::collections::ERR_ELEMENT_DESERIALIZATION),
+                }
+            }
+            None => {
+                let index = match slot(self.entries.len()) {
+                    Some(index) => {
+                        self.entries.replace_raw(index, &raw_pair);
+                        index
+                    }
+                    None => {
+                        self.entries.push_raw(&raw_pair);
+                        self.entries.len() - 1
+                    }
+                };
+                self.index.insert(key, &index);
+                self.len += 1;
+                None
+            }
+        }
+    }
+
+    /// Replaces the pair of the key with a tombstone, and returns the index of the slot and the
+    /// value.
+    pub(crate) fn remove(&mut self, key: &K) -> Option<(u64, V)> {
+        let index = self.index.remove(key)?;
+        let (_, value) = match self.entries.replace(index, &None) {
+            Some(pair) => pair,
+            None => env::panic(ERR_INCONSISTENT_STATE),
+        };
+        self.len -= 1;
+        Some((index, value))
+    }
+
+    /// Moves the pair at the slot `from` into the tombstone at the slot `to`.
+    pub(crate) fn move_slot(&mut self, from: u64, to: u64) {
+        let pair = self.entries.replace(from, &None);
+        if let Some((key, _)) = &pair {
+            self.index.insert(key, &to);
+        }
+        self.entries.replace(to, &pair);
+    }
+
+    /// Removes the slots starting from `slot_count`. The removed slots must be tombstones.
+    pub(crate) fn truncate(&mut self, slot_count: u64) {
+        self.entries.truncate(slot_count);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for (key, _) in self.entries.iter().flatten() {
+            self.index.remove_raw(&serialize(&key));
+        }
+        self.entries.clear();
+        self.len = 0;
+    }
+
+    pub(crate) fn iter(&self) -> impl DoubleEndedIterator<Item = (K, V)> + '_ {
+        self.entries.iter().flatten()
+    }
+
+    pub(crate) fn range<'a, R>(&'a self, range: R) -> impl DoubleEndedIterator<Item = (K, V)> + 'a
+    where
+        R: RangeBounds<u64> + 'a,
+    {
+        self.entries.range(range).flatten()
+    }
+}
+
+/// An iterable map that stores its content on the trie and keeps the index of every element stable
+/// until it is removed. Removed elements leave tombstones, which are reused by later insertions,
+/// so the elements are not iterated in insertion order. Use `InsertionOrderedMap` if the order
+/// matters.
+///
+/// Indices can be passed to `paginate` by readers that page through the map over several calls.
+/// Every element that stays in the map between the calls is seen exactly once.
+///
+/// Values are encoded with the codec `C`, which is Borsh by default. See `with_codec`. Keys are
+/// always serialized with Borsh. The content is stored in the storage `B`, which is the storage of
+/// the contract by default. See `with_storage`.
+///
+/// ```
+/// # use near_sdk::collections::StableMap;
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut map: StableMap<String, u64> = StableMap::new(b"s");
+/// map.insert(&"a".to_string(), &1);
+/// map.insert(&"b".to_string(), &2);
+/// map.insert(&"c".to_string(), &3);
+/// map.remove(&"a".to_string());
+/// assert_eq!(map.index_of(&"c".to_string()), Some(2));
+/// assert_eq!(map.paginate(1, 2).collect::<Vec<_>>(), vec![("b".to_string(), 2), ("c".to_string(), 3)]);
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct StableMap<K, V, C = Borsh, B: Storage = EnvStorage> {
+    slots: Slots<K, V, C, B>,
+    free: Vector<u64, Borsh, B>,
+}
+
+impl<K, V> StableMap<K, V, Borsh> {
+    /// Create new map with zero elements. Use `prefix` as a unique identifier.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec(prefix)
+    }
+}
+
+impl<K, V, B: Storage> StableMap<K, V, Borsh, B> {
+    /// Create new map with zero elements that stores its content in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+}
+
+impl<K, V, C, B: Storage> StableMap<K, V, C, B> {
+    /// Create new map with zero elements that encodes its values with the codec `C`. Use `prefix`
+    /// as a unique identifier.
+    pub fn with_codec<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, B::default())
+    }
+
+    /// Create new map with zero elements that encodes its values with the codec `C` and stores its
+    /// content in `storage`. Use `prefix` as a unique identifier in the storage.
+    pub fn with_codec_and_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        let prefix = prefix.into_storage_key();
+        Self {
+            free: Vector::with_storage(append(&prefix, b'f'), storage.clone()),
+            slots: Slots::new(prefix, storage),
+        }
+    }
+
+    /// Returns the number of elements in the map, also referred to as its size.
+    pub fn len(&self) -> u64 {
+        self.slots.len()
+    }
+
+    /// Returns `true` if the map contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.slots.len() == 0
+    }
+
+    /// Returns the number of slots, including the tombstones. All indices are less than the
+    /// number of slots.
+    pub fn slot_count(&self) -> u64 {
+        self.slots.slot_count()
+    }
+}
+
+impl<K, V, C, B: Storage> StableMap<K, V, C, B>
+where
+    K: BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
+{
+    /// Returns the value corresponding to the key.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.slots.get(key)
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.slots.index_of(key).is_some()
+    }
+
+    /// Returns the index of the key, which does not change until the key is removed or the map is
+    /// compacted.
+    pub fn index_of(&self, key: &K) -> Option<u64> {
+        self.slots.index_of(key)
+    }
+
+    /// Returns the key-value pair at the index, or `None` if the slot is a tombstone or the index
+    /// is out of bounds.
+    pub fn get_index(&self, index: u64) -> Option<(K, V)> {
+        self.slots.get_slot(index)
+    }
+
+    /// Inserts a key-value pair into the map. A new key takes the slot of the most recently removed
+    /// element, or a new slot if there are no tombstones.
+    /// If the map did not have this key present, `None` is returned. Otherwise returns
+    /// a value.
+    pub fn insert(&mut self, key: &K, value: &V) -> Option<V> {
+        let free = &mut self.free;
+        self.slots.insert(key, value, |_| free.pop())
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key was previously in the
+    /// map. Leaves a tombstone in the slot of the key.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (index, value) = self.slots.remove(key)?;
+        self.free.push(&index);
+        Some(value)
+    }
+
+    /// Clears the map, removing all elements and tombstones.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.free.clear();
+    }
+
+    /// Removes all tombstones by moving the elements from the end of the map into them. Changes
+    /// the indices of the moved elements.
+    ///
+    /// Costs a storage read for every tombstone and trailing slot, and a few storage writes for
+    /// every moved element.
+    pub fn compact(&mut self) {
+        let mut slot_count = self.slots.slot_count();
+        while let Some(tombstone) = self.free.pop() {
+            while slot_count > 0 && self.slots.get_slot(slot_count - 1).is_none() {
+                slot_count -= 1;
+            }
+            if tombstone < slot_count {
+                slot_count -= 1;
+                self.slots.move_slot(slot_count, tombstone);
+            }
+        }
+        self.slots.truncate(slot_count);
+    }
+
+    /// Iterate over deserialized keys and values in the order of their indices.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (K, V)> + '_ {
+        self.slots.iter()
+    }
+
+    /// Iterate over deserialized keys and values with indices within the given range, skipping
+    /// the tombstones. The range is clamped to the number of slots.
+    pub fn range<'a, R: RangeBounds<u64> + 'a>(
+        &'a self,
+        range: R,
+    ) -> impl DoubleEndedIterator<Item = (K, V)> + 'a {
+        self.slots.range(range)
+    }
+
+    /// Iterate over deserialized keys and values with indices from `from_index` to
+    /// `from_index + limit`, skipping the tombstones. The next page starts at `from_index + limit`.
+    pub fn paginate(
+        &self,
+        from_index: u64,
+        limit: u64,
+    ) -> impl DoubleEndedIterator<Item = (K, V)> + '_ {
+        self.slots.range(from_index..from_index.saturating_add(limit))
+    }
+
+    /// An iterator visiting all keys. The iterator element type is `K`.
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = K> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    /// An iterator visiting all values. The iterator element type is `V`.
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = V> + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+
+    /// Copies elements into an `std::vec::Vec`.
+    pub fn to_vec(&self) -> Vec<(K, V)> {
+        self.iter().collect()
+    }
+
+    pub fn extend<IT: IntoIterator<Item = (K, V)>>(&mut self, iter: IT) {
+        for (el_key, el_value) in iter {
+            self.insert(&el_key, &el_value);
+        }
+    }
+}
+
+impl<K, V, C, B: Storage> NestedCollection<B> for StableMap<K, V, C, B>
+where
+    K: BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
+{
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear();
+    }
+}
+
+impl<K, V, C> Cacheable for StableMap<K, V, C> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        let mut prefixes = self.slots.storage_prefixes();
+        prefixes.extend(self.free.storage_prefixes());
+        prefixes
+    }
+}
+
+fn serialize<T: BorshSerialize>(value: &T) -> Vec<u8> {
+    match value.try_to_vec() {
+        Ok(x) => x,
+        Err(_) => env::panic(crate::collections::ERR_ELEMENT_SERIALIZATION),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use crate::collections::StableMap;
+    use crate::env;
+    use crate::test_utils::test_env;
+    use borsh::BorshSerialize;
+    use rand::{Rng, SeedableRng};
+    use std::collections::HashMap;
+
+    #[test]
+    pub fn test_insert_remove() {
+        test_env::setup();
+        let mut map = StableMap::new(b"m");
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(0);
+        let mut baseline = HashMap::new();
+        for _ in 0..500 {
+            let key = rng.gen::<u64>() % 100;
+            let value = rng.gen::<u64>();
+            if rng.gen::<bool>() {
+                assert_eq!(map.insert(&key, &value), baseline.insert(key, value));
+            } else {
+                assert_eq!(map.remove(&key), baseline.remove(&key));
+            }
+            assert_eq!(map.len(), baseline.len() as u64);
+        }
+        for (key, value) in &baseline {
+            assert_eq!(map.get(key), Some(*value));
+            assert_eq!(map.get_index(map.index_of(key).unwrap()), Some((*key, *value)));
+        }
+        assert_eq!(map.iter().collect::<HashMap<_, _>>(), baseline);
+    }
+
+    #[test]
+    pub fn test_stable_indices() {
+        test_env::setup();
+        let mut map = StableMap::new(b"m");
+        map.extend((0..10u64).map(|x| (x, x)));
+        map.remove(&3);
+        map.remove(&7);
+        for key in (0..10u64).filter(|x| *x != 3 && *x != 7) {
+            assert_eq!(map.index_of(&key), Some(key));
+        }
+        assert_eq!(map.get_index(3), None);
+        assert_eq!(map.paginate(2, 3).map(|(k, _)| k).collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(map.slot_count(), 10);
+
+        // Tombstones are reused, the most recently removed first.
+        map.insert(&10, &10);
+        assert_eq!(map.index_of(&10), Some(7));
+        map.insert(&11, &11);
+        assert_eq!(map.index_of(&11), Some(3));
+        map.insert(&12, &12);
+        assert_eq!(map.index_of(&12), Some(10));
+    }
+
+    #[test]
+    pub fn test_compact() {
+        test_env::setup();
+        let mut map = StableMap::new(b"m");
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(1);
+        let mut baseline = HashMap::new();
+        for _ in 0..20 {
+            for _ in 0..30 {
+                let key = rng.gen::<u64>() % 50;
+                if rng.gen::<u64>() % 3 == 0 {
+                    assert_eq!(map.insert(&key, &key), baseline.insert(key, key));
+                } else {
+                    assert_eq!(map.remove(&key), baseline.remove(&key));
+                }
+            }
+            map.compact();
+            assert_eq!(map.slot_count(), baseline.len() as u64);
+            assert_eq!(map.iter().collect::<HashMap<_, _>>(), baseline);
+            for (index, (key, _)) in map.iter().enumerate() {
+                assert_eq!(map.index_of(&key), Some(index as u64));
+            }
+        }
+        map.clear();
+        assert!(map.is_empty());
+        assert_eq!(map.slot_count(), 0);
+        assert!(baseline.keys().all(|key| !map.contains_key(key)));
+    }
+
+    #[test]
+    pub fn test_slot_layout() {
+        test_env::setup();
+        let mut map: StableMap<u32, String> = StableMap::new(b"m");
+        map.insert(&1, &"a".to_string());
+        map.insert(&2, &"b".to_string());
+        map.remove(&1);
+        let slot = |index: u64| env::storage_read(&[&b"me"[..], &index.to_le_bytes()].concat());
+        assert_eq!(slot(0), Some(None::<(u32, String)>.try_to_vec().unwrap()));
+        assert_eq!(slot(1), Some(Some((2u32, "b".to_string())).try_to_vec().unwrap()));
+    }
+}
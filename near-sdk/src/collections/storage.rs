@@ -0,0 +1,413 @@
+//! Storage backends of the collections.
+//!
+//! The collections take a storage type parameter that reads and writes their raw key-value pairs,
+//! `EnvStorage` by default. `InMemoryStorage` keeps the pairs in a `HashMap` instead, so the same
+//! collection code can run without a blockchain, e.g. in an indexer that loads a state dump and
+//! reads the collections of a contract from it.
+//!
+//! ```
+//! # use near_sdk::collections::{InMemoryStorage, UnorderedMap};
+//! let storage = InMemoryStorage::new();
+//! let mut map: UnorderedMap<String, u64, _, InMemoryStorage> =
+//!     UnorderedMap::with_storage(b"m", storage.clone());
+//! map.insert(&"alice.near".to_string(), &10);
+//! assert_eq!(map.get(&"alice.near".to_string()), Some(10));
+//! assert_eq!(storage.len(), 3);
+//! ```
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::collections::cache;
+
+/// A raw key-value store that the collections read and write their content with. The methods have
+/// the same semantics as the storage functions of `env`: `storage_write` and `storage_remove`
+/// return `true` if they evicted a value, which can then be read once with `storage_get_evicted`.
+///
+/// Every collection keeps its own instance of the storage and clones it into the collections it is
+/// built from. Collections that are deserialized, e.g. from the state of the contract or as values
+/// of other collections, use `Default::default()`, so it has to return the storage they are read
+/// from. Collections decode every value they read within `scope` of their own storage, which
+/// storages that are not the same for every instance use to return it from `Default::default()`.
+pub trait Storage: Default + Clone {
+    fn storage_read(&self, key: &[u8]) -> Option<Vec<u8>>;
+
+    fn storage_has_key(&self, key: &[u8]) -> bool;
+
+    fn storage_write(&self, key: &[u8], value: &[u8]) -> bool;
+
+    fn storage_remove(&self, key: &[u8]) -> bool;
+
+    fn storage_get_evicted(&self) -> Option<Vec<u8>>;
+
+    /// Runs `f`, which deserializes values read from this storage. Collections within the values
+    /// have to get this storage from `Default::default()` while `f` runs. The default
+    /// implementation only runs `f`, which is enough for storages that are the same for every
+    /// instance, like `EnvStorage`.
+    fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        f()
+    }
+}
+
+/// The default storage, which uses the storage of the contract through `env`. Collections wrapped
+/// in `Cached` go through their cache.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EnvStorage;
+
+impl Storage for EnvStorage {
+    fn storage_read(&self, key: &[u8]) -> Option<Vec<u8>> {
+        cache::storage_read(key)
+    }
+
+    fn storage_has_key(&self, key: &[u8]) -> bool {
+        cache::storage_has_key(key)
+    }
+
+    fn storage_write(&self, key: &[u8], value: &[u8]) -> bool {
+        cache::storage_write(key, value)
+    }
+
+    fn storage_remove(&self, key: &[u8]) -> bool {
+        cache::storage_remove(key)
+    }
+
+    fn storage_get_evicted(&self) -> Option<Vec<u8>> {
+        cache::storage_get_evicted()
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemoryStore {
+    pairs: HashMap<Vec<u8>, Vec<u8>>,
+    evicted: Option<Vec<u8>>,
+}
+
+const ERR_NO_SCOPE: &str = "InMemoryStorage::default can only be called within InMemoryStorage::scope, since it returns the storage of the scope";
+
+thread_local! {
+    /// The stores of the running `InMemoryStorage::scope` calls, the innermost one last.
+    static SCOPED_STORES: RefCell<Vec<Rc<RefCell<MemoryStore>>>> = RefCell::new(vec![]);
+}
+
+/// Removes the store of a scope when the scope ends, even if it panics.
+struct ScopeGuard;
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        SCOPED_STORES.with(|stores| stores.borrow_mut().pop());
+    }
+}
+
+/// A storage that keeps the key-value pairs in a `HashMap` in memory. Clones of an
+/// `InMemoryStorage` share the same store, so the collections that use them need distinct prefixes
+/// just like on the trie.
+///
+/// `InMemoryStorage::new` creates an empty store. There is no store shared by default:
+/// `InMemoryStorage::default` returns the storage of the innermost running `scope` and panics
+/// outside of one. The collections decode the values they read within `scope` of their storage,
+/// so nested collections use the storage of their map. Collections deserialized from a state dump
+/// have to be deserialized within `scope` of the storage that the dump is loaded into.
+///
+/// ```
+/// # use borsh::{BorshDeserialize, BorshSerialize};
+/// # use near_sdk::collections::{Borsh, InMemoryStorage, Storage, Vector};
+/// let storage = InMemoryStorage::new();
+/// let mut vec: Vector<u64, Borsh, InMemoryStorage> = Vector::with_storage(b"v", storage.clone());
+/// vec.push(&1);
+/// let state = vec.try_to_vec().unwrap();
+///
+/// let dump = InMemoryStorage::new();
+/// dump.load(storage.to_map());
+/// let vec: Vector<u64, Borsh, InMemoryStorage> =
+///     dump.scope(|| BorshDeserialize::try_from_slice(&state)).unwrap();
+/// assert_eq!(vec.get(0), Some(1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct InMemoryStorage {
+    store: Rc<RefCell<MemoryStore>>,
+}
+
+impl InMemoryStorage {
+    /// Creates a storage with a new empty store.
+    pub fn new() -> Self {
+        Self { store: Rc::new(RefCell::new(MemoryStore::default())) }
+    }
+
+    /// Adds key-value pairs to the store, e.g. the pairs of a state dump of a contract.
+    pub fn load<I: IntoIterator<Item = (Vec<u8>, Vec<u8>)>>(&self, pairs: I) {
+        self.store.borrow_mut().pairs.extend(pairs);
+    }
+
+    /// Returns a copy of all key-value pairs in the store.
+    pub fn to_map(&self) -> HashMap<Vec<u8>, Vec<u8>> {
+        self.store.borrow().pairs.clone()
+    }
+
+    /// Returns the number of key-value pairs in the store.
+    pub fn len(&self) -> usize {
+        self.store.borrow().pairs.len()
+    }
+
+    /// Returns `true` if the store has no key-value pairs.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all key-value pairs from the store.
+    pub fn clear(&self) {
+        let mut store = self.store.borrow_mut();
+        store.pairs.clear();
+        store.evicted = None;
+    }
+
+    fn evict(&self, value: Option<Vec<u8>>) -> bool {
+        let found = value.is_some();
+        self.store.borrow_mut().evicted = value;
+        found
+    }
+}
+
+impl Default for InMemoryStorage {
+    fn default() -> Self {
+        match SCOPED_STORES.with(|stores| stores.borrow().last().cloned()) {
+            Some(store) => Self { store },
+            None => panic!("{}", ERR_NO_SCOPE),
+        }
+    }
+}
+
+impl Storage for InMemoryStorage {
+    fn storage_read(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.store.borrow().pairs.get(key).cloned()
+    }
+
+    fn storage_has_key(&self, key: &[u8]) -> bool {
+        self.store.borrow().pairs.contains_key(key)
+    }
+
+    fn storage_write(&self, key: &[u8], value: &[u8]) -> bool {
+        let evicted = self.store.borrow_mut().pairs.insert(key.to_vec(), value.to_vec());
+        self.evict(evicted)
+    }
+
+    fn storage_remove(&self, key: &[u8]) -> bool {
+        let evicted = self.store.borrow_mut().pairs.remove(key);
+        self.evict(evicted)
+    }
+
+    fn storage_get_evicted(&self) -> Option<Vec<u8>> {
+        self.store.borrow_mut().evicted.take()
+    }
+
+    fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        SCOPED_STORES.with(|stores| stores.borrow_mut().push(Rc::clone(&self.store)));
+        let _guard = ScopeGuard;
+        f()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use crate::collections::{
+        Bitset, Borsh, Deque, Identity, InMemoryStorage, LazyOption, LookupMap, LookupSet,
+        MultiMap, PackedVector, Retention, Storage, TreeMap, UnorderedMap, UnorderedSet, Vector,
+        VersionedMap,
+    };
+    use crate::test_utils::test_env;
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use rand::{Rng, SeedableRng};
+    use std::collections::{BTreeSet, HashMap, HashSet};
+
+    type InMemoryMap<K, V> = LookupMap<K, V, Identity, Borsh, InMemoryStorage>;
+
+    #[test]
+    pub fn test_in_memory_collections() {
+        let storage = InMemoryStorage::new();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(5);
+        let mut vec: Vector<u64, Borsh, InMemoryStorage> =
+            Vector::with_storage(b"v", storage.clone());
+        let mut lookup_set: LookupSet<u64, Identity, InMemoryStorage> =
+            LookupSet::with_storage(b"l", storage.clone());
+        let mut map: UnorderedMap<u64, u64, Borsh, InMemoryStorage> =
+            UnorderedMap::with_storage(b"u", storage.clone());
+        let mut sets: InMemoryMap<u64, UnorderedSet<u64, Borsh, InMemoryStorage>> =
+            LookupMap::with_storage(b"s", storage.clone());
+        let mut baseline_map = HashMap::new();
+        let mut baseline_set = HashSet::new();
+        let mut baseline_sets: HashMap<u64, BTreeSet<u64>> = HashMap::new();
+        for i in 0..500 {
+            let key = rng.gen::<u64>() % 20;
+            let value = rng.gen::<u64>() % 20;
+            vec.push(&i);
+            if rng.gen::<bool>() {
+                assert_eq!(map.insert(&key, &value), baseline_map.insert(key, value));
+                assert_eq!(lookup_set.insert(&key), baseline_set.insert(key));
+            } else {
+                assert_eq!(map.remove(&key), baseline_map.remove(&key));
+                assert_eq!(lookup_set.remove(&key), baseline_set.remove(&key));
+            }
+            let inserted = sets.entry(key).or_insert_nested().get_mut().insert(&value);
+            assert_eq!(inserted, baseline_sets.entry(key).or_default().insert(value));
+        }
+        assert_eq!(vec.iter().collect::<Vec<_>>(), (0..500).collect::<Vec<_>>());
+        assert_eq!(map.iter().collect::<HashMap<_, _>>(), baseline_map);
+        assert!(map.check_consistency().is_consistent());
+        for key in 0..20 {
+            assert_eq!(lookup_set.contains(&key), baseline_set.contains(&key));
+            let set: BTreeSet<u64> =
+                sets.get(&key).map(|set| set.iter().collect()).unwrap_or_default();
+            assert_eq!(set, baseline_sets.get(&key).cloned().unwrap_or_default());
+        }
+        map.clear();
+        vec.clear();
+        assert_eq!(map.len(), 0);
+        let nested_len: usize = baseline_sets.values().map(|set| 2 * set.len()).sum();
+        assert_eq!(storage.len(), baseline_set.len() + baseline_sets.len() + nested_len);
+    }
+
+    #[test]
+    pub fn test_independent_stores() {
+        let (first, second) = (InMemoryStorage::new(), InMemoryStorage::new());
+        let mut first_map: UnorderedMap<u64, u64, Borsh, InMemoryStorage> =
+            UnorderedMap::with_storage(b"m", first.clone());
+        let mut second_map: UnorderedMap<u64, u64, Borsh, InMemoryStorage> =
+            UnorderedMap::with_storage(b"m", second.clone());
+        first_map.insert(&1, &10);
+        second_map.insert(&1, &20);
+        second_map.insert(&2, &30);
+        assert_eq!(first_map.get(&1), Some(10));
+        assert_eq!(second_map.get(&1), Some(20));
+        assert_eq!(first_map.get(&2), None);
+        assert_eq!((first.len(), second.len()), (3, 6));
+
+        second.clear();
+        assert_eq!(first_map.get(&1), Some(10));
+    }
+
+    #[test]
+    pub fn test_nested_collections_in_independent_stores() {
+        type Sets = InMemoryMap<u64, UnorderedSet<u64, Borsh, InMemoryStorage>>;
+        type Vectors =
+            UnorderedMap<u64, Vector<u64, Borsh, InMemoryStorage>, Borsh, InMemoryStorage>;
+        let (first, second) = (InMemoryStorage::new(), InMemoryStorage::new());
+        let mut first_sets: Sets = LookupMap::with_storage(b"s", first.clone());
+        let mut second_sets: Sets = LookupMap::with_storage(b"s", second.clone());
+        let mut first_vectors: Vectors = UnorderedMap::with_storage(b"v", first.clone());
+        let mut second_vectors: Vectors = UnorderedMap::with_storage(b"v", second.clone());
+        for i in 0..10 {
+            first_sets.entry(i % 2).or_insert_nested().get_mut().insert(&i);
+            second_sets.entry(i % 3).or_insert_nested().get_mut().insert(&(i * 10));
+            first_vectors.entry(i % 2).or_insert_nested().get_mut().push(&i);
+            second_vectors.entry(i % 3).or_insert_nested().get_mut().push(&(i * 10));
+        }
+
+        // The nested collections are read back with the storage of their map.
+        assert_eq!(first_sets.get(&1).unwrap().to_vec(), vec![1, 3, 5, 7, 9]);
+        assert_eq!(second_sets.get(&1).unwrap().to_vec(), vec![10, 40, 70]);
+        assert_eq!(first_vectors.get(&0).unwrap().to_vec(), vec![0, 2, 4, 6, 8]);
+        assert_eq!(second_vectors.get(&2).unwrap().to_vec(), vec![20, 50, 80]);
+        assert!(first_sets.get(&2).is_none());
+        assert_eq!(second_vectors.values().map(|vec| vec.len()).sum::<u64>(), 10);
+
+        // Removing the nested collections of one store leaves the other store intact.
+        for key in 0..3 {
+            second_sets.remove_nested(&key);
+            second_vectors.remove_nested(&key);
+        }
+        assert!(second.is_empty());
+        assert_eq!(first_sets.get(&0).unwrap().to_vec(), vec![0, 2, 4, 6, 8]);
+        assert_eq!(first_vectors.get(&1).unwrap().to_vec(), vec![1, 3, 5, 7, 9]);
+
+        // A deserialized map reads its nested collections from the store it is deserialized in.
+        let state = first_sets.try_to_vec().unwrap();
+        second.load(first.to_map());
+        first.clear();
+        let sets: Sets = second.scope(|| BorshDeserialize::try_from_slice(&state)).unwrap();
+        let mut set = sets.get(&1).unwrap();
+        set.insert(&11);
+        assert_eq!(set.len(), 6);
+        assert!(first.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "InMemoryStorage::default can only be called within")]
+    pub fn test_default_outside_of_scope() {
+        let storage = InMemoryStorage::new();
+        let mut vec: Vector<u64, Borsh, InMemoryStorage> = Vector::with_storage(b"v", storage);
+        vec.push(&1);
+        let state = vec.try_to_vec().unwrap();
+        let _: Vector<u64, Borsh, InMemoryStorage> =
+            BorshDeserialize::try_from_slice(&state).unwrap();
+    }
+
+    #[test]
+    pub fn test_in_memory_composite_collections() {
+        test_env::setup();
+        let storage = InMemoryStorage::new();
+        let mut tree: TreeMap<u64, u64, Borsh, InMemoryStorage> =
+            TreeMap::with_storage(b"t", storage.clone());
+        let mut deque: Deque<u64, Borsh, InMemoryStorage> =
+            Deque::with_storage(b"d", storage.clone());
+        let mut packed: PackedVector<u64, Borsh, InMemoryStorage> =
+            PackedVector::with_storage(b"p", storage.clone());
+        let mut bitset: Bitset<InMemoryStorage> = Bitset::with_storage(b"b", storage.clone());
+        let mut multi_map: MultiMap<u64, u64, InMemoryStorage> =
+            MultiMap::with_storage(b"m", storage.clone());
+        let mut versioned: VersionedMap<u64, u64, InMemoryStorage> =
+            VersionedMap::with_storage(b"v", Retention::All, storage.clone());
+        for i in 0..50 {
+            tree.insert(&(i * 7 % 50), &i);
+            deque.push_front(&i);
+            packed.push(&i);
+            bitset.set(i * 3);
+            multi_map.insert(&(i % 5), &i);
+            versioned.insert(&(i % 5), &i);
+        }
+        assert_eq!(tree.keys().collect::<Vec<_>>(), (0..50).collect::<Vec<_>>());
+        assert_eq!(deque.iter().collect::<Vec<_>>(), (0..50).rev().collect::<Vec<_>>());
+        assert_eq!(packed.iter().collect::<Vec<_>>(), (0..50).collect::<Vec<_>>());
+        assert_eq!(bitset.iter().collect::<Vec<_>>(), (0..50).map(|i| i * 3).collect::<Vec<_>>());
+        assert_eq!(multi_map.get(&2), (0..50).filter(|i| i % 5 == 2).collect::<Vec<_>>());
+        assert_eq!(versioned.get(&2), Some(47));
+
+        // The nested sets and histories are stored in the storage of their map.
+        assert_eq!(multi_map.remove_all(&2), 10);
+        assert!(versioned.remove_history(&2));
+        tree.clear();
+        deque.clear();
+        packed.clear();
+        bitset.clear_all();
+        for key in 0..5 {
+            multi_map.remove_all(&key);
+            versioned.remove_history(&key);
+        }
+        assert!(storage.is_empty());
+    }
+
+    #[test]
+    pub fn test_load_dump() {
+        let storage = InMemoryStorage::new();
+        let mut map: UnorderedMap<String, u64, Borsh, InMemoryStorage> =
+            UnorderedMap::with_storage(b"m", storage.clone());
+        map.insert(&"alice.near".to_string(), &10);
+        map.insert(&"bob.near".to_string(), &20);
+        let option: LazyOption<String, Borsh, InMemoryStorage> =
+            LazyOption::with_storage(b"o", Some(&"value".to_string()), storage.clone());
+        let (map_state, option_state) = (map.try_to_vec().unwrap(), option.try_to_vec().unwrap());
+        let dump = storage.to_map();
+        assert_eq!(storage.len(), 7);
+
+        // Deserialized collections read from the storage of the scope they are deserialized in.
+        let dump_storage = InMemoryStorage::new();
+        dump_storage.load(dump);
+        storage.clear();
+        let map: UnorderedMap<String, u64, Borsh, InMemoryStorage> =
+            dump_storage.scope(|| BorshDeserialize::try_from_slice(&map_state)).unwrap();
+        let option: LazyOption<String, Borsh, InMemoryStorage> =
+            dump_storage.scope(|| BorshDeserialize::try_from_slice(&option_state)).unwrap();
+        assert_eq!(map.get(&"bob.near".to_string()), Some(20));
+        assert_eq!(map.len(), 2);
+        assert_eq!(option.get(), Some("value".to_string()));
+    }
+}
@@ -1,9 +1,16 @@
 use borsh::{BorshDeserialize, BorshSerialize};
-use std::ops::Bound;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::ops::{Bound, RangeBounds};
 
-use crate::collections::LookupMap;
-use crate::collections::{append, Vector};
-use crate::IntoStorageKey;
+use crate::collections::cache::Cacheable;
+use crate::collections::consistency::{ConsistencyReport, Inconsistency};
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{append, NestedCollection, Vector, ERR_INCONSISTENT_STATE};
+use crate::collections::{Borsh, Decoder, Encoder, Identity, LookupMap};
+use crate::{env, IntoStorageKey};
+
+const ERR_UNSORTED_KEYS: &[u8] = b"Keys must be strictly increasing";
 
 /// TreeMap based on AVL-tree
 ///
@@ -13,21 +20,51 @@ use crate::IntoStorageKey;
 /// - `min`/`max`:              O(log(N))
 /// - `above`/`below`:          O(log(N))
 /// - `range` of K elements:    O(Klog(N))
+/// - `rank`/`select`:          O(log(N))
+/// - `from_sorted_iter`:       O(N)
+///
+/// `keys` and `keys_range` read only the nodes of the tree, not the values.
+///
+/// Maps created before the nodes stored the sizes of their subtrees can be used as they are, but
+/// `rank`, `select` and `nth` of their iterators read every node of the subtrees whose sizes are
+/// not stored yet. Migrate them with `migrate_subtree_sizes`, or over several calls with
+/// `migrate_subtree_sizes_in_chunks`, to store the sizes.
 ///
+/// Values are encoded with the codec `C`, which is Borsh by default. See `with_codec`. Keys are
+/// always serialized with Borsh. The content is stored in the storage `B`, which is the storage of
+/// the contract by default. See `with_storage`.
 #[derive(BorshSerialize, BorshDeserialize)]
-pub struct TreeMap<K, V> {
+pub struct TreeMap<K, V, C = Borsh, B: Storage = EnvStorage> {
     root: u64,
-    val: LookupMap<K, V>,
-    tree: Vector<Node<K>>,
+    val: LookupMap<K, V, Identity, C, B>,
+    tree: Vector<Node<K>, Borsh, B>,
 }
 
-#[derive(Clone, BorshSerialize, BorshDeserialize)]
+#[derive(Clone, BorshSerialize)]
 pub struct Node<K> {
     id: u64,
     key: K,           // key stored in a node
     lft: Option<u64>, // left link of a node
     rgt: Option<u64>, // right link of a node
     ht: u64,          // height of a subtree at a node
+    sz: u64,          // number of nodes in a subtree at a node, or UNKNOWN_SIZE
+}
+
+/// The size of a node that has the legacy layout without the size of its subtree, or a parent of
+/// such a node, until the map is migrated with `migrate_subtree_sizes`.
+const UNKNOWN_SIZE: u64 = 0;
+
+impl<K: BorshDeserialize> BorshDeserialize for Node<K> {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        let id = u64::deserialize(buf)?;
+        let key = K::deserialize(buf)?;
+        let lft = Option::<u64>::deserialize(buf)?;
+        let rgt = Option::<u64>::deserialize(buf)?;
+        let ht = u64::deserialize(buf)?;
+        // Nodes stored before the sizes of subtrees end with the height.
+        let sz = if buf.is_empty() { UNKNOWN_SIZE } else { u64::deserialize(buf)? };
+        Ok(Self { id, key, lft, rgt, ht, sz })
+    }
 }
 
 impl<K> Node<K>
@@ -35,24 +72,113 @@ where
     K: Ord + Clone + BorshSerialize + BorshDeserialize,
 {
     fn of(id: u64, key: K) -> Self {
-        Self { id, key, lft: None, rgt: None, ht: 1 }
+        Self { id, key, lft: None, rgt: None, ht: 1, sz: 1 }
     }
 }
 
-impl<K, V> TreeMap<K, V>
+impl<K, V> TreeMap<K, V, Borsh>
 where
     K: Ord + Clone + BorshSerialize + BorshDeserialize,
     V: BorshSerialize + BorshDeserialize,
 {
     pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec(prefix)
+    }
+
+    /// Creates a map from entries sorted by key in strictly increasing order. Builds a balanced
+    /// tree directly, writing every node and value once instead of rebalancing on every insert.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keys are not strictly increasing.
+    pub fn from_sorted_iter<S, I>(prefix: S, iter: I) -> Self
+    where
+        S: IntoStorageKey,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = Self::new(prefix);
+        let mut nodes: Vec<Node<K>> = vec![];
+        for (key, value) in iter {
+            if let Some(last) = nodes.last() {
+                if last.key >= key {
+                    env::panic(ERR_UNSORTED_KEYS);
+                }
+            }
+            map.val.insert(&key, &value);
+            nodes.push(Node::of(nodes.len() as u64, key));
+        }
+        // Node ids are the in-order positions of the keys, so the root of a subtree over a range of
+        // positions is the node in its middle.
+        fn link<K>(nodes: &mut [Node<K>], lo: usize, hi: usize) -> Option<u64> {
+            if lo >= hi {
+                return None;
+            }
+            let mid = lo + (hi - lo) / 2;
+            let lft = link(nodes, lo, mid);
+            let rgt = link(nodes, mid + 1, hi);
+            let child = |id: Option<u64>| {
+                id.map_or((0, 0), |id| (nodes[id as usize].ht, nodes[id as usize].sz))
+            };
+            let ((lft_ht, lft_sz), (rgt_ht, rgt_sz)) = (child(lft), child(rgt));
+            let node = &mut nodes[mid];
+            node.lft = lft;
+            node.rgt = rgt;
+            node.ht = 1 + lft_ht.max(rgt_ht);
+            node.sz = 1 + lft_sz + rgt_sz;
+            Some(mid as u64)
+        }
+        let len = nodes.len();
+        map.root = link(&mut nodes, 0, len).unwrap_or(0);
+        for node in &nodes {
+            map.tree.push(node);
+        }
+        map
+    }
+}
+
+impl<K, V, B: Storage> TreeMap<K, V, Borsh, B>
+where
+    K: Ord + Clone + BorshSerialize + BorshDeserialize,
+    V: BorshSerialize + BorshDeserialize,
+{
+    /// Create a new map that stores its content in `storage`. Use `prefix` as a unique identifier
+    /// in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+}
+
+impl<K, V, C, B: Storage> TreeMap<K, V, C, B>
+where
+    K: Ord + Clone + BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
+{
+    /// Create a new map that encodes its values with the codec `C`. Use `prefix` as a unique
+    /// identifier on the trie.
+    pub fn with_codec<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, B::default())
+    }
+
+    /// Create a new map that encodes its values with the codec `C` and stores its content in
+    /// `storage`. Use `prefix` as a unique identifier in the storage.
+    pub fn with_codec_and_storage<S>(prefix: S, storage: B) -> Self
     where
         S: IntoStorageKey,
     {
         let prefix = prefix.into_storage_key();
         Self {
             root: 0,
-            val: LookupMap::new(append(&prefix, b'v')),
-            tree: Vector::new(append(&prefix, b'n')),
+            val: LookupMap::with_codec_and_storage(append(&prefix, b'v'), storage.clone()),
+            tree: Vector::with_storage(append(&prefix, b'n'), storage),
         }
     }
 
@@ -68,6 +194,27 @@ where
         self.tree.clear();
     }
 
+    /// Removes at most `max_items` entries from the map, so that a large map can be cleared over
+    /// several calls without running out of gas. Returns `true` if the map is empty.
+    ///
+    /// The nodes are removed without rebalancing the tree, so until this method returns `true`
+    /// the map can only be cleared further.
+    pub fn clear_in_chunks(&mut self, max_items: u64) -> bool {
+        let len = self.len();
+        let count = max_items.min(len);
+        for id in len - count..len {
+            if let Some(n) = self.node(id) {
+                self.val.remove(&n.key);
+            }
+        }
+        if self.tree.clear_in_chunks(count) {
+            self.root = 0;
+            true
+        } else {
+            false
+        }
+    }
+
     fn node(&self, id: u64) -> Option<Node<K>> {
         self.tree.get(id)
     }
@@ -169,26 +316,245 @@ where
     ///
     /// Panics if range start > end.
     /// Panics if range start == end and both bounds are Excluded.
-    pub fn range<'a>(&'a self, r: (Bound<K>, Bound<K>)) -> impl Iterator<Item = (K, V)> + 'a {
-        let (lo, hi) = match r {
+    pub fn range<'a, R: RangeBounds<K>>(&'a self, r: R) -> impl Iterator<Item = (K, V)> + 'a {
+        let (lo, hi) = Self::bounds(r);
+        Cursor::range(&self, lo, hi).into_iter()
+    }
+
+    /// Iterate all keys in ascending order without reading the values.
+    pub fn keys<'a>(&'a self) -> impl Iterator<Item = K> + 'a {
+        Keys { cursor: Cursor::asc(&self) }
+    }
+
+    /// Iterate keys in ascending order according to specified bounds, without reading the values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if range start > end.
+    /// Panics if range start == end and both bounds are Excluded.
+    pub fn keys_range<'a, R: RangeBounds<K>>(&'a self, r: R) -> impl Iterator<Item = K> + 'a {
+        let (lo, hi) = Self::bounds(r);
+        Keys { cursor: Cursor::range(&self, lo, hi) }
+    }
+
+    fn bounds<R: RangeBounds<K>>(r: R) -> (Bound<K>, Bound<K>) {
+        fn cloned<K: Clone>(bound: Bound<&K>) -> Bound<K> {
+            match bound {
+                Bound::Included(x) => Bound::Included(x.clone()),
+                Bound::Excluded(x) => Bound::Excluded(x.clone()),
+                Bound::Unbounded => Bound::Unbounded,
+            }
+        }
+        match (cloned(r.start_bound()), cloned(r.end_bound())) {
             (Bound::Included(a), Bound::Included(b)) if a > b => panic!("Invalid range."),
             (Bound::Excluded(a), Bound::Included(b)) if a > b => panic!("Invalid range."),
             (Bound::Included(a), Bound::Excluded(b)) if a > b => panic!("Invalid range."),
             (Bound::Excluded(a), Bound::Excluded(b)) if a == b => panic!("Invalid range."),
             (lo, hi) => (lo, hi),
-        };
-
-        Cursor::range(&self, lo, hi).into_iter()
+        }
     }
 
     pub fn to_vec(&self) -> Vec<(K, V)> {
         self.iter().collect()
     }
 
+    /// Returns the number of keys that are strictly less than the key given as the parameter.
+    /// The key itself does not need to be present in the map.
+    pub fn rank(&self, key: &K) -> u64 {
+        let mut rank = 0;
+        let mut at = if self.len() > 0 { Some(self.root) } else { None };
+        while let Some(node) = at.and_then(|id| self.node(id)) {
+            if key.le(&node.key) {
+                if key.eq(&node.key) {
+                    return rank + self.size(node.lft);
+                }
+                at = node.lft;
+            } else {
+                rank += self.size(node.lft) + 1;
+                at = node.rgt;
+            }
+        }
+        rank
+    }
+
+    /// Returns the key at the given position in ascending order, starting from zero,
+    /// or `None` if the position is out of bounds.
+    pub fn select(&self, mut index: u64) -> Option<K> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut at = Some(self.root);
+        while let Some(node) = at.and_then(|id| self.node(id)) {
+            let lft_size = self.size(node.lft);
+            match index.cmp(&lft_size) {
+                Ordering::Less => at = node.lft,
+                Ordering::Equal => return Some(node.key),
+                Ordering::Greater => {
+                    index -= lft_size + 1;
+                    at = node.rgt;
+                }
+            }
+        }
+        env::panic(ERR_INCONSISTENT_STATE)
+    }
+
+    /// Stores the sizes of the subtrees in the nodes of a map that was created before the nodes
+    /// stored them. Does nothing for the nodes that are already migrated, so it is safe to call it
+    /// more than once.
+    ///
+    /// Reads and writes every node of the map, use `migrate_subtree_sizes_in_chunks` for maps that
+    /// are too large to be migrated in a single call.
+    pub fn migrate_subtree_sizes(&mut self) {
+        self.migrate_subtree_sizes_in_chunks(u64::MAX);
+    }
+
+    /// Migrates the subtree sizes of at most `max_items` nodes, and returns `true` once all the
+    /// nodes are migrated. The nodes are migrated in post-order, so the children of a node are
+    /// always migrated before it.
+    ///
+    /// Costs `O(log(N))` storage reads to find the first node that is not migrated, and a few reads
+    /// and a write per migrated node. Unlike the migrations that use a `ChunkCursor`, the progress
+    /// is kept in the nodes themselves, so the map can be modified between the calls.
+    ///
+    /// ```
+    /// # use near_sdk::collections::TreeMap;
+    /// # near_sdk::test_utils::test_env::setup();
+    /// let mut map: TreeMap<u64, u64> = TreeMap::new(b"t");
+    /// // Each call can happen in a separate transaction.
+    /// while !map.migrate_subtree_sizes_in_chunks(100) {}
+    /// ```
+    pub fn migrate_subtree_sizes_in_chunks(&mut self, max_items: u64) -> bool {
+        let mut count = max_items;
+
+        // The path from the root to the node that is migrated next. The parents of a node without
+        // its size don't have their sizes either, so every node on the path is without its size.
+        let mut path: Vec<Node<K>> = Vec::new();
+        if self.len() > 0 {
+            path.extend(self.unsized_node(self.root));
+        }
+        while count > 0 {
+            let next = match path.last() {
+                Some(node) => {
+                    node.lft.into_iter().chain(node.rgt).find_map(|id| self.unsized_node(id))
+                }
+                None => break,
+            };
+            match next {
+                Some(child) => path.push(child),
+                None => {
+                    if let Some(mut node) = path.pop() {
+                        // The children of the node are already migrated.
+                        node.sz = 1 + self.size(node.lft) + self.size(node.rgt);
+                        self.save(&node);
+                        count -= 1;
+                    }
+                }
+            }
+        }
+        path.is_empty()
+    }
+
+    /// Walks the tree from its root and reports nodes that are missing or linked more than once,
+    /// keys that are out of order or have no value, nodes with wrong ids, heights or subtree sizes,
+    /// nodes that violate the AVL balance, and nodes that are not reachable from the root.
+    ///
+    /// Costs two storage reads per node and keeps every node in memory, so it is meant for tests
+    /// and diagnostic view methods. The sizes of nodes that are not migrated with
+    /// `migrate_subtree_sizes` are not checked.
+    pub fn check_consistency(&self) -> ConsistencyReport {
+        let mut report = ConsistencyReport::default();
+        let len = self.len();
+        if len == 0 {
+            return report;
+        }
+
+        // Nodes in pre-order, checked against the bounds on their keys set by their ancestors.
+        let mut nodes: Vec<(u64, Node<K>)> = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![(self.root, Bound::Unbounded, Bound::Unbounded)];
+        while let Some((id, lo, hi)) = stack.pop() {
+            if !visited.insert(id) {
+                report.push(Inconsistency::DuplicateLink { id });
+                continue;
+            }
+            let node = match self
+                .tree
+                .try_get_raw(id)
+                .and_then(|raw_node| Node::<K>::try_from_slice(&raw_node).ok())
+            {
+                Some(node) => node,
+                None => {
+                    report.push(Inconsistency::MissingNode { id });
+                    continue;
+                }
+            };
+            report.checked += 1;
+            if node.id != id {
+                report.push(Inconsistency::WrongId { id, stored: node.id });
+            }
+            if !fits(&node.key, &lo, &hi) {
+                report.push(Inconsistency::UnorderedKey { id });
+            }
+            if !self.val.contains_key(&node.key) {
+                report.push(Inconsistency::MissingValue { index: id });
+            }
+            if let Some(rgt) = node.rgt {
+                stack.push((rgt, Bound::Excluded(node.key.clone()), hi));
+            }
+            if let Some(lft) = node.lft {
+                stack.push((lft, lo, Bound::Excluded(node.key.clone())));
+            }
+            nodes.push((id, node));
+        }
+        if report.checked != len {
+            report.push(Inconsistency::LengthMismatch { expected: len, actual: report.checked });
+        }
+
+        // Children come after their parents in pre-order, so in reverse they are measured first.
+        let mut measured: HashMap<u64, (u64, u64)> = HashMap::new();
+        for (id, node) in nodes.iter().rev() {
+            let measure =
+                |at: Option<u64>| at.and_then(|id| measured.get(&id)).copied().unwrap_or_default();
+            let (lft_ht, lft_sz) = measure(node.lft);
+            let (rgt_ht, rgt_sz) = measure(node.rgt);
+            let (ht, sz) = (1 + lft_ht.max(rgt_ht), 1 + lft_sz + rgt_sz);
+            if node.ht != ht {
+                report.push(Inconsistency::WrongHeight { id: *id, stored: node.ht, actual: ht });
+            }
+            if node.sz != sz && node.sz != UNKNOWN_SIZE {
+                report.push(Inconsistency::WrongSize { id: *id, stored: node.sz, actual: sz });
+            }
+            let balance = lft_ht as i64 - rgt_ht as i64;
+            if balance.abs() > 1 {
+                report.push(Inconsistency::UnbalancedNode { id: *id, balance });
+            }
+            measured.insert(*id, (ht, sz));
+        }
+        report
+    }
+
     //
     // Internal utilities
     //
 
+    /// Returns the number of nodes in the subtree at node `at`. Counts the nodes of the subtree if
+    /// its size is not stored yet.
+    fn size(&self, at: Option<u64>) -> u64 {
+        match at.and_then(|id| self.node(id)) {
+            Some(node) if node.sz == UNKNOWN_SIZE => 1 + self.size(node.lft) + self.size(node.rgt),
+            Some(node) => node.sz,
+            None => 0,
+        }
+    }
+
+    /// Returns the node `id` if the size of its subtree is not stored yet.
+    fn unsized_node(&self, id: u64) -> Option<Node<K>> {
+        match self.node(id) {
+            Some(node) => Some(node).filter(|node| node.sz == UNKNOWN_SIZE),
+            None => env::panic(ERR_INCONSISTENT_STATE),
+        }
+    }
+
     /// Returns (node, parent node) of left-most lower (min) node starting from given node `at`.
     /// As min_at only traverses the tree down, if a node `at` is the minimum node in a subtree,
     /// its parent must be explicitly provided in advance.
@@ -227,6 +593,27 @@ where
         }
     }
 
+    // Returns the smallest key that is greater or equal to `key`, reading only the nodes.
+    fn at_or_above_at(&self, mut at: u64, key: &K) -> Option<K> {
+        let mut seen: Option<K> = None;
+        while let Some(node) = self.node(at) {
+            let next = if node.key.lt(key) {
+                node.rgt
+            } else if node.key.eq(key) {
+                return Some(node.key);
+            } else {
+                let lft = node.lft;
+                seen = Some(node.key);
+                lft
+            };
+            match next {
+                Some(id) => at = id,
+                None => break,
+            }
+        }
+        seen
+    }
+
     fn above_at(&self, mut at: u64, key: &K) -> Option<K> {
         let mut seen: Option<K> = None;
         loop {
@@ -308,13 +695,22 @@ where
         }
     }
 
-    // Calculate and save the height of a subtree at node `at`:
+    // Calculate and save the height and the size of a subtree at node `at`:
     // height[at] = 1 + max(height[at.L], height[at.R])
+    // size[at] = 1 + size[at.L] + size[at.R], unknown if the size of a child is unknown
     fn update_height(&mut self, node: &mut Node<K>) {
-        let lft = node.lft.and_then(|id| self.node(id).map(|n| n.ht)).unwrap_or_default();
-        let rgt = node.rgt.and_then(|id| self.node(id).map(|n| n.ht)).unwrap_or_default();
-
-        node.ht = 1 + std::cmp::max(lft, rgt);
+        let lft = node.lft.and_then(|id| self.node(id));
+        let rgt = node.rgt.and_then(|id| self.node(id));
+        let lft_ht = lft.as_ref().map(|n| n.ht).unwrap_or_default();
+        let rgt_ht = rgt.as_ref().map(|n| n.ht).unwrap_or_default();
+        let lft_sz = lft.map(|n| n.sz);
+        let rgt_sz = rgt.map(|n| n.sz);
+
+        node.ht = 1 + std::cmp::max(lft_ht, rgt_ht);
+        node.sz = match (lft_sz, rgt_sz) {
+            (Some(UNKNOWN_SIZE), _) | (_, Some(UNKNOWN_SIZE)) => UNKNOWN_SIZE,
+            _ => 1 + lft_sz.unwrap_or_default() + rgt_sz.unwrap_or_default(),
+        };
         self.save(&node);
     }
 
@@ -590,37 +986,77 @@ where
     }
 }
 
-impl<'a, K, V> IntoIterator for &'a TreeMap<K, V>
+impl<K, V, C, B: Storage> NestedCollection<B> for TreeMap<K, V, C, B>
 where
     K: Ord + Clone + BorshSerialize + BorshDeserialize,
-    V: BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
+{
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear();
+    }
+}
+
+impl<K, V, C> Cacheable for TreeMap<K, V, C> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        let mut prefixes = self.val.storage_prefixes();
+        prefixes.extend(self.tree.storage_prefixes());
+        prefixes
+    }
+}
+
+impl<'a, K, V, C, B: Storage> IntoIterator for &'a TreeMap<K, V, C, B>
+where
+    K: Ord + Clone + BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
 {
     type Item = (K, V);
-    type IntoIter = Cursor<'a, K, V>;
+    type IntoIter = Cursor<'a, K, V, C, B>;
 
     fn into_iter(self) -> Self::IntoIter {
         Cursor::asc(self)
     }
 }
 
-impl<K, V> Iterator for Cursor<'_, K, V>
+impl<K, V, C, B: Storage> Iterator for Cursor<'_, K, V, C, B>
 where
     K: Ord + Clone + BorshSerialize + BorshDeserialize,
-    V: BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
 {
     type Item = (K, V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let this_key = self.key.clone();
+        self.next_key().and_then(|k| self.map.get(&k).map(|v| (k, v)))
+    }
 
-        let next_key = self
-            .key
-            .take()
-            .and_then(|k| if self.asc { self.map.higher(&k) } else { self.map.lower(&k) })
-            .filter(|k| fits(k, &self.lo, &self.hi));
-        self.key = next_key;
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.skip_keys(n);
+        self.next()
+    }
+}
 
-        this_key.and_then(|k| self.map.get(&k).map(|v| (k, v)))
+/// Iterator over the keys of a `TreeMap`, which reads only the nodes of the tree.
+pub struct Keys<'a, K, V, C = Borsh, B: Storage = EnvStorage> {
+    cursor: Cursor<'a, K, V, C, B>,
+}
+
+impl<K, V, C, B: Storage> Iterator for Keys<'_, K, V, C, B>
+where
+    K: Ord + Clone + BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
+{
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next_key()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.cursor.skip_keys(n);
+        self.cursor.next_key()
     }
 }
 
@@ -636,55 +1072,91 @@ fn fits<K: Ord>(key: &K, lo: &Bound<K>, hi: &Bound<K>) -> bool {
     })
 }
 
-pub struct Cursor<'a, K, V> {
+pub struct Cursor<'a, K, V, C = Borsh, B: Storage = EnvStorage> {
     asc: bool,
     lo: Bound<K>,
     hi: Bound<K>,
     key: Option<K>,
-    map: &'a TreeMap<K, V>,
+    map: &'a TreeMap<K, V, C, B>,
 }
 
-impl<'a, K, V> Cursor<'a, K, V>
+impl<'a, K, V, C, B: Storage> Cursor<'a, K, V, C, B>
 where
     K: Ord + Clone + BorshSerialize + BorshDeserialize,
-    V: BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
 {
-    fn asc(map: &'a TreeMap<K, V>) -> Self {
+    fn asc(map: &'a TreeMap<K, V, C, B>) -> Self {
         let key: Option<K> = map.min();
         Self { asc: true, key, lo: Bound::Unbounded, hi: Bound::Unbounded, map }
     }
 
-    fn asc_from(map: &'a TreeMap<K, V>, key: K) -> Self {
+    fn asc_from(map: &'a TreeMap<K, V, C, B>, key: K) -> Self {
         let key = map.higher(&key);
         Self { asc: true, key, lo: Bound::Unbounded, hi: Bound::Unbounded, map }
     }
 
-    fn desc(map: &'a TreeMap<K, V>) -> Self {
+    fn desc(map: &'a TreeMap<K, V, C, B>) -> Self {
         let key: Option<K> = map.max();
         Self { asc: false, key, lo: Bound::Unbounded, hi: Bound::Unbounded, map }
     }
 
-    fn desc_from(map: &'a TreeMap<K, V>, key: K) -> Self {
+    fn desc_from(map: &'a TreeMap<K, V, C, B>, key: K) -> Self {
         let key = map.lower(&key);
         Self { asc: false, key, lo: Bound::Unbounded, hi: Bound::Unbounded, map }
     }
 
-    fn range(map: &'a TreeMap<K, V>, lo: Bound<K>, hi: Bound<K>) -> Self {
+    fn range(map: &'a TreeMap<K, V, C, B>, lo: Bound<K>, hi: Bound<K>) -> Self {
         let key = match &lo {
-            Bound::Included(k) if map.contains_key(k) => Some(k.clone()),
-            Bound::Included(k) | Bound::Excluded(k) => map.higher(k),
-            _ => None,
+            Bound::Included(k) => map.at_or_above_at(map.root, k),
+            Bound::Excluded(k) => map.higher(k),
+            Bound::Unbounded => map.min(),
         };
         let key = key.filter(|k| fits(k, &lo, &hi));
 
         Self { asc: true, key, lo, hi, map }
     }
+
+    /// Returns the current key and moves to the next one, without reading any value.
+    fn next_key(&mut self) -> Option<K> {
+        let this_key = self.key.clone();
+
+        let next_key = self
+            .key
+            .take()
+            .and_then(|k| if self.asc { self.map.higher(&k) } else { self.map.lower(&k) })
+            .filter(|k| fits(k, &self.lo, &self.hi));
+        self.key = next_key;
+
+        this_key
+    }
+
+    // Skips `n` entries with a single `rank`/`select` lookup instead of visiting each of them.
+    fn skip_keys(&mut self, n: usize) {
+        if n > 0 {
+            let map = self.map;
+            let asc = self.asc;
+            self.key = self
+                .key
+                .take()
+                .and_then(|k| {
+                    let rank = map.rank(&k);
+                    if asc {
+                        rank.checked_add(n as u64)
+                    } else {
+                        rank.checked_sub(n as u64)
+                    }
+                })
+                .and_then(|index| map.select(index))
+                .filter(|k| fits(k, &self.lo, &self.hi));
+        }
+    }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::collections::Inconsistency;
     use crate::test_utils::{next_trie_id, test_env};
 
     extern crate rand;
@@ -741,6 +1213,7 @@ mod tests {
                 .field("lft", &self.lft)
                 .field("rgt", &self.rgt)
                 .field("ht", &self.ht)
+                .field("sz", &self.sz)
                 .finish()
         }
     }
@@ -1548,6 +2021,80 @@ mod tests {
         map.clear();
     }
 
+    #[test]
+    fn test_range_bounds() {
+        test_env::setup();
+        let map = TreeMap::from_sorted_iter(next_trie_id(), (0..20u32).map(|x| (x * 5, x)));
+        let baseline: BTreeMap<u32, u32> = map.iter().collect();
+        let bounds = [0, 3, 5, 37, 40, 95, 100];
+        for &a in &bounds {
+            assert_eq!(
+                map.range(..a).collect::<Vec<_>>(),
+                baseline.range(..a).map(|(&k, &v)| (k, v)).collect::<Vec<_>>()
+            );
+            assert_eq!(
+                map.range(a..).collect::<Vec<_>>(),
+                baseline.range(a..).map(|(&k, &v)| (k, v)).collect::<Vec<_>>()
+            );
+            for &b in bounds.iter().filter(|&&b| b >= a) {
+                assert_eq!(
+                    map.keys_range(a..b).collect::<Vec<_>>(),
+                    baseline.range(a..b).map(|(&k, _)| k).collect::<Vec<_>>()
+                );
+                assert_eq!(
+                    map.keys_range(a..=b).collect::<Vec<_>>(),
+                    baseline.range(a..=b).map(|(&k, _)| k).collect::<Vec<_>>()
+                );
+            }
+        }
+        assert_eq!(map.keys().collect::<Vec<_>>(), baseline.keys().cloned().collect::<Vec<_>>());
+        assert_eq!(map.keys().nth(7), Some(35));
+        assert_eq!(map.keys_range(12..).nth(2), Some(25));
+        assert_eq!(map.range(..).count(), 20);
+    }
+
+    #[test]
+    fn test_keys_skip_values() {
+        test_env::setup();
+        let map = TreeMap::from_sorted_iter(b"k".to_vec(), (0..10u32).map(|x| (x, x)));
+        for key in 0..10u32 {
+            env::storage_remove(&[&b"kv"[..], &key.to_le_bytes()].concat());
+        }
+        assert_eq!(map.keys().collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+        assert_eq!(map.keys_range(3..6).collect::<Vec<_>>(), vec![3, 4, 5]);
+        assert_eq!(map.get(&3), None);
+    }
+
+    #[test]
+    fn test_from_sorted_iter() {
+        test_env::setup();
+        for n in 0..70u32 {
+            let entries: Vec<(u32, u32)> = (0..n).map(|x| (x * 3, x)).collect();
+            let mut map = TreeMap::from_sorted_iter(next_trie_id(), entries.clone());
+            assert_eq!(map.len(), n as u64);
+            assert!(height(&map) <= max_tree_height(n as u64));
+            assert!(map.check_consistency().is_consistent());
+            assert_eq!(map.to_vec(), entries);
+            assert_eq!(map.rank(&30), 10.min(n as u64));
+
+            let mut baseline: BTreeMap<u32, u32> = entries.into_iter().collect();
+            for x in 0..n / 2 {
+                assert_eq!(map.insert(&(x * 2 + 1), &x), baseline.insert(x * 2 + 1, x));
+                assert_eq!(map.remove(&(x * 3)), baseline.remove(&(x * 3)));
+            }
+            assert!(map.check_consistency().is_consistent());
+            assert_eq!(map.to_vec(), baseline.into_iter().collect::<Vec<_>>());
+            map.clear();
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Keys must be strictly increasing")]
+    fn test_from_sorted_iter_panics_unsorted() {
+        test_env::setup();
+        let _ = TreeMap::from_sorted_iter(next_trie_id(), vec![(1u32, 1u32), (3, 3), (3, 4)]);
+    }
+
     #[test]
     #[should_panic(expected = "Invalid range.")]
     fn test_range_panics_same_excluded() {
@@ -1766,4 +2313,297 @@ mod tests {
 
         QuickCheck::new().tests(300).quickcheck(prop as Prop);
     }
+
+    #[test]
+    fn test_rank_select() {
+        test_env::setup();
+
+        let mut map: TreeMap<u32, u32> = TreeMap::new(next_trie_id());
+        assert_eq!(map.rank(&10), 0);
+        assert_eq!(map.select(0), None);
+
+        let keys: Vec<u32> = (0..30).map(|x| x * 2).collect();
+        for k in keys.iter().rev() {
+            map.insert(k, &1);
+        }
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(map.rank(k), i as u64);
+            assert_eq!(map.rank(&(k + 1)), i as u64 + 1);
+            assert_eq!(map.select(i as u64), Some(*k));
+        }
+        assert_eq!(map.select(keys.len() as u64), None);
+
+        for k in keys.iter().step_by(3) {
+            map.remove(k);
+        }
+        let left: Vec<u32> = map.iter().map(|(k, _)| k).collect();
+        for (i, k) in left.iter().enumerate() {
+            assert_eq!(map.rank(k), i as u64);
+            assert_eq!(map.select(i as u64), Some(*k));
+        }
+        map.clear();
+    }
+
+    #[test]
+    fn test_iter_nth() {
+        test_env::setup();
+
+        let mut map: TreeMap<u32, u32> = TreeMap::new(next_trie_id());
+        for k in 0..20 {
+            map.insert(&(k * 10), &k);
+        }
+
+        let mut iter = map.iter();
+        assert_eq!(iter.nth(3), Some((30, 3)));
+        assert_eq!(iter.next(), Some((40, 4)));
+        assert_eq!(iter.nth(14), Some((190, 19)));
+        assert_eq!(iter.next(), None);
+
+        let mut iter = map.iter_rev();
+        assert_eq!(iter.nth(2), Some((170, 17)));
+        assert_eq!(iter.nth(17), None);
+
+        let mut iter = map.range((Bound::Included(50), Bound::Excluded(100)));
+        assert_eq!(iter.nth(2), Some((70, 7)));
+        assert_eq!(iter.nth(2), None);
+    }
+
+    /// Rewrites the nodes of `map` in the layout used before they stored the sizes of subtrees.
+    fn into_legacy_nodes(map: &mut TreeMap<u32, u32>) {
+        #[derive(BorshSerialize)]
+        struct LegacyNode {
+            id: u64,
+            key: u32,
+            lft: Option<u64>,
+            rgt: Option<u64>,
+            ht: u64,
+        }
+
+        for id in 0..map.len() {
+            let node = map.node(id).unwrap();
+            let legacy = LegacyNode {
+                id: node.id,
+                key: node.key,
+                lft: node.lft,
+                rgt: node.rgt,
+                ht: node.ht,
+            };
+            map.tree.replace_raw(id, &legacy.try_to_vec().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_migrate_subtree_sizes() {
+        test_env::setup();
+
+        let mut map: TreeMap<u32, u32> = TreeMap::new(next_trie_id());
+        for k in random(100) {
+            map.insert(&k, &k);
+        }
+        into_legacy_nodes(&mut map);
+
+        map.migrate_subtree_sizes();
+        map.migrate_subtree_sizes();
+        for (i, (k, _)) in map.iter().enumerate() {
+            assert_eq!(map.rank(&k), i as u64);
+            assert_eq!(map.select(i as u64), Some(k));
+        }
+        assert_eq!(map.node(map.root).map(|n| n.sz), Some(map.len()));
+        map.clear();
+    }
+
+    #[test]
+    fn test_legacy_nodes_without_migration() {
+        test_env::setup();
+
+        let mut map: TreeMap<u32, u32> = TreeMap::new(next_trie_id());
+        let mut baseline = BTreeMap::new();
+        for k in random(100) {
+            map.insert(&k, &k);
+            baseline.insert(k, k);
+        }
+        into_legacy_nodes(&mut map);
+
+        // Legacy nodes are read without their sizes, which are counted when they are needed.
+        for k in random(20) {
+            map.insert(&k, &(k + 1));
+            baseline.insert(k, k + 1);
+        }
+        for k in random(50) {
+            assert_eq!(map.remove(&k), baseline.remove(&k));
+        }
+        assert!(map.check_consistency().is_consistent());
+        assert_eq!(map.to_vec(), baseline.clone().into_iter().collect::<Vec<_>>());
+        for (i, k) in baseline.keys().enumerate() {
+            assert_eq!(map.rank(k), i as u64);
+            assert_eq!(map.select(i as u64), Some(*k));
+        }
+        assert_eq!(map.iter().nth(3), baseline.clone().into_iter().nth(3));
+
+        map.migrate_subtree_sizes();
+        assert!(map.check_consistency().is_consistent());
+        assert_eq!(map.node(map.root).map(|n| n.sz), Some(map.len()));
+        map.clear();
+    }
+
+    #[test]
+    fn test_migrate_subtree_sizes_in_chunks() {
+        test_env::setup();
+
+        let mut map: TreeMap<u32, u32> = TreeMap::new(next_trie_id());
+        for k in random(100) {
+            map.insert(&k, &k);
+        }
+        into_legacy_nodes(&mut map);
+
+        let mut calls = 0;
+        while !map.migrate_subtree_sizes_in_chunks(7) {
+            calls += 1;
+        }
+        assert_eq!(calls, (map.len() - 1) / 7);
+        assert!(map.check_consistency().is_consistent());
+        for (i, (k, _)) in map.iter().enumerate() {
+            assert_eq!(map.rank(&k), i as u64);
+            assert_eq!(map.select(i as u64), Some(k));
+        }
+
+        // A migrated map is left as it is.
+        assert!(map.migrate_subtree_sizes_in_chunks(1));
+        assert!(map.check_consistency().is_consistent());
+
+        let mut empty: TreeMap<u32, u32> = TreeMap::new(next_trie_id());
+        assert!(empty.migrate_subtree_sizes_in_chunks(0));
+        map.clear();
+    }
+
+    #[test]
+    fn test_modify_during_migration() {
+        test_env::setup();
+
+        let mut map: TreeMap<u32, u32> = TreeMap::new(next_trie_id());
+        let mut baseline = BTreeMap::new();
+        for k in 0..1000 {
+            map.insert(&k, &k);
+            baseline.insert(k, k);
+        }
+        into_legacy_nodes(&mut map);
+        assert!(!map.migrate_subtree_sizes_in_chunks(900));
+
+        // Removals and inserts between the calls shrink the map and rotate the nodes.
+        for k in (0..1000).step_by(5).chain((0..1000).step_by(7)) {
+            assert_eq!(map.remove(&k), baseline.remove(&k));
+        }
+        for k in 2000..2050 {
+            map.insert(&k, &k);
+            baseline.insert(k, k);
+        }
+
+        let mut calls = 0;
+        while !map.migrate_subtree_sizes_in_chunks(100) {
+            calls += 1;
+            assert!(calls < map.len());
+        }
+        assert!(map.check_consistency().is_consistent());
+        assert_eq!(map.node(map.root).map(|n| n.sz), Some(map.len()));
+        for (i, k) in baseline.keys().enumerate() {
+            assert_eq!(map.rank(k), i as u64);
+            assert_eq!(map.select(i as u64), Some(*k));
+        }
+        map.clear();
+    }
+
+    #[test]
+    fn prop_avl_vs_rb_rank_select() {
+        fn prop(insert: Vec<(u32, u32)>, remove: Vec<u32>, key: u32, index: u64) -> bool {
+            let a = avl(&insert, &remove);
+            let b = rb(&insert, &remove);
+            let rank = b.range(..key).count() as u64;
+            let index = index % (b.len() as u64 + 1);
+            let selected = b.keys().nth(index as usize).cloned();
+            a.rank(&key) == rank && a.select(index) == selected
+        }
+
+        QuickCheck::new().tests(300).quickcheck(
+            prop as fn(std::vec::Vec<(u32, u32)>, std::vec::Vec<u32>, u32, u64) -> bool,
+        );
+    }
+
+    #[test]
+    fn test_clear_in_chunks() {
+        test_env::setup();
+
+        let mut map: TreeMap<u32, u32> = TreeMap::new(next_trie_id());
+        for k in 0..50 {
+            map.insert(&k, &k);
+        }
+        assert!(!map.clear_in_chunks(20));
+        assert_eq!(map.len(), 30);
+        assert!(!map.clear_in_chunks(20));
+        assert!(map.clear_in_chunks(20));
+        assert_eq!(map.len(), 0);
+        for k in 0..50 {
+            assert!(!map.contains_key(&k));
+        }
+
+        map.insert(&1, &1);
+        map.insert(&2, &2);
+        assert_eq!(map.to_vec(), vec![(1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn test_check_consistency() {
+        test_env::setup();
+
+        let mut map: TreeMap<u32, u32> = TreeMap::new(next_trie_id());
+        for k in random(100) {
+            map.insert(&k, &k);
+        }
+        for k in random(30) {
+            map.remove(&k);
+        }
+        let report = map.check_consistency();
+        assert!(report.is_consistent());
+        assert_eq!(report.checked, map.len());
+
+        let mut map: TreeMap<u32, u32> = TreeMap::new(next_trie_id());
+        for k in 1..=3 {
+            map.insert(&k, &k);
+        }
+        let mut root = map.node(map.root).unwrap();
+        let rgt = root.rgt.unwrap();
+        let mut node = map.node(rgt).unwrap();
+        node.key = 5;
+        node.ht = 3;
+        map.save(&node);
+        root.lft = Some(rgt);
+        map.save(&root);
+        let report = map.check_consistency();
+        assert_eq!(report.checked, 2);
+        assert_eq!(
+            report.inconsistencies,
+            vec![
+                Inconsistency::UnorderedKey { id: rgt },
+                Inconsistency::MissingValue { index: rgt },
+                Inconsistency::DuplicateLink { id: rgt },
+                Inconsistency::LengthMismatch { expected: 3, actual: 2 },
+                Inconsistency::WrongHeight { id: rgt, stored: 3, actual: 1 },
+            ]
+        );
+
+        // Removing the left subtree of a bigger tree unbalances the root.
+        let mut map: TreeMap<u32, u32> = TreeMap::new(next_trie_id());
+        for k in 0..15 {
+            map.insert(&k, &k);
+        }
+        let mut root = map.node(map.root).unwrap();
+        root.lft = None;
+        map.save(&root);
+        let report = map.check_consistency();
+        assert!(report
+            .inconsistencies
+            .contains(&Inconsistency::UnbalancedNode { id: map.root, balance: -3 }));
+        assert!(report
+            .inconsistencies
+            .contains(&Inconsistency::LengthMismatch { expected: 15, actual: 8 }));
+    }
 }
@@ -1,24 +1,57 @@
 //! A map implemented on a trie. Unlike `std::collections::HashMap` the keys in this map are not
 //! hashed but are instead serialized.
-use crate::collections::{append, append_slice, Vector};
+use crate::collections::cache::Cacheable;
+use crate::collections::codec::{Borsh, Decoder, Encoder};
+use crate::collections::consistency::{ConsistencyReport, Inconsistency};
+use crate::collections::entry::{Entry, OccupiedEntry, RawMap, VacantEntry};
+use crate::collections::nested::{nested_prefix, NestedCollection};
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{append, append_slice, ChunkCursor, Vector, VectorIter};
 use crate::{env, IntoStorageKey};
 use borsh::{BorshDeserialize, BorshSerialize};
+use std::iter::FusedIterator;
 use std::mem::size_of;
+use std::ops::RangeBounds;
 
 const ERR_INCONSISTENT_STATE: &[u8] = b"The collection is an inconsistent state. Did previous smart contract execution terminate unexpectedly?";
 const ERR_KEY_SERIALIZATION: &[u8] = b"Cannot serialize key with Borsh";
-const ERR_VALUE_DESERIALIZATION: &[u8] = b"Cannot deserialize value with Borsh";
-const ERR_VALUE_SERIALIZATION: &[u8] = b"Cannot serialize value with Borsh";
+const ERR_VALUE_DESERIALIZATION: &[u8] = b"Cannot deserialize value";
+const ERR_VALUE_SERIALIZATION: &[u8] = b"Cannot serialize value";
 
 /// An iterable implementation of a map that stores its content directly on the trie.
+///
+/// Values are encoded with the codec `C`, which is Borsh by default. See `with_codec`. The content
+/// is stored in the storage `B`, which is the storage of the contract by default. See
+/// `with_storage`.
 #[derive(BorshSerialize, BorshDeserialize)]
-pub struct UnorderedMap<K, V> {
+pub struct UnorderedMap<K, V, C = Borsh, B: Storage = EnvStorage> {
     key_index_prefix: Vec<u8>,
-    keys: Vector<K>,
-    values: Vector<V>,
+    keys: Vector<K, Borsh, B>,
+    values: Vector<V, C, B>,
 }
 
-impl<K, V> UnorderedMap<K, V> {
+impl<K, V> UnorderedMap<K, V, Borsh> {
+    /// Create new map with zero elements. Use `prefix` as a unique identifier.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec(prefix)
+    }
+}
+
+impl<K, V, B: Storage> UnorderedMap<K, V, Borsh, B> {
+    /// Create new map with zero elements that stores its content in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+}
+
+impl<K, V, C, B: Storage> UnorderedMap<K, V, C, B> {
     /// Returns the number of elements in the map, also referred to as its size.
     pub fn len(&self) -> u64 {
         let keys_len = self.keys.len();
@@ -41,8 +74,18 @@ impl<K, V> UnorderedMap<K, V> {
         }
     }
 
-    /// Create new map with zero elements. Use `prefix` as a unique identifier.
-    pub fn new<S>(prefix: S) -> Self
+    /// Create new map with zero elements that encodes its values with the codec `C`. Use `prefix`
+    /// as a unique identifier.
+    pub fn with_codec<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, B::default())
+    }
+
+    /// Create new map with zero elements that encodes its values with the codec `C` and stores its
+    /// content in `storage`. Use `prefix` as a unique identifier in the storage.
+    pub fn with_codec_and_storage<S>(prefix: S, storage: B) -> Self
     where
         S: IntoStorageKey,
     {
@@ -53,11 +96,15 @@ impl<K, V> UnorderedMap<K, V> {
 
         Self {
             key_index_prefix,
-            keys: Vector::new(index_key_id),
-            values: Vector::new(index_value_id),
+            keys: Vector::with_storage(index_key_id, storage.clone()),
+            values: Vector::with_codec_and_storage(index_value_id, storage),
         }
     }
 
+    pub(crate) fn storage(&self) -> &B {
+        self.keys.storage()
+    }
+
     fn serialize_index(index: u64) -> [u8; size_of::<u64>()] {
         index.to_le_bytes()
     }
@@ -75,7 +122,10 @@ impl<K, V> UnorderedMap<K, V> {
     /// Returns an index of the given raw key.
     fn get_index_raw(&self, key_raw: &[u8]) -> Option<u64> {
         let index_lookup = self.raw_key_to_index_lookup(key_raw);
-        env::storage_read(&index_lookup).map(|raw_index| Self::deserialize_index(&raw_index))
+        self.keys
+            .storage()
+            .storage_read(&index_lookup)
+            .map(|raw_index| Self::deserialize_index(&raw_index))
     }
 
     /// Returns the serialized value corresponding to the serialized key.
@@ -92,7 +142,7 @@ impl<K, V> UnorderedMap<K, V> {
     /// the implementation.
     pub fn insert_raw(&mut self, key_raw: &[u8], value_raw: &[u8]) -> Option<Vec<u8>> {
         let index_lookup = self.raw_key_to_index_lookup(key_raw);
-        match env::storage_read(&index_lookup) {
+        match self.keys.storage().storage_read(&index_lookup) {
             Some(index_raw) => {
                 // The element already exists.
                 let index = Self::deserialize_index(&index_raw);
@@ -102,7 +152,7 @@ impl<K, V> UnorderedMap<K, V> {
                 // The element does not exist yet.
                 let next_index = self.len();
                 let next_index_raw = Self::serialize_index(next_index);
-                env::storage_write(&index_lookup, &next_index_raw);
+                self.keys.storage().storage_write(&index_lookup, &next_index_raw);
                 self.keys.push_raw(key_raw);
                 self.values.push_raw(value_raw);
                 None
@@ -114,12 +164,12 @@ impl<K, V> UnorderedMap<K, V> {
     /// was previously in the map.
     pub fn remove_raw(&mut self, key_raw: &[u8]) -> Option<Vec<u8>> {
         let index_lookup = self.raw_key_to_index_lookup(key_raw);
-        match env::storage_read(&index_lookup) {
+        match self.keys.storage().storage_read(&index_lookup) {
             Some(index_raw) => {
                 if self.len() == 1 {
                     // If there is only one element then swap remove simply removes it without
                     // swapping with the last element.
-                    env::storage_remove(&index_lookup);
+                    self.keys.storage().storage_remove(&index_lookup);
                 } else {
                     // If there is more than one element then swap remove swaps it with the last
                     // element.
@@ -127,12 +177,12 @@ impl<K, V> UnorderedMap<K, V> {
                         Some(x) => x,
                         None => env::panic(ERR_INCONSISTENT_STATE),
                     };
-                    env::storage_remove(&index_lookup);
+                    self.keys.storage().storage_remove(&index_lookup);
                     // If the removed element was the last element from keys, then we don't need to
                     // reinsert the lookup back.
                     if last_key_raw != key_raw {
                         let last_lookup_key = self.raw_key_to_index_lookup(&last_key_raw);
-                        env::storage_write(&last_lookup_key, &index_raw);
+                        self.keys.storage().storage_write(&last_lookup_key, &index_raw);
                     }
                 }
                 let index = Self::deserialize_index(&index_raw);
@@ -142,12 +192,61 @@ impl<K, V> UnorderedMap<K, V> {
             None => None,
         }
     }
+
+    /// Removes at most `max_items` elements from the map, so that a large map can be cleared over
+    /// several calls without running out of gas. Returns `true` if the map is empty.
+    pub fn clear_in_chunks(&mut self, max_items: u64) -> bool {
+        let len = self.len();
+        let count = max_items.min(len);
+        for index in len - count..len {
+            let raw_key = match self.keys.get_raw(index) {
+                Some(x) => x,
+                None => env::panic(ERR_INCONSISTENT_STATE),
+            };
+            let index_lookup = self.raw_key_to_index_lookup(&raw_key);
+            self.keys.storage().storage_remove(&index_lookup);
+        }
+        self.keys.clear_in_chunks(count);
+        self.values.clear_in_chunks(count)
+    }
+
+    /// Walks the storage of the map and reports keys and values that are missing from the trie,
+    /// keys whose index map entries do not point back to them, and a mismatch between the numbers
+    /// of keys and values. Index map entries of keys that are not in the map cannot be found, since
+    /// the index map is not iterable.
+    ///
+    /// Costs three storage reads per element, so it is meant for tests and diagnostic view methods.
+    pub fn check_consistency(&self) -> ConsistencyReport {
+        let mut report = ConsistencyReport::default();
+        let keys_len = self.keys.len();
+        let values_len = self.values.len();
+        if keys_len != values_len {
+            report.push(Inconsistency::LengthMismatch { expected: keys_len, actual: values_len });
+        }
+        for index in 0..keys_len.max(values_len) {
+            report.checked += 1;
+            if index < keys_len {
+                match self.keys.try_get_raw(index) {
+                    Some(key_raw) => report.check_index(
+                        self.keys.storage(),
+                        &self.raw_key_to_index_lookup(&key_raw),
+                        index,
+                    ),
+                    None => report.push(Inconsistency::MissingKey { index }),
+                }
+            }
+            if index < values_len && self.values.try_get_raw(index).is_none() {
+                report.push(Inconsistency::MissingValue { index });
+            }
+        }
+        report
+    }
 }
 
-impl<K, V> UnorderedMap<K, V>
+impl<K, V, C, B: Storage> UnorderedMap<K, V, C, B>
 where
     K: BorshSerialize + BorshDeserialize,
-    V: BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
 {
     fn serialize_key(key: &K) -> Vec<u8> {
         match key.try_to_vec() {
@@ -156,15 +255,15 @@ where
         }
     }
 
-    fn deserialize_value(raw_value: &[u8]) -> V {
-        match V::try_from_slice(&raw_value) {
+    fn deserialize_value(&self, raw_value: &[u8]) -> V {
+        match self.storage().scope(|| C::decode(raw_value)) {
             Ok(x) => x,
             Err(_) => env::panic(ERR_VALUE_DESERIALIZATION),
         }
     }
 
     fn serialize_value(value: &V) -> Vec<u8> {
-        match value.try_to_vec() {
+        match C::encode(value) {
             Ok(x) => x,
             Err(_) => env::panic(ERR_VALUE_SERIALIZATION),
         }
@@ -172,14 +271,14 @@ where
 
     /// Returns the value corresponding to the key.
     pub fn get(&self, key: &K) -> Option<V> {
-        self.get_raw(&Self::serialize_key(key)).map(|value_raw| Self::deserialize_value(&value_raw))
+        self.get_raw(&Self::serialize_key(key)).map(|value_raw| self.deserialize_value(&value_raw))
     }
 
     /// Removes a key from the map, returning the value at the key if the key was previously in the
     /// map.
     pub fn remove(&mut self, key: &K) -> Option<V> {
         self.remove_raw(&Self::serialize_key(key))
-            .map(|value_raw| Self::deserialize_value(&value_raw))
+            .map(|value_raw| self.deserialize_value(&value_raw))
     }
 
     /// Inserts a key-value pair into the map.
@@ -188,14 +287,29 @@ where
     /// the implementation.
     pub fn insert(&mut self, key: &K, value: &V) -> Option<V> {
         self.insert_raw(&Self::serialize_key(key), &Self::serialize_value(&value))
-            .map(|value_raw| Self::deserialize_value(&value_raw))
+            .map(|value_raw| self.deserialize_value(&value_raw))
+    }
+
+    /// Gets the given key's corresponding entry in the map for in-place manipulation.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, C, B> {
+        let key_raw = Self::serialize_key(&key);
+        match self.get_raw(&key_raw) {
+            Some(value_raw) => {
+                let value = self.deserialize_value(&value_raw);
+                Entry::Occupied(OccupiedEntry::new(key, key_raw, value, self))
+            }
+            None => {
+                let storage = self.storage().clone();
+                Entry::Vacant(VacantEntry::new(key, key_raw, self, storage))
+            }
+        }
     }
 
     /// Clears the map, removing all elements.
     pub fn clear(&mut self) {
         for raw_key in self.keys.iter_raw() {
             let index_lookup = self.raw_key_to_index_lookup(&raw_key);
-            env::storage_remove(&index_lookup);
+            self.keys.storage().storage_remove(&index_lookup);
         }
         self.keys.clear();
         self.values.clear();
@@ -206,19 +320,40 @@ where
         self.iter().collect()
     }
 
+    /// Returns at most `max_items` key-value pairs starting from the position of the `cursor`, and
+    /// moves the cursor past them. Returns an empty vector once all elements are processed.
+    pub fn next_chunk(&self, cursor: &mut ChunkCursor, max_items: u64) -> Vec<(K, V)> {
+        cursor
+            .next_range(self.len(), max_items)
+            .map(|i| (self.keys.get(i).unwrap(), self.values.get(i).unwrap()))
+            .collect()
+    }
+
     /// An iterator visiting all keys. The iterator element type is `K`.
-    pub fn keys<'a>(&'a self) -> impl Iterator<Item = K> + 'a {
+    pub fn keys(&self) -> VectorIter<'_, K, Borsh, B> {
         self.keys.iter()
     }
 
     /// An iterator visiting all values. The iterator element type is `V`.
-    pub fn values<'a>(&'a self) -> impl Iterator<Item = V> + 'a {
+    pub fn values(&self) -> VectorIter<'_, V, C, B> {
         self.values.iter()
     }
 
     /// Iterate over deserialized keys and values.
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (K, V)> + 'a {
-        self.keys.iter().zip(self.values.iter())
+    pub fn iter(&self) -> UnorderedMapIter<'_, K, V, C, B> {
+        UnorderedMapIter { keys: self.keys.iter(), values: self.values.iter() }
+    }
+
+    /// Iterate over deserialized keys and values with indices within the given range. The range
+    /// is clamped to the length of the map.
+    pub fn range<R: RangeBounds<u64> + Clone>(&self, range: R) -> UnorderedMapIter<'_, K, V, C, B> {
+        UnorderedMapIter { keys: self.keys.range(range.clone()), values: self.values.range(range) }
+    }
+
+    /// Iterate over at most `limit` deserialized keys and values starting from `from_index`.
+    /// Elements before `from_index` are not read.
+    pub fn paginate(&self, from_index: u64, limit: u64) -> UnorderedMapIter<'_, K, V, C, B> {
+        self.range(from_index..from_index.saturating_add(limit))
     }
 
     pub fn extend<IT: IntoIterator<Item = (K, V)>>(&mut self, iter: IT) {
@@ -229,22 +364,152 @@ where
 
     /// Returns a view of keys as a vector.
     /// It's sometimes useful to have random access to the keys.
-    pub fn keys_as_vector(&self) -> &Vector<K> {
+    pub fn keys_as_vector(&self) -> &Vector<K, Borsh, B> {
         &self.keys
     }
 
     /// Returns a view of values as a vector.
     /// It's sometimes useful to have random access to the values.
-    pub fn values_as_vector(&self) -> &Vector<V> {
+    pub fn values_as_vector(&self) -> &Vector<V, C, B> {
         &self.values
     }
 }
 
+/// An iterator over the keys and values of an `UnorderedMap`. Elements are read from the trie
+/// only when they are yielded, so `nth` and `skip` do not read the elements they skip over.
+pub struct UnorderedMapIter<'a, K, V, C = Borsh, B: Storage = EnvStorage> {
+    keys: VectorIter<'a, K, Borsh, B>,
+    values: VectorIter<'a, V, C, B>,
+}
+
+impl<'a, K, V, C, B: Storage> Iterator for UnorderedMapIter<'a, K, V, C, B>
+where
+    K: BorshDeserialize,
+    C: Decoder<V>,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some((self.keys.next()?, self.values.next()?))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+
+    fn count(self) -> usize {
+        self.keys.count()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        Some((self.keys.nth(n)?, self.values.nth(n)?))
+    }
+}
+
+impl<'a, K, V, C, B: Storage> DoubleEndedIterator for UnorderedMapIter<'a, K, V, C, B>
+where
+    K: BorshDeserialize,
+    C: Decoder<V>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        Some((self.keys.next_back()?, self.values.next_back()?))
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
+        Some((self.keys.nth_back(n)?, self.values.nth_back(n)?))
+    }
+}
+
+impl<'a, K, V, C, B: Storage> ExactSizeIterator for UnorderedMapIter<'a, K, V, C, B>
+where
+    K: BorshDeserialize,
+    C: Decoder<V>,
+{
+}
+
+impl<'a, K, V, C, B: Storage> FusedIterator for UnorderedMapIter<'a, K, V, C, B>
+where
+    K: BorshDeserialize,
+    C: Decoder<V>,
+{
+}
+
+impl<'a, K, V, C, B: Storage> IntoIterator for &'a UnorderedMap<K, V, C, B>
+where
+    K: BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
+{
+    type Item = (K, V);
+    type IntoIter = UnorderedMapIter<'a, K, V, C, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, V, C, B: Storage> UnorderedMap<K, V, C, B>
+where
+    K: BorshSerialize + BorshDeserialize,
+    V: NestedCollection<B>,
+    C: Encoder<V> + Decoder<V>,
+{
+    /// Removes a nested collection from the map together with its content. Returns `true` if the
+    /// key was previously in the map.
+    pub fn remove_nested(&mut self, key: &K) -> bool {
+        match self.remove(key) {
+            Some(mut collection) => {
+                collection.clear_nested();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<K, V, C, B: Storage> RawMap for UnorderedMap<K, V, C, B> {
+    fn insert_raw(&mut self, key_raw: &[u8], value_raw: &[u8]) -> Option<Vec<u8>> {
+        UnorderedMap::insert_raw(self, key_raw, value_raw)
+    }
+
+    fn remove_raw(&mut self, key_raw: &[u8]) -> Option<Vec<u8>> {
+        UnorderedMap::remove_raw(self, key_raw)
+    }
+
+    fn nested_prefix(&self, key_raw: &[u8]) -> Vec<u8> {
+        nested_prefix(&self.key_index_prefix, key_raw)
+    }
+}
+
+impl<K, V, C, B: Storage> NestedCollection<B> for UnorderedMap<K, V, C, B>
+where
+    K: BorshSerialize + BorshDeserialize,
+    C: Encoder<V> + Decoder<V>,
+{
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear();
+    }
+}
+
+impl<K, V, C> Cacheable for UnorderedMap<K, V, C> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        let mut prefixes = vec![&self.key_index_prefix[..]];
+        prefixes.extend(self.keys.storage_prefixes());
+        prefixes.extend(self.values.storage_prefixes());
+        prefixes
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 #[cfg(test)]
 mod tests {
-    use crate::collections::UnorderedMap;
+    use crate::collections::{ChunkCursor, Entry, Inconsistency, UnorderedMap, Vector};
+    use crate::env;
     use crate::test_utils::test_env;
+    use borsh::BorshSerialize;
     use rand::seq::SliceRandom;
     use rand::{Rng, SeedableRng};
     use std::collections::{HashMap, HashSet};
@@ -445,4 +710,164 @@ mod tests {
         let actual: HashMap<u64, u64> = HashMap::from_iter(map.iter());
         assert_eq!(actual, key_to_value);
     }
+
+    #[test]
+    pub fn test_entry() {
+        test_env::setup();
+        let mut map = UnorderedMap::new(b"m");
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(6);
+        let mut key_to_value = HashMap::new();
+        for _ in 0..500 {
+            let key = rng.gen::<u64>() % 50;
+            let value = rng.gen::<u64>() % 1000;
+            map.entry(key).and_modify(|v| *v += value).or_insert(value);
+            key_to_value.entry(key).and_modify(|v| *v += value).or_insert(value);
+        }
+        assert_eq!(HashMap::from_iter(map.iter()), key_to_value);
+
+        for key in 0..50 {
+            if let Entry::Occupied(mut entry) = map.entry(key) {
+                if key % 2 == 0 {
+                    entry.remove();
+                    key_to_value.remove(&key);
+                } else {
+                    assert_eq!(entry.insert(key), key_to_value.insert(key, key).unwrap());
+                }
+            }
+        }
+        assert_eq!(map.len(), key_to_value.len() as u64);
+        assert_eq!(HashMap::from_iter(map.iter()), key_to_value);
+    }
+
+    #[test]
+    pub fn test_clear_in_chunks() {
+        test_env::setup();
+        let mut map = UnorderedMap::new(b"m");
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(7);
+        let mut key_to_value = HashMap::new();
+        for _ in 0..100 {
+            let key = rng.gen::<u64>();
+            let value = rng.gen::<u64>();
+            key_to_value.insert(key, value);
+            map.insert(&key, &value);
+        }
+        let mut chunks = 0;
+        while !map.clear_in_chunks(15) {
+            chunks += 1;
+            assert_eq!(map.len(), 100 - chunks * 15);
+            let actual: HashMap<u64, u64> = HashMap::from_iter(map.iter());
+            assert_eq!(actual.len() as u64, map.len());
+            for (key, value) in actual {
+                assert_eq!(key_to_value[&key], value);
+            }
+        }
+        assert_eq!(chunks, 6);
+        for key in key_to_value.keys() {
+            assert_eq!(map.get(key), None);
+        }
+    }
+
+    #[test]
+    pub fn test_next_chunk() {
+        test_env::setup();
+        let mut map = UnorderedMap::new(b"m");
+        map.extend((0..10u64).map(|x| (x, x * 10)));
+        let mut cursor = ChunkCursor::new();
+        let mut actual = vec![];
+        loop {
+            let chunk = map.next_chunk(&mut cursor, 3);
+            if chunk.is_empty() {
+                break;
+            }
+            actual.extend(chunk);
+        }
+        assert_eq!(actual, map.to_vec());
+    }
+
+    #[test]
+    pub fn test_iter_range_paginate() {
+        test_env::setup();
+        let mut map = UnorderedMap::new(b"m");
+        map.extend((0..20u64).map(|x| (x, x + 100)));
+        let baseline = map.to_vec();
+
+        assert_eq!(map.iter().len(), 20);
+        assert_eq!(
+            map.iter().rev().collect::<Vec<_>>(),
+            baseline.iter().rev().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(map.range(5..8).collect::<Vec<_>>(), baseline[5..8].to_vec());
+        assert_eq!(map.paginate(18, 5).collect::<Vec<_>>(), baseline[18..].to_vec());
+        assert_eq!(map.paginate(25, 5).count(), 0);
+
+        let mut iter = map.iter();
+        assert_eq!(iter.nth(3), Some(baseline[3]));
+        assert_eq!(iter.nth_back(3), Some(baseline[16]));
+        assert_eq!(iter.len(), 12);
+        assert_eq!(map.keys().nth(10), Some(baseline[10].0));
+        assert_eq!(map.values().last(), Some(baseline[19].1));
+        assert_eq!((&map).into_iter().count(), 20);
+    }
+
+    #[test]
+    pub fn test_nested() {
+        test_env::setup();
+        let mut map: UnorderedMap<String, Vector<u64>> = UnorderedMap::new(b"m");
+        map.entry("a".to_string()).or_insert_nested().get_mut().extend(0..5);
+        map.entry("ab".to_string()).or_insert_nested().get_mut().push(&5);
+        map.entry("a".to_string()).or_insert_nested().get_mut().push(&6);
+        assert_eq!(map.get(&"a".to_string()).unwrap().to_vec(), vec![0, 1, 2, 3, 4, 6]);
+        assert_eq!(map.get(&"ab".to_string()).unwrap().to_vec(), vec![5]);
+
+        match map.entry("a".to_string()) {
+            Entry::Occupied(entry) => entry.remove_nested(),
+            Entry::Vacant(_) => panic!("the entry is occupied"),
+        }
+        assert!(map.entry("a".to_string()).or_insert_nested().is_empty());
+        assert!(map.remove_nested(&"ab".to_string()));
+        assert!(!map.remove_nested(&"ab".to_string()));
+        let element_key =
+            [&b"mi"[..], &"ab".try_to_vec().unwrap(), b"n", &0u64.to_le_bytes()].concat();
+        assert!(!env::storage_has_key(&element_key));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    pub fn test_check_consistency() {
+        test_env::setup();
+        let mut map = UnorderedMap::new(b"m");
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(4);
+        for _ in 0..200 {
+            let key = rng.gen::<u64>() % 50;
+            if rng.gen::<bool>() {
+                map.insert(&key, &rng.gen::<u64>());
+            } else {
+                map.remove(&key);
+            }
+        }
+        let report = map.check_consistency();
+        assert!(report.is_consistent());
+        assert_eq!(report.checked, map.len());
+
+        let mut map = UnorderedMap::new(b"n");
+        map.extend((0u64..10).map(|k| (k, k)));
+        env::storage_remove(&[&b"ni"[..], &2u64.to_le_bytes()].concat());
+        env::storage_remove(&[&b"nv"[..], &4u64.to_le_bytes()].concat());
+        // A value pushed without its key, as if an execution stopped halfway through an insert.
+        map.values.push(&10);
+        let report = map.check_consistency();
+        assert_eq!(report.checked, 11);
+        assert_eq!(
+            report.inconsistencies,
+            vec![
+                Inconsistency::LengthMismatch { expected: 10, actual: 11 },
+                Inconsistency::OrphanedKey { index: 2 },
+                Inconsistency::MissingValue { index: 4 },
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&report.inconsistencies[1]).unwrap(),
+            r#"{"kind":"orphaned_key","index":2}"#
+        );
+    }
 }
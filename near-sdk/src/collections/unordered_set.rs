@@ -1,21 +1,52 @@
 //! A set implemented on a trie. Unlike `std::collections::HashSet` the elements in this set are not
 //! hashed but are instead serialized.
-use crate::collections::{append, append_slice, Vector};
+use crate::collections::cache::Cacheable;
+use crate::collections::codec::{Borsh, Decoder, Encoder};
+use crate::collections::consistency::{ConsistencyReport, Inconsistency};
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{append, append_slice, NestedCollection, Vector, VectorIter};
 use crate::{env, IntoStorageKey};
 use borsh::{BorshDeserialize, BorshSerialize};
 use std::mem::size_of;
+use std::ops::RangeBounds;
 
 const ERR_INCONSISTENT_STATE: &[u8] = b"The collection is an inconsistent state. Did previous smart contract execution terminate unexpectedly?";
-const ERR_ELEMENT_SERIALIZATION: &[u8] = b"Cannot serialize element with Borsh";
+const ERR_ELEMENT_SERIALIZATION: &[u8] = b"Cannot serialize element";
 
 /// An iterable implementation of a set that stores its content directly on the trie.
+///
+/// Elements are encoded with the codec `C`, which is Borsh by default. See `with_codec`. The
+/// encoded element is also the key of its index entry, so the codec has to encode equal elements
+/// into the same bytes. The content is stored in the storage `B`, which is the storage of the
+/// contract by default. See `with_storage`.
 #[derive(BorshSerialize, BorshDeserialize)]
-pub struct UnorderedSet<T> {
+pub struct UnorderedSet<T, C = Borsh, B: Storage = EnvStorage> {
     element_index_prefix: Vec<u8>,
-    elements: Vector<T>,
+    elements: Vector<T, C, B>,
 }
 
-impl<T> UnorderedSet<T> {
+impl<T> UnorderedSet<T, Borsh> {
+    /// Create new map with zero elements. Use `id` as a unique identifier.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec(prefix)
+    }
+}
+
+impl<T, B: Storage> UnorderedSet<T, Borsh, B> {
+    /// Create new set with zero elements that stores its content in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+}
+
+impl<T, C, B: Storage> UnorderedSet<T, C, B> {
     /// Returns the number of elements in the set, also referred to as its size.
     pub fn len(&self) -> u64 {
         self.elements.len()
@@ -26,8 +57,18 @@ impl<T> UnorderedSet<T> {
         self.elements.is_empty()
     }
 
-    /// Create new map with zero elements. Use `id` as a unique identifier.
-    pub fn new<S>(prefix: S) -> Self
+    /// Create new set with zero elements that encodes its elements with the codec `C`. Use
+    /// `prefix` as a unique identifier.
+    pub fn with_codec<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, B::default())
+    }
+
+    /// Create new set with zero elements that encodes its elements with the codec `C` and stores
+    /// its content in `storage`. Use `prefix` as a unique identifier in the storage.
+    pub fn with_codec_and_storage<S>(prefix: S, storage: B) -> Self
     where
         S: IntoStorageKey,
     {
@@ -35,7 +76,10 @@ impl<T> UnorderedSet<T> {
         let element_index_prefix = append(&prefix, b'i');
         let elements_prefix = append(&prefix, b'e');
 
-        Self { element_index_prefix, elements: Vector::new(elements_prefix) }
+        Self {
+            element_index_prefix,
+            elements: Vector::with_codec_and_storage(elements_prefix, storage),
+        }
     }
 
     fn serialize_index(index: u64) -> [u8; size_of::<u64>()] {
@@ -55,7 +99,7 @@ impl<T> UnorderedSet<T> {
     /// Returns true if the set contains a serialized element.
     fn contains_raw(&self, element_raw: &[u8]) -> bool {
         let index_lookup = self.raw_element_to_index_lookup(element_raw);
-        env::storage_has_key(&index_lookup)
+        self.elements.storage().storage_has_key(&index_lookup)
     }
 
     /// Adds a value to the set.
@@ -63,13 +107,13 @@ impl<T> UnorderedSet<T> {
     /// If the set did have this value present, `false` is returned.
     pub fn insert_raw(&mut self, element_raw: &[u8]) -> bool {
         let index_lookup = self.raw_element_to_index_lookup(element_raw);
-        match env::storage_read(&index_lookup) {
+        match self.elements.storage().storage_read(&index_lookup) {
             Some(_index_raw) => false,
             None => {
                 // The element does not exist yet.
                 let next_index = self.len();
                 let next_index_raw = Self::serialize_index(next_index);
-                env::storage_write(&index_lookup, &next_index_raw);
+                self.elements.storage().storage_write(&index_lookup, &next_index_raw);
                 self.elements.push_raw(element_raw);
                 true
             }
@@ -79,12 +123,12 @@ impl<T> UnorderedSet<T> {
     /// Removes a value from the set. Returns whether the value was present in the set.
     pub fn remove_raw(&mut self, element_raw: &[u8]) -> bool {
         let index_lookup = self.raw_element_to_index_lookup(element_raw);
-        match env::storage_read(&index_lookup) {
+        match self.elements.storage().storage_read(&index_lookup) {
             Some(index_raw) => {
                 if self.len() == 1 {
                     // If there is only one element then swap remove simply removes it without
                     // swapping with the last element.
-                    env::storage_remove(&index_lookup);
+                    self.elements.storage().storage_remove(&index_lookup);
                 } else {
                     // If there is more than one element then swap remove swaps it with the last
                     // element.
@@ -92,13 +136,13 @@ impl<T> UnorderedSet<T> {
                         Some(x) => x,
                         None => env::panic(ERR_INCONSISTENT_STATE),
                     };
-                    env::storage_remove(&index_lookup);
+                    self.elements.storage().storage_remove(&index_lookup);
                     // If the removed element was the last element from keys, then we don't need to
                     // reinsert the lookup back.
                     if last_element_raw != element_raw {
                         let last_lookup_element =
                             self.raw_element_to_index_lookup(&last_element_raw);
-                        env::storage_write(&last_lookup_element, &index_raw);
+                        self.elements.storage().storage_write(&last_lookup_element, &index_raw);
                     }
                 }
                 let index = Self::deserialize_index(&index_raw);
@@ -108,14 +152,50 @@ impl<T> UnorderedSet<T> {
             None => false,
         }
     }
+
+    /// Removes at most `max_items` elements from the set, so that a large set can be cleared over
+    /// several calls without running out of gas. Returns `true` if the set is empty.
+    pub fn clear_in_chunks(&mut self, max_items: u64) -> bool {
+        let len = self.len();
+        for index in len - max_items.min(len)..len {
+            let raw_element = match self.elements.get_raw(index) {
+                Some(x) => x,
+                None => env::panic(ERR_INCONSISTENT_STATE),
+            };
+            let index_lookup = self.raw_element_to_index_lookup(&raw_element);
+            self.elements.storage().storage_remove(&index_lookup);
+        }
+        self.elements.clear_in_chunks(max_items)
+    }
+
+    /// Walks the storage of the set and reports the elements that are missing from the trie or
+    /// whose index map entries do not point back to them. Index map entries of elements that are
+    /// not in the set cannot be found, since the index map is not iterable.
+    ///
+    /// Costs two storage reads per element, so it is meant for tests and diagnostic view methods.
+    pub fn check_consistency(&self) -> ConsistencyReport {
+        let mut report = ConsistencyReport::default();
+        for index in 0..self.len() {
+            report.checked += 1;
+            match self.elements.try_get_raw(index) {
+                Some(element_raw) => report.check_index(
+                    self.elements.storage(),
+                    &self.raw_element_to_index_lookup(&element_raw),
+                    index,
+                ),
+                None => report.push(Inconsistency::MissingKey { index }),
+            }
+        }
+        report
+    }
 }
 
-impl<T> UnorderedSet<T>
+impl<T, C, B: Storage> UnorderedSet<T, C, B>
 where
-    T: BorshSerialize + BorshDeserialize,
+    C: Encoder<T> + Decoder<T>,
 {
     fn serialize_element(element: &T) -> Vec<u8> {
-        match element.try_to_vec() {
+        match C::encode(element) {
             Ok(x) => x,
             Err(_) => env::panic(ERR_ELEMENT_SERIALIZATION),
         }
@@ -142,7 +222,7 @@ where
     pub fn clear(&mut self) {
         for raw_element in self.elements.iter_raw() {
             let index_lookup = self.raw_element_to_index_lookup(&raw_element);
-            env::storage_remove(&index_lookup);
+            self.elements.storage().storage_remove(&index_lookup);
         }
         self.elements.clear();
     }
@@ -153,10 +233,22 @@ where
     }
 
     /// Iterate over deserialized elements.
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item = T> + 'a {
+    pub fn iter(&self) -> VectorIter<'_, T, C, B> {
         self.elements.iter()
     }
 
+    /// Iterate over deserialized elements with indices within the given range. The range is
+    /// clamped to the length of the set.
+    pub fn range<R: RangeBounds<u64>>(&self, range: R) -> VectorIter<'_, T, C, B> {
+        self.elements.range(range)
+    }
+
+    /// Iterate over at most `limit` deserialized elements starting from `from_index`. Elements
+    /// before `from_index` are not read.
+    pub fn paginate(&self, from_index: u64, limit: u64) -> VectorIter<'_, T, C, B> {
+        self.elements.paginate(from_index, limit)
+    }
+
     pub fn extend<IT: IntoIterator<Item = T>>(&mut self, iter: IT) {
         for el in iter {
             self.insert(&el);
@@ -165,15 +257,49 @@ where
 
     /// Returns a view of elements as a vector.
     /// It's sometimes useful to have random access to the elements.
-    pub fn as_vector(&self) -> &Vector<T> {
+    pub fn as_vector(&self) -> &Vector<T, C, B> {
         &self.elements
     }
 }
 
+impl<'a, T, C, B: Storage> IntoIterator for &'a UnorderedSet<T, C, B>
+where
+    C: Encoder<T> + Decoder<T>,
+{
+    type Item = T;
+    type IntoIter = VectorIter<'a, T, C, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, C, B: Storage> NestedCollection<B> for UnorderedSet<T, C, B>
+where
+    C: Encoder<T> + Decoder<T>,
+{
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, C> Cacheable for UnorderedSet<T, C> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        let mut prefixes = vec![&self.element_index_prefix[..]];
+        prefixes.extend(self.elements.storage_prefixes());
+        prefixes
+    }
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 #[cfg(test)]
 mod tests {
-    use crate::collections::UnorderedSet;
+    use crate::collections::{Inconsistency, UnorderedSet};
+    use crate::env;
     use crate::test_utils::test_env;
     use rand::seq::SliceRandom;
     use rand::{Rng, SeedableRng};
@@ -332,4 +458,70 @@ mod tests {
         let actual: HashSet<u64> = HashSet::from_iter(set.iter());
         assert_eq!(actual, keys);
     }
+
+    #[test]
+    pub fn test_clear_in_chunks() {
+        test_env::setup();
+        let mut set = UnorderedSet::new(b"s");
+        set.extend(0..50u64);
+        assert!(!set.clear_in_chunks(20));
+        assert_eq!(set.len(), 30);
+        assert_eq!(set.iter().collect::<HashSet<_>>(), (0..30u64).collect());
+        assert!(!set.clear_in_chunks(20));
+        assert!(set.clear_in_chunks(20));
+        assert!(!set.contains(&0));
+        assert!(set.insert(&0));
+    }
+
+    #[test]
+    pub fn test_paginate() {
+        test_env::setup();
+        let mut set = UnorderedSet::new(b"s");
+        set.extend(0..20u64);
+        let baseline = set.to_vec();
+        assert_eq!(set.paginate(4, 3).collect::<Vec<_>>(), baseline[4..7].to_vec());
+        assert_eq!(
+            set.range(17..).rev().collect::<Vec<_>>(),
+            vec![baseline[19], baseline[18], baseline[17]]
+        );
+        assert_eq!(set.paginate(15, 10).len(), 5);
+        assert_eq!((&set).into_iter().nth(9), Some(baseline[9]));
+    }
+
+    #[test]
+    pub fn test_check_consistency() {
+        test_env::setup();
+        let mut set = UnorderedSet::new(b"s");
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(4);
+        for _ in 0..200 {
+            let element = rng.gen::<u64>() % 50;
+            if rng.gen::<bool>() {
+                set.insert(&element);
+            } else {
+                set.remove(&element);
+            }
+        }
+        let report = set.check_consistency();
+        assert!(report.is_consistent());
+        assert_eq!(report.checked, set.len());
+
+        let mut set = UnorderedSet::new(b"t");
+        set.extend(0u64..10);
+        let index_lookup = |element: u64| [&b"ti"[..], &element.to_le_bytes()].concat();
+        env::storage_remove(&index_lookup(3));
+        env::storage_write(&index_lookup(5), &7u64.to_le_bytes());
+        env::storage_write(&index_lookup(6), b"bad");
+        env::storage_remove(&[&b"te"[..], &8u64.to_le_bytes()].concat());
+        let report = set.check_consistency();
+        assert_eq!(report.checked, 10);
+        assert_eq!(
+            report.inconsistencies,
+            vec![
+                Inconsistency::OrphanedKey { index: 3 },
+                Inconsistency::DanglingIndex { index: 5, points_to: Some(7) },
+                Inconsistency::DanglingIndex { index: 6, points_to: None },
+                Inconsistency::MissingKey { index: 8 },
+            ]
+        );
+    }
 }
@@ -1,10 +1,17 @@
-//! A vector implemented on a trie. Unlike standard vector does not support insertion and removal
-//! of an element results in the last element being placed in the empty position.
+//! A vector implemented on a trie. Each element is stored under its own key, so `swap_remove`,
+//! `push` and `pop` are `O(1)`, while order-preserving `insert` and `remove` move every element
+//! after the given index and cost a read and a write per moved element.
+use std::iter::FusedIterator;
 use std::marker::PhantomData;
+use std::ops::{Bound, Range, RangeBounds};
 
 use borsh::{BorshDeserialize, BorshSerialize};
 
 use crate::collections::append_slice;
+use crate::collections::cache::Cacheable;
+use crate::collections::codec::{Borsh, Decoder, Encoder};
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{ChunkCursor, NestedCollection};
 use crate::{env, IntoStorageKey};
 
 const ERR_INCONSISTENT_STATE: &[u8] = b"The collection is an inconsistent state. Did previous smart contract execution terminate unexpectedly?";
@@ -14,16 +21,64 @@ const ERR_INDEX_OUT_OF_BOUNDS: &[u8] = b"Index out of bounds";
 
 /// An iterable implementation of vector that stores its content on the trie.
 /// Uses the following map: index -> element.
+///
+/// Elements are encoded with the codec `C`, which is Borsh by default. See `with_codec`. The content
+/// is stored in the storage `B`, which is the storage of the contract by default. See
+/// `with_storage`.
 #[derive(BorshSerialize, BorshDeserialize)]
-#[cfg_attr(not(feature = "expensive-debug"), derive(Debug))]
-pub struct Vector<T> {
+pub struct Vector<T, C = Borsh, B: Storage = EnvStorage> {
     len: u64,
     prefix: Vec<u8>,
     #[borsh_skip]
-    el: PhantomData<T>,
+    el: PhantomData<(T, C)>,
+    #[borsh_skip]
+    storage: B,
+}
+
+impl<T> Vector<T, Borsh> {
+    /// Create new vector with zero elements. Use `id` as a unique identifier on the trie.
+    pub fn new<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec(prefix)
+    }
+}
+
+impl<T, B: Storage> Vector<T, Borsh, B> {
+    /// Create new vector with zero elements that stores its content in `storage`. Use `prefix` as
+    /// a unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, storage)
+    }
 }
 
-impl<T> Vector<T> {
+impl<T, C, B: Storage> Vector<T, C, B> {
+    /// Create new vector with zero elements that encodes its elements with the codec `C`. Use
+    /// `prefix` as a unique identifier on the trie.
+    pub fn with_codec<S>(prefix: S) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_codec_and_storage(prefix, B::default())
+    }
+
+    /// Create new vector with zero elements that encodes its elements with the codec `C` and stores
+    /// them in `storage`. Use `prefix` as a unique identifier in the storage.
+    pub fn with_codec_and_storage<S>(prefix: S, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self { len: 0, prefix: prefix.into_storage_key(), el: PhantomData, storage }
+    }
+
+    pub(crate) fn storage(&self) -> &B {
+        &self.storage
+    }
+
     /// Returns the number of elements in the vector, also referred to as its size.
     pub fn len(&self) -> u64 {
         self.len
@@ -34,14 +89,6 @@ impl<T> Vector<T> {
         self.len == 0
     }
 
-    /// Create new vector with zero elements. Use `id` as a unique identifier on the trie.
-    pub fn new<S>(prefix: S) -> Self
-    where
-        S: IntoStorageKey,
-    {
-        Self { len: 0, prefix: prefix.into_storage_key(), el: PhantomData }
-    }
-
     fn index_to_lookup_key(&self, index: u64) -> Vec<u8> {
         append_slice(&self.prefix, &index.to_le_bytes()[..])
     }
@@ -52,12 +99,21 @@ impl<T> Vector<T> {
             return None;
         }
         let lookup_key = self.index_to_lookup_key(index);
-        match env::storage_read(&lookup_key) {
+        match self.storage.storage_read(&lookup_key) {
             Some(raw_element) => Some(raw_element),
             None => env::panic(ERR_INCONSISTENT_STATE),
         }
     }
 
+    /// Returns the serialized element by index, or `None` if it is out of bounds or missing from the
+    /// trie. Unlike `get_raw`, does not panic on a missing element.
+    pub(crate) fn try_get_raw(&self, index: u64) -> Option<Vec<u8>> {
+        if index >= self.len {
+            return None;
+        }
+        self.storage.storage_read(&self.index_to_lookup_key(index))
+    }
+
     /// Removes an element from the vector and returns it in serialized form.
     /// The removed element is replaced by the last element of the vector.
     /// Does not preserve ordering, but is `O(1)`.
@@ -76,8 +132,8 @@ impl<T> Vector<T> {
         } else {
             let lookup_key = self.index_to_lookup_key(index);
             let raw_last_value = self.pop_raw().expect("checked `index < len` above, so `len > 0`");
-            if env::storage_write(&lookup_key, &raw_last_value) {
-                match env::storage_get_evicted() {
+            if self.storage.storage_write(&lookup_key, &raw_last_value) {
+                match self.storage.storage_get_evicted() {
                     Some(x) => x,
                     None => env::panic(ERR_INCONSISTENT_STATE),
                 }
@@ -91,7 +147,7 @@ impl<T> Vector<T> {
     pub fn push_raw(&mut self, raw_element: &[u8]) {
         let lookup_key = self.index_to_lookup_key(self.len);
         self.len += 1;
-        env::storage_write(&lookup_key, raw_element);
+        self.storage.storage_write(&lookup_key, raw_element);
     }
 
     /// Removes the last element from a vector and returns it without deserializing, or `None` if it is empty.
@@ -103,8 +159,8 @@ impl<T> Vector<T> {
             let last_lookup_key = self.index_to_lookup_key(last_index);
 
             self.len -= 1;
-            let raw_last_value = if env::storage_remove(&last_lookup_key) {
-                match env::storage_get_evicted() {
+            let raw_last_value = if self.storage.storage_remove(&last_lookup_key) {
+                match self.storage.storage_get_evicted() {
                     Some(x) => x,
                     None => env::panic(ERR_INCONSISTENT_STATE),
                 }
@@ -125,8 +181,8 @@ impl<T> Vector<T> {
             env::panic(ERR_INDEX_OUT_OF_BOUNDS)
         } else {
             let lookup_key = self.index_to_lookup_key(index);
-            if env::storage_write(&lookup_key, &raw_element) {
-                match env::storage_get_evicted() {
+            if self.storage.storage_write(&lookup_key, &raw_element) {
+                match self.storage.storage_get_evicted() {
                     Some(x) => x,
                     None => env::panic(ERR_INCONSISTENT_STATE),
                 }
@@ -136,11 +192,107 @@ impl<T> Vector<T> {
         }
     }
 
+    /// Inserts a serialized element at `index`, shifting all elements after it to the right.
+    ///
+    /// Costs `len - index` storage reads and `len - index + 1` storage writes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert_raw(&mut self, index: u64, raw_element: &[u8]) {
+        if index > self.len {
+            env::panic(ERR_INDEX_OUT_OF_BOUNDS)
+        }
+        for i in (index..self.len).rev() {
+            self.move_raw(i, i + 1);
+        }
+        self.len += 1;
+        let lookup_key = self.index_to_lookup_key(index);
+        self.storage.storage_write(&lookup_key, raw_element);
+    }
+
+    /// Removes an element from the vector and returns it in serialized form, shifting all
+    /// elements after it to the left. Preserves ordering, unlike `swap_remove_raw`.
+    ///
+    /// Costs `len - index` storage reads, `len - index - 1` storage writes and one storage removal.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove_raw(&mut self, index: u64) -> Vec<u8> {
+        if index >= self.len {
+            env::panic(ERR_INDEX_OUT_OF_BOUNDS)
+        }
+        let raw_element = self.read_raw(index);
+        for i in index + 1..self.len {
+            self.move_raw(i, i - 1);
+        }
+        self.truncate(self.len - 1);
+        raw_element
+    }
+
+    /// Retains only the serialized elements for which the predicate returns `true`, preserving
+    /// their order.
+    ///
+    /// Costs `len` storage reads, a storage write for every retained element that has to be moved
+    /// and a storage removal for every removed element.
+    pub fn retain_raw<F: FnMut(&[u8]) -> bool>(&mut self, mut f: F) {
+        let mut retained = 0;
+        for i in 0..self.len {
+            let raw_element = self.read_raw(i);
+            if f(&raw_element) {
+                if retained != i {
+                    let lookup_key = self.index_to_lookup_key(retained);
+                    self.storage.storage_write(&lookup_key, &raw_element);
+                }
+                retained += 1;
+            }
+        }
+        self.truncate(retained);
+    }
+
+    /// Removes the elements with indices within the given range from the vector and returns them
+    /// in serialized form, shifting all elements after the range to the left.
+    ///
+    /// Costs `len - start` storage reads, a storage write for every element after the range and a
+    /// storage removal for every removed element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end or the end is out of bounds.
+    pub fn drain_raw<R: RangeBounds<u64>>(&mut self, range: R) -> Vec<Vec<u8>> {
+        let Range { start, end } = bounds_to_range(range, self.len);
+        if start > end || end > self.len {
+            env::panic(ERR_INDEX_OUT_OF_BOUNDS)
+        }
+        let drained = (start..end).map(|i| self.read_raw(i)).collect();
+        let count = end - start;
+        for i in end..self.len {
+            self.move_raw(i, i - count);
+        }
+        self.truncate(self.len - count);
+        drained
+    }
+
+    /// Reads the serialized element at `index`. Does not check bounds.
+    fn read_raw(&self, index: u64) -> Vec<u8> {
+        match self.storage.storage_read(&self.index_to_lookup_key(index)) {
+            Some(x) => x,
+            None => env::panic(ERR_INCONSISTENT_STATE),
+        }
+    }
+
+    /// Copies the serialized element at index `from` to index `to`. Does not check bounds.
+    fn move_raw(&mut self, from: u64, to: u64) {
+        let raw_element = self.read_raw(from);
+        self.storage.storage_write(&self.index_to_lookup_key(to), &raw_element);
+    }
+
     /// Iterate over raw serialized elements.
     pub fn iter_raw<'a>(&'a self) -> impl Iterator<Item = Vec<u8>> + 'a {
         (0..self.len).map(move |i| {
             let lookup_key = self.index_to_lookup_key(i);
-            match env::storage_read(&lookup_key) {
+            match self.storage.storage_read(&lookup_key) {
                 Some(x) => x,
                 None => env::panic(ERR_INCONSISTENT_STATE),
             }
@@ -155,23 +307,67 @@ impl<T> Vector<T> {
     }
 }
 
-impl<T> Vector<T> {
+impl<T, C, B: Storage> Vector<T, C, B> {
     /// Removes all elements from the collection.
     pub fn clear(&mut self) {
         for i in 0..self.len {
             let lookup_key = self.index_to_lookup_key(i);
-            env::storage_remove(&lookup_key);
+            self.storage.storage_remove(&lookup_key);
         }
         self.len = 0;
     }
+
+    /// Removes at most `max_items` elements from the back of the vector, so that a large vector can
+    /// be cleared over several calls without running out of gas. Returns `true` if the vector is
+    /// empty.
+    pub fn clear_in_chunks(&mut self, max_items: u64) -> bool {
+        for _ in 0..max_items.min(self.len) {
+            self.len -= 1;
+            let lookup_key = self.index_to_lookup_key(self.len);
+            self.storage.storage_remove(&lookup_key);
+        }
+        self.is_empty()
+    }
+
+    /// Shortens the vector, keeping the first `len` elements and removing the rest. Has no effect
+    /// if `len` is greater than or equal to the vector's current length.
+    ///
+    /// Costs a storage removal for every removed element. The removed elements are not read.
+    pub fn truncate(&mut self, len: u64) {
+        while self.len > len {
+            self.len -= 1;
+            let lookup_key = self.index_to_lookup_key(self.len);
+            self.storage.storage_remove(&lookup_key);
+        }
+    }
+
+    /// Swaps two elements in the vector.
+    ///
+    /// Costs two storage reads and two storage writes, or nothing if `a == b`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` or `b` are out of bounds.
+    pub fn swap(&mut self, a: u64, b: u64) {
+        if a >= self.len || b >= self.len {
+            env::panic(ERR_INDEX_OUT_OF_BOUNDS)
+        }
+        if a == b {
+            return;
+        }
+        let raw_a = self.read_raw(a);
+        self.move_raw(b, a);
+        let lookup_key = self.index_to_lookup_key(b);
+        self.storage.storage_write(&lookup_key, &raw_a);
+    }
 }
 
-impl<T> Vector<T>
+impl<T, C, B: Storage> Vector<T, C, B>
 where
-    T: BorshSerialize,
+    C: Encoder<T>,
 {
     fn serialize_element(element: &T) -> Vec<u8> {
-        match element.try_to_vec() {
+        match C::encode(element) {
             Ok(x) => x,
             Err(_) => env::panic(ERR_ELEMENT_SERIALIZATION),
         }
@@ -189,14 +385,27 @@ where
             self.push(&el)
         }
     }
+
+    /// Inserts an element at `index`, shifting all elements after it to the right.
+    ///
+    /// Costs `len - index` storage reads and `len - index + 1` storage writes, but the shifted
+    /// elements are not deserialized.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: u64, element: &T) {
+        let raw_element = Self::serialize_element(element);
+        self.insert_raw(index, &raw_element);
+    }
 }
 
-impl<T> Vector<T>
+impl<T, C, B: Storage> Vector<T, C, B>
 where
-    T: BorshDeserialize,
+    C: Decoder<T>,
 {
-    fn deserialize_element(raw_element: &[u8]) -> T {
-        match T::try_from_slice(&raw_element) {
+    fn deserialize_element(storage: &B, raw_element: &[u8]) -> T {
+        match storage.scope(|| C::decode(raw_element)) {
             Ok(x) => x,
             Err(_) => env::panic(ERR_ELEMENT_DESERIALIZATION),
         }
@@ -204,7 +413,7 @@ where
 
     /// Returns the element by index or `None` if it is not present.
     pub fn get(&self, index: u64) -> Option<T> {
-        self.get_raw(index).map(|x| Self::deserialize_element(&x))
+        self.get_raw(index).map(|x| Self::deserialize_element(&self.storage, &x))
     }
 
     /// Removes an element from the vector and returns it.
@@ -216,27 +425,84 @@ where
     /// Panics if `index` is out of bounds.
     pub fn swap_remove(&mut self, index: u64) -> T {
         let raw_evicted = self.swap_remove_raw(index);
-        Self::deserialize_element(&raw_evicted)
+        Self::deserialize_element(&self.storage, &raw_evicted)
     }
 
     /// Removes the last element from a vector and returns it, or `None` if it is empty.
     pub fn pop(&mut self) -> Option<T> {
-        self.pop_raw().map(|x| Self::deserialize_element(&x))
+        self.pop_raw().map(|x| Self::deserialize_element(&self.storage, &x))
+    }
+
+    /// Removes an element from the vector and returns it, shifting all elements after it to the
+    /// left. Preserves ordering, but is `O(len - index)`; use `swap_remove` when the order does not
+    /// matter.
+    ///
+    /// Costs `len - index` storage reads, `len - index - 1` storage writes and one storage removal.
+    /// Only the removed element is deserialized.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: u64) -> T {
+        let raw_element = self.remove_raw(index);
+        Self::deserialize_element(&self.storage, &raw_element)
+    }
+
+    /// Retains only the elements for which the predicate returns `true`, preserving their order.
+    ///
+    /// Costs `len` storage reads, a storage write for every retained element that has to be moved
+    /// and a storage removal for every removed element.
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        let storage = self.storage.clone();
+        self.retain_raw(|raw_element| f(&Self::deserialize_element(&storage, raw_element)))
+    }
+
+    /// Removes the elements with indices within the given range from the vector and returns them,
+    /// shifting all elements after the range to the left. Unlike `Vec::drain`, the elements are
+    /// removed eagerly.
+    ///
+    /// Costs `len - start` storage reads, a storage write for every element after the range and a
+    /// storage removal for every removed element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of the range is greater than its end or the end is out of bounds.
+    pub fn drain<R: RangeBounds<u64>>(&mut self, range: R) -> Vec<T> {
+        let raw_elements = self.drain_raw(range);
+        raw_elements.iter().map(|x| Self::deserialize_element(&self.storage, x)).collect()
     }
 
     /// Iterate over deserialized elements.
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item = T> + 'a {
-        self.iter_raw().map(|raw_element| Self::deserialize_element(&raw_element))
+    pub fn iter(&self) -> VectorIter<'_, T, C, B> {
+        VectorIter { vec: self, range: 0..self.len }
+    }
+
+    /// Iterate over deserialized elements with indices within the given range. The range is
+    /// clamped to the length of the vector.
+    pub fn range<R: RangeBounds<u64>>(&self, range: R) -> VectorIter<'_, T, C, B> {
+        VectorIter { vec: self, range: clamp_range(range, self.len) }
+    }
+
+    /// Iterate over at most `limit` deserialized elements starting from `from_index`. Elements
+    /// before `from_index` are not read.
+    pub fn paginate(&self, from_index: u64, limit: u64) -> VectorIter<'_, T, C, B> {
+        self.range(from_index..from_index.saturating_add(limit))
     }
 
     pub fn to_vec(&self) -> Vec<T> {
         self.iter().collect()
     }
+
+    /// Returns at most `max_items` elements starting from the position of the `cursor`, and moves
+    /// the cursor past them. Returns an empty vector once all elements are processed.
+    pub fn next_chunk(&self, cursor: &mut ChunkCursor, max_items: u64) -> Vec<T> {
+        cursor.next_range(self.len, max_items).map(|i| self.get(i).unwrap()).collect()
+    }
 }
 
-impl<T> Vector<T>
+impl<T, C, B: Storage> Vector<T, C, B>
 where
-    T: BorshSerialize + BorshDeserialize,
+    C: Encoder<T> + Decoder<T>,
 {
     /// Inserts a element at `index`, returns an evicted element.
     ///
@@ -245,12 +511,147 @@ where
     /// If `index` is out of bounds.
     pub fn replace(&mut self, index: u64, element: &T) -> T {
         let raw_element = Self::serialize_element(element);
-        Self::deserialize_element(&self.replace_raw(index, &raw_element))
+        let raw_evicted = self.replace_raw(index, &raw_element);
+        Self::deserialize_element(&self.storage, &raw_evicted)
+    }
+}
+
+/// Converts a range of indices into a `Range`, where an unbounded end is `len`.
+pub(crate) fn bounds_to_range<R: RangeBounds<u64>>(range: R, len: u64) -> Range<u64> {
+    let start = match range.start_bound() {
+        Bound::Included(&x) => x,
+        Bound::Excluded(&x) => x.saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&x) => x.saturating_add(1),
+        Bound::Excluded(&x) => x,
+        Bound::Unbounded => len,
+    };
+    start..end
+}
+
+/// Converts a range of indices into a `Range` that is within `0..len`.
+pub(crate) fn clamp_range<R: RangeBounds<u64>>(range: R, len: u64) -> Range<u64> {
+    let Range { start, end } = bounds_to_range(range, len);
+    let end = end.min(len);
+    start.min(end)..end
+}
+
+/// An iterator over the elements of a `Vector`. Elements are read from the trie only when they are
+/// yielded, so `nth` and `skip` do not read the elements they skip over.
+pub struct VectorIter<'a, T, C = Borsh, B: Storage = EnvStorage> {
+    vec: &'a Vector<T, C, B>,
+    range: Range<u64>,
+}
+
+impl<'a, T, C, B: Storage> VectorIter<'a, T, C, B>
+where
+    C: Decoder<T>,
+{
+    fn read(&self, index: u64) -> T {
+        match self.vec.get(index) {
+            Some(x) => x,
+            None => env::panic(ERR_INCONSISTENT_STATE),
+        }
+    }
+}
+
+impl<'a, T, C, B: Storage> Iterator for VectorIter<'a, T, C, B>
+where
+    C: Decoder<T>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.range.next()?;
+        Some(self.read(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = (self.range.end - self.range.start) as usize;
+        (len, Some(len))
+    }
+
+    fn count(self) -> usize {
+        self.len()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        let index = self.range.start.saturating_add(n as u64);
+        if index >= self.range.end {
+            self.range.start = self.range.end;
+            return None;
+        }
+        self.range.start = index + 1;
+        Some(self.read(index))
+    }
+}
+
+impl<'a, T, C, B: Storage> DoubleEndedIterator for VectorIter<'a, T, C, B>
+where
+    C: Decoder<T>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let index = self.range.next_back()?;
+        Some(self.read(index))
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<Self::Item> {
+        if self.range.end - self.range.start <= n as u64 {
+            self.range.end = self.range.start;
+            return None;
+        }
+        self.range.end -= n as u64 + 1;
+        Some(self.read(self.range.end))
+    }
+}
+
+impl<'a, T, C, B: Storage> ExactSizeIterator for VectorIter<'a, T, C, B> where C: Decoder<T> {}
+
+impl<'a, T, C, B: Storage> FusedIterator for VectorIter<'a, T, C, B> where C: Decoder<T> {}
+
+impl<'a, T, C, B: Storage> IntoIterator for &'a Vector<T, C, B>
+where
+    C: Decoder<T>,
+{
+    type Item = T;
+    type IntoIter = VectorIter<'a, T, C, B>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, C, B: Storage> NestedCollection<B> for Vector<T, C, B> {
+    fn new_nested<S: IntoStorageKey>(prefix: S, storage: B) -> Self {
+        Self::with_codec_and_storage(prefix, storage)
+    }
+
+    fn clear_nested(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, C> Cacheable for Vector<T, C> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        vec![&self.prefix]
+    }
+}
+
+#[cfg(not(feature = "expensive-debug"))]
+impl<T, C, B: Storage> std::fmt::Debug for Vector<T, C, B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Vector")
+            .field("len", &self.len)
+            .field("prefix", &self.prefix)
+            .field("el", &self.el)
+            .finish()
     }
 }
 
 #[cfg(feature = "expensive-debug")]
-impl<T: std::fmt::Debug + BorshDeserialize> std::fmt::Debug for Vector<T> {
+impl<T: std::fmt::Debug, C: Decoder<T>, B: Storage> std::fmt::Debug for Vector<T, C, B> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.to_vec().fmt(f)
     }
@@ -262,7 +663,8 @@ mod tests {
     use borsh::BorshDeserialize;
     use rand::{Rng, SeedableRng};
 
-    use crate::collections::Vector;
+    use crate::collections::{ChunkCursor, Vector};
+    use crate::env;
     use crate::test_utils::test_env;
 
     #[test]
@@ -404,8 +806,12 @@ mod tests {
         #[derive(Debug, BorshDeserialize)]
         struct WithoutBorshSerialize(u64);
 
-        let deserialize_only_vec =
-            Vector::<WithoutBorshSerialize> { len: vec.len(), prefix, el: Default::default() };
+        let deserialize_only_vec = Vector::<WithoutBorshSerialize> {
+            len: vec.len(),
+            prefix,
+            el: Default::default(),
+            storage: Default::default(),
+        };
         let baseline: Vec<_> = baseline.into_iter().map(|x| WithoutBorshSerialize(x)).collect();
         if cfg!(feature = "expensive-debug") {
             assert_eq!(format!("{:#?}", deserialize_only_vec), format!("{:#?}", baseline));
@@ -419,4 +825,139 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    pub fn test_clear_in_chunks() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(5);
+        let mut vec = Vector::new(b"v".to_vec());
+        let mut baseline = vec![];
+        for _ in 0..100 {
+            let value = rng.gen::<u64>();
+            vec.push(&value);
+            baseline.push(value);
+        }
+        assert!(!vec.clear_in_chunks(30));
+        baseline.truncate(70);
+        assert_eq!(vec.to_vec(), baseline);
+        assert!(!vec.clear_in_chunks(69));
+        assert!(vec.clear_in_chunks(30));
+        assert!(vec.is_empty());
+        assert!(vec.clear_in_chunks(30));
+    }
+
+    #[test]
+    pub fn test_next_chunk() {
+        test_env::setup();
+        let mut vec = Vector::new(b"v".to_vec());
+        vec.extend(0..10u64);
+        let mut cursor = ChunkCursor::new();
+        assert_eq!(vec.next_chunk(&mut cursor, 4), vec![0, 1, 2, 3]);
+        assert_eq!(vec.next_chunk(&mut cursor, 4), vec![4, 5, 6, 7]);
+        assert!(!cursor.is_finished(vec.len()));
+        assert_eq!(vec.next_chunk(&mut cursor, 4), vec![8, 9]);
+        assert!(cursor.is_finished(vec.len()));
+        assert_eq!(vec.next_chunk(&mut cursor, 4), Vec::<u64>::new());
+        assert_eq!(cursor.position(), 10);
+    }
+
+    #[test]
+    pub fn test_iter_range_paginate() {
+        test_env::setup();
+        let mut vec = Vector::new(b"v".to_vec());
+        let baseline: Vec<u64> = (0..20).collect();
+        vec.extend(baseline.iter().cloned());
+
+        assert_eq!(vec.iter().len(), 20);
+        assert_eq!(
+            vec.iter().rev().collect::<Vec<_>>(),
+            baseline.iter().rev().cloned().collect::<Vec<_>>()
+        );
+        assert_eq!(vec.range(5..8).collect::<Vec<_>>(), vec![5, 6, 7]);
+        assert_eq!(vec.range(18..).collect::<Vec<_>>(), vec![18, 19]);
+        assert_eq!(vec.range(..=1).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(vec.range(15..100).len(), 5);
+        assert_eq!(vec.range(30..40).len(), 0);
+        assert_eq!(vec.paginate(10, 3).collect::<Vec<_>>(), vec![10, 11, 12]);
+        assert_eq!(vec.paginate(19, 3).collect::<Vec<_>>(), vec![19]);
+        assert_eq!(vec.paginate(3, u64::MAX).len(), 17);
+
+        let mut iter = vec.iter();
+        assert_eq!(iter.nth(5), Some(5));
+        assert_eq!(iter.next_back(), Some(19));
+        assert_eq!(iter.nth_back(2), Some(16));
+        assert_eq!(iter.len(), 10);
+        assert_eq!(iter.nth(9), Some(15));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        let mut iter = vec.iter();
+        assert_eq!(iter.nth(25), None);
+        assert_eq!(iter.next(), None);
+        assert_eq!((&vec).into_iter().skip(18).collect::<Vec<_>>(), vec![18, 19]);
+    }
+
+    #[test]
+    pub fn test_insert_remove() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(7);
+        let mut vec = Vector::new(b"v".to_vec());
+        let mut baseline = vec![];
+        for _ in 0..500 {
+            if baseline.is_empty() || rng.gen::<bool>() {
+                let index = rng.gen::<u64>() % (vec.len() + 1);
+                let value = rng.gen::<u64>();
+                vec.insert(index, &value);
+                baseline.insert(index as usize, value);
+            } else {
+                let index = rng.gen::<u64>() % vec.len();
+                assert_eq!(vec.remove(index), baseline.remove(index as usize));
+            }
+        }
+        assert_eq!(vec.to_vec(), baseline);
+    }
+
+    #[test]
+    pub fn test_swap_truncate() {
+        test_env::setup();
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(8);
+        let mut vec = Vector::new(b"v".to_vec());
+        let mut baseline: Vec<u64> = (0..100).collect();
+        vec.extend(baseline.iter().cloned());
+        for _ in 0..100 {
+            let a = rng.gen::<u64>() % vec.len();
+            let b = rng.gen::<u64>() % vec.len();
+            vec.swap(a, b);
+            baseline.swap(a as usize, b as usize);
+        }
+        assert_eq!(vec.to_vec(), baseline);
+        vec.truncate(200);
+        assert_eq!(vec.len(), 100);
+        vec.truncate(40);
+        baseline.truncate(40);
+        assert_eq!(vec.to_vec(), baseline);
+        assert_eq!(vec.get_raw(40), None);
+        assert!(!env::storage_has_key(&[&b"v"[..], &40u64.to_le_bytes()[..]].concat()));
+    }
+
+    #[test]
+    pub fn test_retain_drain() {
+        test_env::setup();
+        let mut vec = Vector::new(b"v".to_vec());
+        let mut baseline: Vec<u64> = (0..100).collect();
+        vec.extend(baseline.iter().cloned());
+        vec.retain(|x| x % 3 != 0);
+        baseline.retain(|x| x % 3 != 0);
+        assert_eq!(vec.to_vec(), baseline);
+
+        assert_eq!(vec.drain(10..20), baseline.drain(10..20).collect::<Vec<_>>());
+        assert_eq!(vec.drain(..=2), baseline.drain(..=2).collect::<Vec<_>>());
+        assert_eq!(vec.drain(50..), baseline.drain(50..).collect::<Vec<_>>());
+        assert_eq!(vec.drain(5..5), Vec::<u64>::new());
+        assert_eq!(vec.to_vec(), baseline);
+
+        vec.retain_raw(|_| false);
+        assert!(vec.is_empty());
+        assert!(!env::storage_has_key(&[&b"v"[..], &0u64.to_le_bytes()[..]].concat()));
+    }
 }
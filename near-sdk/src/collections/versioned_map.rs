@@ -0,0 +1,384 @@
+//! A map that keeps the history of the values of every key by block height, so that it can answer
+//! what the value of a key was at a past block, e.g. the balance of an account at a snapshot.
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use crate::collections::cache::Cacheable;
+use crate::collections::entry::RawMap;
+use crate::collections::storage::{EnvStorage, Storage};
+use crate::collections::{Borsh, Deque, Identity, LookupMap};
+use crate::{env, BlockHeight, IntoStorageKey};
+
+const ERR_KEY_SERIALIZATION: &[u8] = b"Cannot serialize key with Borsh";
+const ERR_VALUE_SERIALIZATION: &[u8] = b"Cannot serialize value with Borsh";
+const ERR_INCONSISTENT_STATE: &[u8] = b"The collection is an inconsistent state. Did previous smart contract execution terminate unexpectedly?";
+
+/// The value of a key starting from a block height. `None` if the key was removed.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Checkpoint<V> {
+    height: BlockHeight,
+    value: Option<V>,
+}
+
+/// How much of the history of every key a `VersionedMap` keeps. Older checkpoints of a key are
+/// removed when the key is written.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Retention {
+    /// Keep every checkpoint.
+    All,
+    /// Keep at most this many of the latest checkpoints of every key, and at least one.
+    Checkpoints(u64),
+    /// Keep the checkpoints needed to answer queries for the given number of latest blocks.
+    Blocks(u64),
+}
+
+/// A map that records a checkpoint of a key at `env::block_index()` every time the key is written,
+/// and answers `get_at(key, height)` with a binary search over the checkpoints of the key. Writes in
+/// the same block replace the checkpoint of that block.
+///
+/// Uses the following maps: key -> checkpoints of the key, where the checkpoints are a `Deque` of
+/// (height, value) ordered by height. Costs `O(log(N))` storage reads to query the value at a
+/// height, where `N` is the number of retained checkpoints of the key. The content is stored in
+/// the storage `B`, which is the storage of the contract by default. See `with_storage`.
+///
+/// ```
+/// # use near_sdk::collections::{Retention, VersionedMap};
+/// # near_sdk::test_utils::test_env::setup();
+/// let mut balances: VersionedMap<String, u128> = VersionedMap::new(b"b", Retention::Blocks(1000));
+/// balances.insert(&"alice.near".to_string(), &100);
+/// let snapshot = near_sdk::env::block_index();
+/// assert_eq!(balances.get_at(&"alice.near".to_string(), snapshot), Some(100));
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct VersionedMap<K, V, B: Storage = EnvStorage> {
+    histories: LookupMap<K, Deque<Checkpoint<V>, Borsh, B>, Identity, Borsh, B>,
+    retention: Retention,
+}
+
+impl<K, V> VersionedMap<K, V>
+where
+    K: BorshSerialize,
+    V: BorshSerialize + BorshDeserialize,
+{
+    /// Create new map with zero entries. Use `prefix` as a unique identifier on the trie.
+    pub fn new<S>(prefix: S, retention: Retention) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self::with_storage(prefix, retention, EnvStorage)
+    }
+}
+
+impl<K, V, B: Storage> VersionedMap<K, V, B>
+where
+    K: BorshSerialize,
+    V: BorshSerialize + BorshDeserialize,
+{
+    /// Create new map with zero entries that stores its content in `storage`. Use `prefix` as a
+    /// unique identifier in the storage.
+    pub fn with_storage<S>(prefix: S, retention: Retention, storage: B) -> Self
+    where
+        S: IntoStorageKey,
+    {
+        Self { histories: LookupMap::with_storage(prefix, storage), retention }
+    }
+
+    /// Returns the retention policy of the map.
+    pub fn retention(&self) -> Retention {
+        self.retention
+    }
+
+    /// Changes the retention policy of the map. The history of a key is pruned according to the
+    /// new policy the next time the key is written, or when `prune` is called for it.
+    pub fn set_retention(&mut self, retention: Retention) {
+        self.retention = retention;
+    }
+
+    fn serialize_key(key: &K) -> Vec<u8> {
+        match key.try_to_vec() {
+            Ok(x) => x,
+            Err(_) => env::panic(ERR_KEY_SERIALIZATION),
+        }
+    }
+
+    fn serialize_checkpoint(height: BlockHeight, value: Option<&V>) -> Vec<u8> {
+        let mut raw_checkpoint = height.to_le_bytes().to_vec();
+        match value {
+            Some(value) => {
+                raw_checkpoint.push(1);
+                match value.serialize(&mut raw_checkpoint) {
+                    Ok(()) => {}
+                    Err(_) => env::panic(ERR_VALUE_SERIALIZATION),
+                }
+            }
+            None => raw_checkpoint.push(0),
+        }
+        raw_checkpoint
+    }
+
+    /// Returns the current value of the key.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.histories.get(key)?.back()?.value
+    }
+
+    /// Returns `true` if the key currently has a value.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the value of the key at the end of the block at `height`. Returns `None` if the key
+    /// had no value then, or if the checkpoints of the key up to `height` are no longer retained.
+    pub fn get_at(&self, key: &K, height: BlockHeight) -> Option<V> {
+        let history = self.histories.get(key)?;
+        // Finds the number of checkpoints at or before `height`.
+        let (mut lo, mut hi) = (0, history.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if Self::checkpoint(&history, mid).height <= height {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if lo == 0 {
+            return None;
+        }
+        Self::checkpoint(&history, lo - 1).value
+    }
+
+    /// Returns the height of the oldest retained checkpoint of the key. Queries for earlier heights
+    /// return `None`.
+    pub fn earliest_height(&self, key: &K) -> Option<BlockHeight> {
+        Some(self.histories.get(key)?.front()?.height)
+    }
+
+    /// Returns the retained checkpoints of the key ordered by height, as pairs of the height and the
+    /// value starting from it, which is `None` if the key was removed.
+    pub fn history(&self, key: &K) -> Vec<(BlockHeight, Option<V>)> {
+        match self.histories.get(key) {
+            Some(history) => history.iter().map(|c| (c.height, c.value)).collect(),
+            None => vec![],
+        }
+    }
+
+    fn checkpoint(history: &Deque<Checkpoint<V>, Borsh, B>, index: u64) -> Checkpoint<V> {
+        match history.get(index) {
+            Some(x) => x,
+            None => env::panic(ERR_INCONSISTENT_STATE),
+        }
+    }
+
+    /// Sets the current value of the key, recording a checkpoint at the current block height.
+    /// Returns the previous value.
+    pub fn insert(&mut self, key: &K, value: &V) -> Option<V> {
+        self.write(key, Some(value))
+    }
+
+    /// Removes the current value of the key, recording the removal at the current block height.
+    /// The earlier values are still returned by `get_at`. Returns the previous value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.write(key, None)
+    }
+
+    fn write(&mut self, key: &K, value: Option<&V>) -> Option<V> {
+        let height = env::block_index();
+        let key_raw = Self::serialize_key(key);
+        let mut history = match self.histories.get(key) {
+            Some(history) => history,
+            None if value.is_none() => return None,
+            None => Deque::with_storage(
+                self.histories.nested_prefix(&key_raw),
+                self.histories.storage().clone(),
+            ),
+        };
+        let last = history.back();
+        let raw_checkpoint = Self::serialize_checkpoint(height, value);
+        let prev = match last {
+            Some(last) if last.height == height => {
+                history.replace_raw(history.len() - 1, &raw_checkpoint);
+                last.value
+            }
+            Some(last) if value.is_none() && last.value.is_none() => return None,
+            last => {
+                history.push_back_raw(&raw_checkpoint);
+                last.and_then(|c| c.value)
+            }
+        };
+        self.save(key, history, height);
+        prev
+    }
+
+    /// Removes the checkpoints of the key that are not retained by the retention policy. Returns
+    /// the number of removed checkpoints.
+    pub fn prune(&mut self, key: &K) -> u64 {
+        match self.histories.get(key) {
+            Some(history) => {
+                let len = history.len();
+                let left = self.save(key, history, env::block_index());
+                len - left
+            }
+            None => 0,
+        }
+    }
+
+    /// Applies the retention policy to the history of the key and stores it. Returns the number of
+    /// retained checkpoints.
+    fn save(
+        &mut self,
+        key: &K,
+        mut history: Deque<Checkpoint<V>, Borsh, B>,
+        height: BlockHeight,
+    ) -> u64 {
+        match self.retention {
+            Retention::All => {}
+            Retention::Checkpoints(count) => {
+                while history.len() > count.max(1) {
+                    history.pop_front_raw();
+                }
+            }
+            Retention::Blocks(count) => {
+                // The oldest checkpoint is needed as long as the next one is after the cutoff.
+                let cutoff = height.saturating_sub(count);
+                while history.len() > 1 && Self::checkpoint(&history, 1).height <= cutoff {
+                    history.pop_front_raw();
+                }
+            }
+        }
+        // A history that only records a removal is the same as no history.
+        if history.len() == 1 && Self::checkpoint(&history, 0).value.is_none() {
+            history.clear();
+        }
+        if history.is_empty() {
+            self.histories.remove(key);
+        } else {
+            self.histories.insert(key, &history);
+        }
+        history.len()
+    }
+
+    /// Removes the key together with its history. Returns `true` if the key had any retained
+    /// checkpoints.
+    pub fn remove_history(&mut self, key: &K) -> bool {
+        match self.histories.remove(key) {
+            Some(mut history) => {
+                history.clear();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<K, V> Cacheable for VersionedMap<K, V> {
+    fn storage_prefixes(&self) -> Vec<&[u8]> {
+        self.histories.storage_prefixes()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use crate::collections::{Retention, VersionedMap};
+    use crate::test_utils::{test_env, VMContextBuilder};
+    use rand::{Rng, SeedableRng};
+    use std::collections::{BTreeMap, HashMap};
+
+    fn set_block_index(block_index: u64) {
+        test_env::setup_with_context(VMContextBuilder::new().block_index(block_index).build());
+    }
+
+    /// Writes random values at increasing heights, and returns the value of every key after every
+    /// block.
+    fn random_writes(
+        map: &mut VersionedMap<u64, u64>,
+        seed: u64,
+    ) -> HashMap<u64, BTreeMap<u64, Option<u64>>> {
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(seed);
+        let mut current = HashMap::new();
+        let mut baseline: HashMap<u64, BTreeMap<u64, Option<u64>>> = HashMap::new();
+        for height in 1..200 {
+            set_block_index(height);
+            for _ in 0..rng.gen::<u64>() % 3 {
+                let key = rng.gen::<u64>() % 10;
+                if rng.gen::<u64>() % 4 == 0 {
+                    assert_eq!(map.remove(&key), current.remove(&key));
+                } else {
+                    let value = rng.gen::<u64>();
+                    assert_eq!(map.insert(&key, &value), current.insert(key, value));
+                }
+            }
+            for key in 0..10 {
+                baseline.entry(key).or_default().insert(height, current.get(&key).cloned());
+            }
+        }
+        baseline
+    }
+
+    #[test]
+    pub fn test_get_at() {
+        test_env::setup();
+        let mut map = VersionedMap::new(b"v", Retention::All);
+        let baseline = random_writes(&mut map, 0);
+        for (key, values) in &baseline {
+            assert_eq!(map.get_at(key, 0), None);
+            for (height, value) in values {
+                assert_eq!(&map.get_at(key, *height), value);
+            }
+            assert_eq!(&map.get(key), values.values().last().unwrap());
+        }
+    }
+
+    #[test]
+    pub fn test_same_block_writes() {
+        test_env::setup();
+        set_block_index(5);
+        let mut map = VersionedMap::new(b"v", Retention::All);
+        assert_eq!(map.insert(&1u64, &10u64), None);
+        assert_eq!(map.insert(&1, &11), Some(10));
+        set_block_index(7);
+        assert_eq!(map.remove(&1), Some(11));
+        assert_eq!(map.remove(&1), None);
+        set_block_index(9);
+        map.insert(&1, &12);
+        assert_eq!(map.history(&1), vec![(5, Some(11)), (7, None), (9, Some(12))]);
+        assert_eq!(map.get_at(&1, 4), None);
+        assert_eq!(map.get_at(&1, 6), Some(11));
+        assert_eq!(map.get_at(&1, 8), None);
+        assert_eq!(map.get_at(&1, 100), Some(12));
+
+        // Inserting and removing in the same block leaves no history.
+        map.insert(&2, &20);
+        map.remove(&2);
+        assert_eq!(map.history(&2), vec![]);
+        assert!(map.remove_history(&1));
+        assert_eq!(map.get_at(&1, 6), None);
+    }
+
+    #[test]
+    pub fn test_retention() {
+        test_env::setup();
+        let mut map = VersionedMap::new(b"k", Retention::Checkpoints(3));
+        random_writes(&mut map, 1);
+        for key in 0..10 {
+            assert!(map.history(&key).len() <= 3);
+        }
+
+        set_block_index(0);
+        let mut map = VersionedMap::new(b"b", Retention::Blocks(50));
+        let baseline = random_writes(&mut map, 1);
+        for (key, values) in &baseline {
+            for (height, value) in values.range(199 - 50..) {
+                assert_eq!(&map.get_at(key, *height), value);
+            }
+        }
+
+        // Checkpoints are removed when the retention policy is tightened and the key is pruned. A
+        // key whose last checkpoint is a removal has no history left.
+        map.set_retention(Retention::Checkpoints(1));
+        for key in 0..10 {
+            let len = map.history(&key).len() as u64;
+            let left = if map.contains_key(&key) { 1 } else { 0 };
+            assert_eq!(map.prune(&key), len - left);
+            assert_eq!(map.history(&key).len() as u64, left);
+        }
+    }
+}
@@ -83,7 +83,10 @@ macro_rules! method_into_register {
 pub fn set_blockchain_interface(blockchain_interface: Box<dyn BlockchainInterface>) {
     BLOCKCHAIN_INTERFACE.with(|b| {
         *b.borrow_mut() = Some(blockchain_interface);
-    })
+    });
+    // Entries cached by the collections were read from the storage of the previous interface.
+    #[cfg(not(target_arch = "wasm32"))]
+    crate::collections::cache::reset();
 }
 
 /// Removes and returns the current low-level blockchain interface accessible through `env::*`.
@@ -2,8 +2,8 @@
 extern crate quickcheck;
 
 pub use near_sdk_macros::{
-    callback, callback_vec, ext_contract, init, metadata, near_bindgen, result_serializer,
-    serializer, BorshStorageKey, PanicOnDefault,
+    callback, callback_result, callback_vec, ext_contract, init, metadata, near_bindgen,
+    result_serializer, serializer, BorshStorageKey, ContractError, PanicOnDefault,
 };
 
 pub mod collections;
@@ -11,7 +11,9 @@ mod environment;
 pub use environment::env;
 
 mod promise;
-pub use promise::{Promise, PromiseOrValue};
+pub use promise::{
+    And, JointPromise, Promise, PromiseError, PromiseOrValue, Then, TypedCallback, TypedPromise,
+};
 
 mod metadata;
 pub use metadata::{Metadata, MethodMetadata};
@@ -3,6 +3,7 @@ use near_vm_logic::types::{AccountId, Balance, Gas, PromiseIndex, PublicKey};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::{Error, Write};
+use std::marker::PhantomData;
 use std::rc::Rc;
 
 pub enum PromiseAction {
@@ -324,11 +325,11 @@ impl Promise {
     /// let p3 = p1.and(p2);
     /// // p3.create_account();
     /// ```
-    pub fn and(self, other: Promise) -> Promise {
+    pub fn and<P: Into<Promise>>(self, other: P) -> Promise {
         Promise {
             subtype: PromiseSubtype::Joint(Rc::new(PromiseJoint {
                 promise_a: self,
-                promise_b: other,
+                promise_b: other.into(),
                 promise_index: RefCell::new(None),
             })),
             should_return: RefCell::new(false),
@@ -349,7 +350,8 @@ impl Promise {
     /// let p4 = Promise::new("eva_near".to_string()).create_account();
     /// p1.then(p2).and(p3).then(p4);
     /// ```
-    pub fn then(self, mut other: Promise) -> Promise {
+    pub fn then<P: Into<Promise>>(self, other: P) -> Promise {
+        let mut other = other.into();
         match &mut other.subtype {
             PromiseSubtype::Single(x) => *x.after.borrow_mut() = Some(self),
             PromiseSubtype::Joint(_) => panic!("Cannot callback joint promise."),
@@ -417,6 +419,288 @@ impl serde::Serialize for Promise {
     }
 }
 
+/// A promise that resolves to a value of type `T`, e.g. to the return value of the method that it
+/// calls. The functions in the `typed` module generated by `ext_contract` return typed promises, so
+/// the type of the result is carried through `and` and `then`, and is checked against the
+/// `#[callback]` arguments of the callback that is scheduled after the promise.
+///
+/// ```
+/// # use near_sdk::{ext_contract, near_bindgen, env, TypedPromise};
+/// # use borsh::{BorshDeserialize, BorshSerialize};
+/// #[ext_contract]
+/// pub trait Counter {
+///     fn get(&self) -> u64;
+/// }
+///
+/// #[ext_contract(ext_self)]
+/// pub trait Callbacks {
+///     fn on_get(&self, #[callback] value: u64) -> String;
+/// }
+///
+/// #[near_bindgen]
+/// #[derive(Default, BorshDeserialize, BorshSerialize)]
+/// struct ContractA {}
+///
+/// #[near_bindgen]
+/// impl ContractA {
+///     pub fn a(&self) -> TypedPromise<String> {
+///         // `on_get` takes the `u64` that `get` resolves to, so it can be scheduled after it.
+///         counter::typed::get(&"bob_near".to_string(), 0, 1_000)
+///             .then(ext_self::typed::on_get(&env::current_account_id(), 0, 1_000))
+///     }
+/// }
+/// ```
+pub struct TypedPromise<T> {
+    promise: Promise,
+    result: PhantomData<T>,
+}
+
+impl<T> TypedPromise<T> {
+    /// Declare that the promise resolves to a value of type `T`.
+    pub fn new(promise: Promise) -> Self {
+        Self { promise, result: PhantomData }
+    }
+
+    /// Merge this promise with another promise, see `Promise::and`. The joint promise resolves to
+    /// the results of both promises if `other` is a `TypedPromise`, and is untyped otherwise.
+    pub fn and<P: And<Self>>(self, other: P) -> P::Output {
+        P::joint(self.promise.and(other))
+    }
+
+    /// Schedule `next` right after this promise, see `Promise::then`.
+    pub fn then<N: Then<T>>(self, next: N) -> N::Output {
+        next.after(self.promise)
+    }
+
+    /// Mark the promise as the return value, see `Promise::as_return`.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn as_return(self) -> Self {
+        Self::new(self.promise.as_return())
+    }
+}
+
+impl<T> From<TypedPromise<T>> for Promise {
+    fn from(promise: TypedPromise<T>) -> Self {
+        promise.promise
+    }
+}
+
+impl<T> BorshSchema for TypedPromise<T>
+where
+    T: BorshSchema,
+{
+    fn add_definitions_recursively(
+        definitions: &mut HashMap<borsh::schema::Declaration, borsh::schema::Definition>,
+    ) {
+        T::add_definitions_recursively(definitions);
+    }
+
+    fn declaration() -> borsh::schema::Declaration {
+        T::declaration()
+    }
+}
+
+impl<T> serde::Serialize for TypedPromise<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.promise.serialize(serializer)
+    }
+}
+
+impl<T> borsh::BorshSerialize for TypedPromise<T> {
+    fn serialize<W: Write>(&self, _writer: &mut W) -> Result<(), Error> {
+        *self.promise.should_return.borrow_mut() = true;
+        Ok(())
+    }
+}
+
+/// Promises merged with `and` that resolve to a tuple `T` of their results.
+pub struct JointPromise<T> {
+    promise: Promise,
+    result: PhantomData<T>,
+}
+
+impl<T> JointPromise<T> {
+    fn new(promise: Promise) -> Self {
+        Self { promise, result: PhantomData }
+    }
+
+    /// Merge another promise into the joint promise. The joint promise resolves to the tuple of
+    /// the results if `other` is a `TypedPromise`, and is untyped otherwise.
+    pub fn and<P: And<Self>>(self, other: P) -> P::Output {
+        P::joint(self.promise.and(other))
+    }
+
+    /// Schedule `next` right after all merged promises, see `Promise::then`.
+    pub fn then<N: Then<T>>(self, next: N) -> N::Output {
+        next.after(self.promise)
+    }
+
+    /// Mark the promise as the return value, see `Promise::as_return`.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn as_return(self) -> Self {
+        Self::new(self.promise.as_return())
+    }
+}
+
+impl<T> From<JointPromise<T>> for Promise {
+    fn from(promise: JointPromise<T>) -> Self {
+        promise.promise
+    }
+}
+
+/// A call of a method with `#[callback]` arguments of type `I` that resolves to a value of type
+/// `T`. Functions generated by `ext_contract` return it for such methods, so that it can only be
+/// scheduled after a promise that resolves to `I`: the type of the single `#[callback]` argument,
+/// or the tuple of the types of all `#[callback]` arguments.
+pub struct TypedCallback<I, T> {
+    promise: Promise,
+    result: PhantomData<fn(I) -> T>,
+}
+
+impl<I, T> TypedCallback<I, T> {
+    /// Declare that the promise calls a method with `#[callback]` arguments of type `I` that
+    /// returns `T`.
+    pub fn new(promise: Promise) -> Self {
+        Self { promise, result: PhantomData }
+    }
+
+    /// Merge this promise with another promise, see `TypedPromise::and`.
+    pub fn and<P: And<TypedPromise<T>>>(self, other: P) -> P::Output {
+        P::joint(self.promise.and(other))
+    }
+
+    /// Schedule `next` right after this promise, see `Promise::then`.
+    pub fn then<N: Then<T>>(self, next: N) -> N::Output {
+        next.after(self.promise)
+    }
+
+    /// Mark the promise as the return value, see `Promise::as_return`.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn as_return(self) -> Self {
+        Self::new(self.promise.as_return())
+    }
+}
+
+impl<I, T> From<TypedCallback<I, T>> for Promise {
+    fn from(callback: TypedCallback<I, T>) -> Self {
+        callback.promise
+    }
+}
+
+/// A promise that can be scheduled after a promise that resolves to `T`. Untyped and typed
+/// promises can follow any promise, while a `TypedCallback` can only follow a promise that
+/// resolves to the type of its `#[callback]` arguments.
+pub trait Then<T> {
+    /// The promise that is returned by `then`.
+    type Output;
+
+    /// Schedule this promise after `promise`.
+    fn after(self, promise: Promise) -> Self::Output;
+}
+
+impl<T> Then<T> for Promise {
+    type Output = Promise;
+
+    fn after(self, promise: Promise) -> Self::Output {
+        promise.then(self)
+    }
+}
+
+impl<T, U> Then<T> for TypedPromise<U> {
+    type Output = TypedPromise<U>;
+
+    fn after(self, promise: Promise) -> Self::Output {
+        TypedPromise::new(promise.then(self.promise))
+    }
+}
+
+impl<T, U> Then<T> for TypedCallback<T, U> {
+    type Output = TypedPromise<U>;
+
+    fn after(self, promise: Promise) -> Self::Output {
+        TypedPromise::new(promise.then(self.promise))
+    }
+}
+
+/// A promise that can be merged with `and` into the promise `P`. Any promise can be merged, but
+/// only a `TypedPromise` merged into a typed promise gives a `JointPromise` of the results.
+pub trait And<P>: Into<Promise> {
+    /// The promise that is returned by `and`.
+    type Output;
+
+    /// Wrap the promise that is merged from `P` and this promise.
+    fn joint(promise: Promise) -> Self::Output;
+}
+
+impl<P> And<P> for Promise {
+    type Output = Promise;
+
+    fn joint(promise: Promise) -> Self::Output {
+        promise
+    }
+}
+
+impl<P, T> And<P> for JointPromise<T> {
+    type Output = Promise;
+
+    fn joint(promise: Promise) -> Self::Output {
+        promise
+    }
+}
+
+impl<P, I, T> And<P> for TypedCallback<I, T> {
+    type Output = Promise;
+
+    fn joint(promise: Promise) -> Self::Output {
+        promise
+    }
+}
+
+impl<T, U> And<TypedPromise<T>> for TypedPromise<U> {
+    type Output = JointPromise<(T, U)>;
+
+    fn joint(promise: Promise) -> Self::Output {
+        JointPromise::new(promise)
+    }
+}
+
+macro_rules! impl_joint_promise_and {
+    ($($ty:ident),*) => {
+        impl<$($ty,)* U> And<JointPromise<($($ty,)*)>> for TypedPromise<U> {
+            type Output = JointPromise<($($ty,)* U,)>;
+
+            fn joint(promise: Promise) -> Self::Output {
+                JointPromise::new(promise)
+            }
+        }
+    };
+}
+
+impl_joint_promise_and!(A, B);
+impl_joint_promise_and!(A, B, C);
+impl_joint_promise_and!(A, B, C, D);
+
+/// Why a promise has no result, given to `#[callback_result]` arguments instead of the value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromiseError {
+    /// The promise failed, e.g. the method that it called panicked.
+    Failed,
+    /// The promise has not finished yet.
+    NotReady,
+}
+
+impl std::fmt::Display for PromiseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromiseError::Failed => f.write_str("Promise failed"),
+            PromiseError::NotReady => f.write_str("Promise is not ready"),
+        }
+    }
+}
+
 pub enum PromiseOrValue<T> {
     Promise(Promise),
     Value(T),
@@ -443,6 +727,72 @@ impl<T> From<Promise> for PromiseOrValue<T> {
     }
 }
 
+impl<T> From<TypedPromise<T>> for PromiseOrValue<T> {
+    fn from(promise: TypedPromise<T>) -> Self {
+        PromiseOrValue::Promise(promise.promise.as_return())
+    }
+}
+
+impl<T> PromiseOrValue<T> {
+    /// Returns `true` if this is a value.
+    pub fn is_value(&self) -> bool {
+        matches!(self, PromiseOrValue::Value(_))
+    }
+
+    /// Returns `true` if this is a promise.
+    pub fn is_promise(&self) -> bool {
+        matches!(self, PromiseOrValue::Promise(_))
+    }
+
+    /// Returns the value, or `None` if this is a promise.
+    pub fn value(self) -> Option<T> {
+        match self {
+            PromiseOrValue::Value(x) => Some(x),
+            PromiseOrValue::Promise(_) => None,
+        }
+    }
+
+    /// Maps the value with `f`, or schedules the callback returned by `callback` after the promise,
+    /// so that it maps the result of the promise instead. The callback takes `T` and resolves to
+    /// `U`, so both give the same type, and it is only created when this is a promise.
+    /// ```
+    /// # use near_sdk::{ext_contract, near_bindgen, env, PromiseOrValue};
+    /// # use borsh::{BorshDeserialize, BorshSerialize};
+    /// #[ext_contract(ext_self)]
+    /// pub trait Callbacks {
+    ///     fn on_balance(&self, #[callback] balance: u64) -> String;
+    /// }
+    ///
+    /// #[near_bindgen]
+    /// #[derive(Default, BorshDeserialize, BorshSerialize)]
+    /// struct Contract {}
+    ///
+    /// #[near_bindgen]
+    /// impl Contract {
+    ///     pub fn format(&self, balance: PromiseOrValue<u64>) -> PromiseOrValue<String> {
+    ///         balance.map(
+    ///             |balance| balance.to_string(),
+    ///             || ext_self::typed::on_balance(&env::current_account_id(), 0, 1_000),
+    ///         )
+    ///     }
+    /// }
+    /// ```
+    pub fn map<U, F, C>(self, f: F, callback: C) -> PromiseOrValue<U>
+    where
+        F: FnOnce(T) -> U,
+        C: FnOnce() -> TypedCallback<T, U>,
+    {
+        match self {
+            PromiseOrValue::Value(x) => PromiseOrValue::Value(f(x)),
+            PromiseOrValue::Promise(promise) => {
+                // The callback is the result now, not the promise it follows.
+                *promise.should_return.borrow_mut() = false;
+                TypedPromise::new(promise).then(callback()).into()
+            }
+        }
+    }
+}
+
 impl<T: serde::Serialize> serde::Serialize for PromiseOrValue<T> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -1,5 +1,5 @@
 use crate::test_utils::VMContextBuilder;
-use crate::{env, MockedBlockchain};
+use crate::{env, MockedBlockchain, VMContext};
 use near_vm_logic::types::AccountId;
 use near_vm_logic::VMConfig;
 
@@ -16,7 +16,16 @@ pub fn carol() -> AccountId {
 }
 
 pub fn setup_with_config(vm_config: VMConfig) {
-    let context = VMContextBuilder::new().build();
+    setup_with_context_and_config(VMContextBuilder::new().build(), vm_config);
+}
+
+/// Sets up the mocked blockchain with the given context, keeping the storage of the previous one.
+/// Useful to move the block timestamp or height forward between the calls of a test.
+pub fn setup_with_context(context: VMContext) {
+    setup_with_context_and_config(context, VMConfig::default());
+}
+
+fn setup_with_context_and_config(context: VMContext, vm_config: VMConfig) {
     let storage = match env::take_blockchain_interface() {
         Some(mut bi) => bi.as_mut_mocked_blockchain().unwrap().take_storage(),
         None => Default::default(),
@@ -1,6 +1,7 @@
 pub(crate) mod storage_key_impl;
 
 use crate::{env, AccountId, PromiseResult};
+use serde::{Deserialize, Serialize};
 
 #[macro_export]
 macro_rules! log {
@@ -62,6 +63,147 @@ impl PendingContractTx {
     }
 }
 
+/// An error returned from a `#[handle_result]` method. Fails the call with the error as the panic
+/// message, so that the state changes of the call are rolled back. Implemented for string errors,
+/// for other errors that implement `Display` through `DisplayError`, and derived together with
+/// `ContractError`.
+pub trait FunctionError {
+    fn panic(&self) -> !;
+}
+
+impl FunctionError for String {
+    fn panic(&self) -> ! {
+        env::panic(self.as_bytes())
+    }
+}
+
+impl FunctionError for &str {
+    fn panic(&self) -> ! {
+        env::panic(self.as_bytes())
+    }
+}
+
+impl FunctionError for Box<dyn std::error::Error> {
+    fn panic(&self) -> ! {
+        env::panic(self.to_string().as_bytes())
+    }
+}
+
+/// Wraps an error that implements `Display`, so that it can be returned from a `#[handle_result]`
+/// method and fails the call with its `Display` message. Converts from the wrapped error, so `?`
+/// works on results with the wrapped error:
+/// ```
+/// use near_sdk::DisplayError;
+///
+/// fn parse(value: &str) -> Result<u32, DisplayError<std::num::ParseIntError>> {
+///     Ok(value.parse::<u32>()?)
+/// }
+/// assert_eq!(parse("x").unwrap_err().to_string(), "invalid digit found in string");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayError<T>(pub T);
+
+impl<T> From<T> for DisplayError<T> {
+    fn from(err: T) -> Self {
+        Self(err)
+    }
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for DisplayError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: std::fmt::Display> FunctionError for DisplayError<T> {
+    fn panic(&self) -> ! {
+        env::panic(self.0.to_string().as_bytes())
+    }
+}
+
+/// An error of a contract with a stable code and a JSON payload, so that clients can match on the
+/// code instead of the text of the error. Usually derived with `#[derive(ContractError)]`, which
+/// also implements `FunctionError` with the error message, so the error fails the call with the
+/// message when it is returned from a `#[handle_result]` method.
+pub trait ContractError: Serialize {
+    /// The stable code of the error, e.g. `NOT_ENOUGH_BALANCE`.
+    fn error_code(&self) -> &'static str;
+
+    /// The error in the standard format `{"error_code":"<code>","error":<error as JSON>}`.
+    fn error_message(&self) -> String {
+        let message = ContractErrorMessage { error_code: self.error_code(), error: self };
+        serde_json::to_string(&message).expect("Failed to serialize the error using JSON.")
+    }
+}
+
+const CONTRACT_ERROR_MARKER: &str = "{\"error_code\":";
+
+#[derive(Serialize, Deserialize)]
+struct ContractErrorMessage<C, E> {
+    error_code: C,
+    error: E,
+}
+
+/// Finds a `ContractError` message in `message`, e.g. in the error of a failed transaction, and
+/// returns the code and the JSON payload of the error.
+pub fn parse_contract_error(message: &str) -> Option<(String, serde_json::Value)> {
+    message.match_indices(CONTRACT_ERROR_MARKER).find_map(|(index, _)| {
+        let mut values = serde_json::Deserializer::from_str(&message[index..])
+            .into_iter::<ContractErrorMessage<String, serde_json::Value>>();
+        match values.next() {
+            Some(Ok(parsed)) => Some((parsed.error_code, parsed.error)),
+            _ => None,
+        }
+    })
+}
+
+/// An error that the code generated by `near_bindgen` fails a call with, e.g. when a deposit is
+/// attached to a method that is not `#[payable]`. It is a `ContractError`, so it is reported in the
+/// same format as the errors of the contract.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SdkError {
+    /// A deposit is attached to a call of a method that is not `#[payable]`.
+    DepositNotAccepted { method: String },
+    /// A `#[private]` method is called by another account.
+    PrivateMethod { method: String },
+    /// A method with arguments is called without input.
+    MissingInput,
+    /// The input can not be deserialized with the serializer of the method, `JSON` or `Borsh`.
+    InvalidInput { serializer: String },
+    /// The promise result `index` of a callback is not successful.
+    CallbackFailed { index: u64 },
+    /// The promise result `index` of a callback can not be deserialized.
+    InvalidCallbackResult { index: u64, serializer: String },
+    /// The return value can not be serialized.
+    ResultSerialization { serializer: String },
+    /// An `#[init]` method is called on a contract with a state.
+    AlreadyInitialized,
+    /// A method is called on a contract without a state that derives `PanicOnDefault`.
+    NotInitialized,
+}
+
+impl ContractError for SdkError {
+    fn error_code(&self) -> &'static str {
+        match self {
+            SdkError::DepositNotAccepted { .. } => "DEPOSIT_NOT_ACCEPTED",
+            SdkError::PrivateMethod { .. } => "PRIVATE_METHOD",
+            SdkError::MissingInput => "MISSING_INPUT",
+            SdkError::InvalidInput { .. } => "INVALID_INPUT",
+            SdkError::CallbackFailed { .. } => "CALLBACK_FAILED",
+            SdkError::InvalidCallbackResult { .. } => "INVALID_CALLBACK_RESULT",
+            SdkError::ResultSerialization { .. } => "RESULT_SERIALIZATION",
+            SdkError::AlreadyInitialized => "ALREADY_INITIALIZED",
+            SdkError::NotInitialized => "NOT_INITIALIZED",
+        }
+    }
+}
+
+impl FunctionError for SdkError {
+    fn panic(&self) -> ! {
+        env::panic(self.error_message().as_bytes())
+    }
+}
+
 /// Boilerplate for setting up allocator used in Wasm binary.
 #[macro_export]
 macro_rules! setup_alloc {
@@ -75,6 +217,9 @@ macro_rules! setup_alloc {
 #[cfg(test)]
 mod tests {
     use crate::test_utils::{get_logs, test_env};
+    use crate::{parse_contract_error, ContractError, DisplayError, FunctionError, SdkError};
+    use serde::Serialize;
+    use serde_json::json;
 
     #[test]
     fn test_log_simple() {
@@ -91,4 +236,57 @@ mod tests {
 
         assert_eq!(get_logs(), vec!["hello user_name (25)".to_string()]);
     }
+
+    #[derive(Serialize)]
+    enum Error {
+        NotEnoughBalance { needed: u64 },
+    }
+
+    impl ContractError for Error {
+        fn error_code(&self) -> &'static str {
+            "NOT_ENOUGH_BALANCE"
+        }
+    }
+
+    #[test]
+    fn test_contract_error_message() {
+        let message = Error::NotEnoughBalance { needed: 10 }.error_message();
+        assert_eq!(
+            message,
+            r#"{"error_code":"NOT_ENOUGH_BALANCE","error":{"NotEnoughBalance":{"needed":10}}}"#
+        );
+        let panic_message = format!("Smart contract panicked: {}", message);
+        assert_eq!(
+            parse_contract_error(&panic_message),
+            Some(("NOT_ENOUGH_BALANCE".to_string(), json!({"NotEnoughBalance": {"needed": 10}})))
+        );
+        assert_eq!(parse_contract_error("Method method is private"), None);
+    }
+
+    #[test]
+    fn test_sdk_error_message() {
+        let message = SdkError::DepositNotAccepted { method: "inc".to_string() }.error_message();
+        assert_eq!(
+            message,
+            r#"{"error_code":"DEPOSIT_NOT_ACCEPTED","error":{"DepositNotAccepted":{"method":"inc"}}}"#
+        );
+        assert_eq!(
+            SdkError::MissingInput.error_message(),
+            r#"{"error_code":"MISSING_INPUT","error":"MissingInput"}"#
+        );
+        let (code, error) = parse_contract_error(&message).unwrap();
+        assert_eq!(code, "DEPOSIT_NOT_ACCEPTED");
+        assert_eq!(
+            serde_json::from_value::<SdkError>(error).unwrap(),
+            SdkError::DepositNotAccepted { method: "inc".to_string() }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid digit found in string")]
+    fn test_display_error_panic() {
+        test_env::setup();
+        let err: DisplayError<_> = "x".parse::<u32>().unwrap_err().into();
+        err.panic();
+    }
 }